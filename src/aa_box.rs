@@ -0,0 +1,158 @@
+use crate::{
+    aabb::Aabb,
+    hittable::{HitRecord, HitSpan, Hittable},
+    interval::Interval,
+    material::Material,
+    ray::Ray,
+    vector3::Vector3,
+};
+
+/// A solid box whose faces are aligned with the X, Y and Z axes.
+/// Each face is UV mapped from 0 to 1 across its two in-plane axes
+#[derive(Clone)]
+pub struct AxisAlignedBox {
+    pub min: Vector3,
+    pub max: Vector3,
+    pub material: Box<dyn Material>,
+}
+
+impl AxisAlignedBox {
+    /// Creates a box with the two points as opposite corners
+    ///
+    /// ## Arguments
+    /// - `a` The first corner
+    /// - `b` The second corner
+    /// - `material` Material of the box
+    pub fn new(a: Vector3, b: Vector3, material: Box<dyn Material>) -> Self {
+        Self {
+            min: Vector3::from(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z)),
+            max: Vector3::from(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z)),
+            material,
+        }
+    }
+
+    /// Finds where a ray enters and leaves the box as `(t_enter, enter_axis, t_exit, exit_axis)` using the slab method
+    pub fn slab_intersect(&self, ray: &Ray) -> Option<(f64, usize, f64, usize)> {
+        let (mut t_enter, mut enter_axis) = (f64::NEG_INFINITY, 0);
+        let (mut t_exit, mut exit_axis) = (f64::INFINITY, 0);
+
+        for a in 0..3 {
+            // A ray parallel to a pair of faces never crosses them, 0 * infinity would make the crossing times NaN
+            if ray.direction[a] == 0.0 {
+                if ray.origin[a] < self.min[a] || ray.origin[a] > self.max[a] {
+                    return None;
+                }
+                continue;
+            }
+
+            let inv_d = 1.0 / ray.direction[a];
+            let mut t0 = (self.min[a] - ray.origin[a]) * inv_d;
+            let mut t1 = (self.max[a] - ray.origin[a]) * inv_d;
+
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+
+            if t0 > t_enter { t_enter = t0; enter_axis = a; }
+            if t1 < t_exit { t_exit = t1; exit_axis = a; }
+        }
+
+        if t_exit < t_enter {
+            return None;
+        }
+
+        return Some((t_enter, enter_axis, t_exit, exit_axis));
+    }
+
+    /// Fills in the hit record for a hit on the face perpendicular to `axis`
+    pub fn fill_record(&self, ray: &Ray, t: f64, axis: usize, rec: &mut HitRecord) {
+        rec.t = t;
+        rec.point = ray.at(t);
+
+        let center = 0.5 * (self.min + self.max);
+        let mut outward_normal = Vector3::new();
+        let sign = if rec.point[axis] > center[axis] { 1.0 } else { -1.0 };
+        match axis {
+            0 => outward_normal.x = sign,
+            1 => outward_normal.y = sign,
+            _ => outward_normal.z = sign,
+        }
+
+        // Map the two remaining axes of the face onto 0 to 1
+        let (a, b) = match axis {
+            0 => (2, 1),
+            1 => (0, 2),
+            _ => (0, 1),
+        };
+        rec.u = (rec.point[a] - self.min[a]) / (self.max[a] - self.min[a]);
+        rec.v = (rec.point[b] - self.min[b]) / (self.max[b] - self.min[b]);
+
+        rec.set_face_normal(ray, outward_normal);
+        rec.material = self.material.clone();
+    }
+}
+
+impl Hittable for AxisAlignedBox {
+    fn hit(&self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        let (t_enter, enter_axis, t_exit, exit_axis) = match self.slab_intersect(ray) {
+            Some(hit) => hit,
+            None => return false,
+        };
+
+        if ray_t.surrounds(t_enter) {
+            self.fill_record(ray, t_enter, enter_axis, rec);
+            return true;
+        }
+
+        if ray_t.surrounds(t_exit) {
+            self.fill_record(ray, t_exit, exit_axis, rec);
+            return true;
+        }
+
+        return false;
+    }
+
+    fn hit_spans(&self, ray: &Ray, spans: &mut Vec<HitSpan>) -> bool {
+        if let Some((t_enter, enter_axis, t_exit, exit_axis)) = self.slab_intersect(ray) {
+            let mut enter = HitRecord::new();
+            let mut exit = HitRecord::new();
+            self.fill_record(ray, t_enter, enter_axis, &mut enter);
+            self.fill_record(ray, t_exit, exit_axis, &mut exit);
+
+            spans.push(HitSpan { enter, exit });
+        }
+
+        return true;
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        return Aabb::from_points(self.min, self.max).pad();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::colour::Colour;
+
+    fn unit_box() -> AxisAlignedBox {
+        return AxisAlignedBox::new(Vector3::from(-1.0, -1.0, -1.0), Vector3::from(1.0, 1.0, 1.0), Box::new(Lambertian::new(Colour::from(0.5, 0.5, 0.5))));
+    }
+
+    #[test]
+    fn ray_along_a_face_plane_hits_without_nan() {
+        // Travelling along x with y exactly on the top face's plane, so the y slab gives 0 * infinity
+        let ray = Ray::from(Vector3::from(-5.0, 1.0, 0.0), Vector3::from(1.0, 0.0, 0.0));
+        let (t_enter, enter_axis, t_exit, exit_axis) = unit_box().slab_intersect(&ray).unwrap();
+
+        assert_eq!((t_enter, enter_axis), (4.0, 0));
+        assert_eq!((t_exit, exit_axis), (6.0, 0));
+    }
+
+    #[test]
+    fn parallel_ray_outside_a_slab_misses() {
+        let ray = Ray::from(Vector3::from(-5.0, 1.5, 0.0), Vector3::from(1.0, 0.0, 0.0));
+        assert!(unit_box().slab_intersect(&ray).is_none());
+    }
+}
@@ -0,0 +1,108 @@
+use crate::{interval::Interval, ray::Ray, vector3::Vector3};
+
+/// Axis aligned bounding box used to quickly reject rays which miss an object
+#[derive(Copy, Clone, Debug)]
+pub struct Aabb {
+    pub x: Interval,
+    pub y: Interval,
+    pub z: Interval,
+}
+
+impl Aabb {
+    /// A bounding box containing nothing
+    pub const EMPTY: Aabb = Aabb { x: Interval::EMPTY, y: Interval::EMPTY, z: Interval::EMPTY };
+
+    /// A bounding box containing all of space, used for unbounded objects such as planes
+    pub const UNIVERSE: Aabb = Aabb { x: Interval::UNIVERSE, y: Interval::UNIVERSE, z: Interval::UNIVERSE };
+
+    /// Creates an empty bounding box
+    pub fn new() -> Self {
+        return Aabb::EMPTY;
+    }
+
+    /// Creates a bounding box from three intervals
+    pub fn from(x: Interval, y: Interval, z: Interval) -> Self {
+        Self { x, y, z }
+    }
+
+    /// Creates a bounding box with the two points as opposite corners
+    ///
+    /// ## Arguments
+    /// - `a` The first corner
+    /// - `b` The second corner
+    pub fn from_points(a: Vector3, b: Vector3) -> Self {
+        Self {
+            x: Interval::from(a.x.min(b.x), a.x.max(b.x)),
+            y: Interval::from(a.y.min(b.y), a.y.max(b.y)),
+            z: Interval::from(a.z.min(b.z), a.z.max(b.z)),
+        }
+    }
+
+    /// Creates the smallest bounding box which encloses both boxes
+    ///
+    /// ## Arguments
+    /// - `a` The first box
+    /// - `b` The second box
+    pub fn from_boxes(a: Aabb, b: Aabb) -> Self {
+        Self {
+            x: Interval::from_intervals(a.x, b.x),
+            y: Interval::from_intervals(a.y, b.y),
+            z: Interval::from_intervals(a.z, b.z),
+        }
+    }
+
+    /// Returns the interval of the given axis, 0 = x, 1 = y and 2 = z
+    pub fn axis(&self, n: usize) -> Interval {
+        match n {
+            1 => self.y,
+            2 => self.z,
+            _ => self.x,
+        }
+    }
+
+    /// Returns a copy of the box where no side is thinner than a small delta, so flat objects such as quads can still be hit
+    pub fn pad(&self) -> Self {
+        let delta = 0.0001;
+        let pad_axis = |i: Interval| if i.size() >= delta { i } else { i.expand(delta) };
+
+        return Aabb::from(pad_axis(self.x), pad_axis(self.y), pad_axis(self.z));
+    }
+
+    /// Returns the index of the longest axis of the box
+    pub fn longest_axis(&self) -> usize {
+        if self.x.size() > self.y.size() {
+            if self.x.size() > self.z.size() { 0 } else { 2 }
+        } else if self.y.size() > self.z.size() {
+            1
+        } else {
+            2
+        }
+    }
+
+    /// Checks if a ray passes through the box within the interval `ray_t` using the slab method
+    pub fn hit(&self, ray: &Ray, ray_t: Interval) -> bool {
+        let mut ray_t = ray_t;
+
+        for a in 0..3 {
+            let inv_d = 1.0 / ray.direction[a];
+            let orig = ray.origin[a];
+            let axis = self.axis(a);
+
+            let mut t0 = (axis.min - orig) * inv_d;
+            let mut t1 = (axis.max - orig) * inv_d;
+
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+
+            if t0 > ray_t.min { ray_t.min = t0; }
+            if t1 < ray_t.max { ray_t.max = t1; }
+
+            if ray_t.max <= ray_t.min {
+                return false;
+            }
+        }
+
+        return true;
+    }
+}
@@ -0,0 +1,366 @@
+use std::{fs, io, ops::RangeInclusive, sync::Arc};
+
+use crate::{
+    camera::Camera,
+    cancel,
+    hittable::Hittable,
+    hittable_list::HittableList,
+    save::{self, ExrPrecision, OutputSettings},
+    stats::RenderStats,
+    tonemap::DisplaySettings,
+    transform::{Instance, Transform},
+    vector3::Vector3,
+};
+
+/// How a track moves from one keyframe to the next
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Interpolation {
+    /// Jump to the next value when its frame is reached
+    Step,
+    /// Move at a constant speed
+    Linear,
+    /// Smooth cubic Bezier curve whose handles are placed automatically from the neighbouring keyframes
+    Bezier,
+}
+
+impl Interpolation {
+    /// Finds an interpolation from its name, as used on the command line
+    pub fn from_name(name: &str) -> Option<Self> {
+        return match name.to_lowercase().as_str() {
+            "step" => Some(Interpolation::Step),
+            "linear" => Some(Interpolation::Linear),
+            "bezier" => Some(Interpolation::Bezier),
+            _ => None,
+        };
+    }
+}
+
+/// Parses a range of frames such as `1-48`. Either end can be negative, so `-10--1` is the ten frames before frame 0
+///
+/// ## Arguments
+/// - `text` First and last frame separated by a dash
+pub fn parse_frame_range(text: &str) -> Result<RangeInclusive<i64>, String> {
+    // The separator is the first dash after the first character, since a dash at the start is a minus sign
+    let Some(split) = text.get(1..).and_then(|rest| rest.find('-')).map(|i| i + 1) else {
+        return Err(format!("--frames should look like 1-48, got '{}'", text));
+    };
+
+    let parse = |n: &str| n.trim().parse::<i64>().map_err(|e| format!("invalid --frames '{}': {}", text, e));
+    let first = parse(&text[..split])?;
+    let last = parse(&text[split + 1..])?;
+
+    if first > last {
+        return Err(format!("--frames starts after it ends, got '{}'", text));
+    }
+
+    return Ok(first..=last);
+}
+
+/// A value which can be animated by a track
+pub trait Animatable: Copy {
+    /// Returns `a + (b - a) * t`
+    fn lerp(a: Self, b: Self, t: f64) -> Self;
+
+    /// Returns `a + (b - c) * scale`, used to place Bezier handles
+    fn offset(a: Self, b: Self, c: Self, scale: f64) -> Self;
+}
+
+impl Animatable for f64 {
+    fn lerp(a: Self, b: Self, t: f64) -> Self {
+        return a + (b - a) * t;
+    }
+
+    fn offset(a: Self, b: Self, c: Self, scale: f64) -> Self {
+        return a + (b - c) * scale;
+    }
+}
+
+impl Animatable for Vector3 {
+    fn lerp(a: Self, b: Self, t: f64) -> Self {
+        return a + (b - a) * t;
+    }
+
+    fn offset(a: Self, b: Self, c: Self, scale: f64) -> Self {
+        return a + (b - c) * scale;
+    }
+}
+
+impl Animatable for Transform {
+    fn lerp(a: Self, b: Self, t: f64) -> Self {
+        return Transform::lerp(&a, &b, t);
+    }
+
+    fn offset(a: Self, b: Self, c: Self, scale: f64) -> Self {
+        Transform::new(
+            Vector3::offset(a.translation, b.translation, c.translation, scale),
+            Vector3::offset(a.rotation, b.rotation, c.rotation, scale),
+            Vector3::offset(a.scale, b.scale, c.scale, scale),
+        )
+    }
+}
+
+/// A value at a frame, along with how to move towards the next keyframe
+#[derive(Copy, Clone, Debug)]
+pub struct Keyframe<T: Animatable> {
+    pub frame: f64,
+    pub value: T,
+    pub interpolation: Interpolation,
+}
+
+/// A list of keyframes for one value, sorted by frame
+#[derive(Clone, Debug)]
+pub struct Track<T: Animatable> {
+    pub keys: Vec<Keyframe<T>>,
+}
+
+impl<T: Animatable> Track<T> {
+    /// Creates an empty track
+    pub fn new() -> Self {
+        Self {
+            keys: vec![],
+        }
+    }
+
+    /// Adds a keyframe, keeping the keys sorted by frame
+    ///
+    /// ## Arguments
+    /// - `frame` Frame the value is reached at
+    /// - `value` Value at the frame
+    /// - `interpolation` How to move from this keyframe to the next one
+    pub fn key(mut self, frame: f64, value: T, interpolation: Interpolation) -> Self {
+        self.keys.push(Keyframe { frame, value, interpolation });
+        self.keys.sort_by(|a, b| a.frame.total_cmp(&b.frame));
+        return self;
+    }
+
+    /// Returns the value of the track at a frame, holding the first and last values outside the keyed range
+    pub fn sample(&self, frame: f64) -> T {
+        let first = self.keys.first().expect("cannot sample a track without keyframes");
+        if frame <= first.frame {
+            return first.value;
+        }
+
+        let last = self.keys.last().unwrap();
+        if frame >= last.frame {
+            return last.value;
+        }
+
+        let i = self.keys.iter().rposition(|k| k.frame <= frame).unwrap();
+        let (k0, k1) = (&self.keys[i], &self.keys[i + 1]);
+        let t = (frame - k0.frame) / (k1.frame - k0.frame);
+
+        match k0.interpolation {
+            Interpolation::Step => return k0.value,
+            Interpolation::Linear => return T::lerp(k0.value, k1.value, t),
+            Interpolation::Bezier => {
+                // Catmull-Rom handles, parallel to the line between the neighbouring keys
+                let before = if i > 0 { self.keys[i - 1].value } else { k0.value };
+                let after = if i + 2 < self.keys.len() { self.keys[i + 2].value } else { k1.value };
+
+                let p0 = k0.value;
+                let p1 = T::offset(k0.value, k1.value, before, 1.0 / 6.0);
+                let p2 = T::offset(k1.value, k0.value, after, 1.0 / 6.0);
+                let p3 = k1.value;
+
+                // De Casteljau's algorithm
+                let a = T::lerp(p0, p1, t);
+                let b = T::lerp(p1, p2, t);
+                let c = T::lerp(p2, p3, t);
+                let d = T::lerp(a, b, t);
+                let e = T::lerp(b, c, t);
+                return T::lerp(d, e, t);
+            }
+        }
+    }
+}
+
+/// Keyframe tracks for the animatable fields of a camera, fields without a track keep their value
+#[derive(Clone, Debug, Default)]
+pub struct CameraAnimation {
+    pub look_from: Option<Track<Vector3>>,
+    pub look_at: Option<Track<Vector3>>,
+    pub vfov: Option<Track<f64>>,
+    pub focus_dist: Option<Track<f64>>,
+    pub defocus_angle: Option<Track<f64>>,
+}
+
+impl CameraAnimation {
+    /// Sets the camera's fields to their values at a frame
+    pub fn apply(&self, camera: &mut Camera, frame: f64) {
+        if let Some(track) = &self.look_from { camera.look_from = track.sample(frame); }
+        if let Some(track) = &self.look_at { camera.look_at = track.sample(frame); }
+        if let Some(track) = &self.vfov { camera.vfov = track.sample(frame); }
+        if let Some(track) = &self.focus_dist { camera.focus_dist = track.sample(frame); }
+        if let Some(track) = &self.defocus_angle { camera.defocus_angle = track.sample(frame); }
+    }
+}
+
+/// An object whose transform is driven by a track
+#[derive(Clone)]
+pub struct AnimatedObject {
+    pub object: Box<dyn Hittable>,
+    pub transform: Track<Transform>,
+    /// Object id the object keeps in every frame
+    pub id: u32,
+}
+
+/// A scene split into static geometry, which is built once and shared by every frame, and animated objects
+#[derive(Clone)]
+pub struct AnimatedScene {
+    pub static_world: Arc<HittableList>,
+    pub animated: Vec<AnimatedObject>,
+    /// Object id given to the next animated object, after every id in the static world
+    next_id: u32,
+}
+
+impl AnimatedScene {
+    /// Creates a scene
+    ///
+    /// ## Arguments
+    /// - `static_world` Objects which never move, ideally already inside a BVH. They are numbered for the object id pass
+    pub fn new(mut static_world: HittableList) -> Self {
+        let mut next_id = 1;
+        static_world.assign_object_ids(&mut next_id);
+
+        Self {
+            static_world: Arc::new(static_world),
+            animated: vec![],
+            next_id,
+        }
+    }
+
+    /// Adds an object which is moved by a transform track, reserving an object id for it
+    pub fn add_animated(&mut self, object: Box<dyn Hittable>, transform: Track<Transform>) {
+        self.animated.push(AnimatedObject { object, transform, id: self.next_id });
+        self.next_id += 1;
+    }
+
+    /// Returns the world at a frame. Animated objects move from their transform at `frame` to the one at `frame + 1`
+    /// over camera times 0 to 1, so the camera's shutter interval controls how much they blur
+    pub fn world_at(&self, frame: f64) -> HittableList {
+        let mut world = HittableList::new();
+        world.add_with_id(Box::new(Arc::clone(&self.static_world)), 0);
+
+        for animated in &self.animated {
+            let start = animated.transform.sample(frame);
+            let end = animated.transform.sample(frame + 1.0);
+            world.add_with_id(Box::new(Instance::moving(animated.object.clone(), start, end)), animated.id);
+        }
+
+        return world;
+    }
+}
+
+/// Renders every frame in a range as numbered files such as 'frame_0001.png'
+///
+/// ## Arguments
+/// - `camera` Camera with the settings shared by every frame
+/// - `camera_animation` Tracks for the camera's fields
+/// - `scene` The scene to render
+/// - `frames` Range of frame numbers to render
+/// - `output_dir` Directory the frames are written to, it is created if needed
+/// - `extension` File extension which picks the format of each frame, such as "png" or "exr"
+/// - `display` Exposure and tone mapping for 8-bit formats
+///
+/// Returns the stats of every frame added together
+pub fn render_animation(camera: Camera, camera_animation: &CameraAnimation, scene: &AnimatedScene, frames: RangeInclusive<i64>, output_dir: &str, extension: &str, display: DisplaySettings) -> io::Result<RenderStats> {
+    fs::create_dir_all(output_dir)?;
+    let mut render_stats = RenderStats::default();
+
+    for frame in frames {
+        let mut frame_camera = camera;
+        camera_animation.apply(&mut frame_camera, frame as f64);
+
+        println!("\nFrame {}", frame);
+        let world = Arc::new(scene.world_at(frame as f64));
+        let film = frame_camera.render_film(&world);
+        render_stats.merge(&film.stats);
+
+        let settings = OutputSettings {
+            path: format!("{}/frame_{}{:04}.{}", output_dir, if frame < 0 { "-" } else { "" }, frame.abs(), extension),
+            precision: ExrPrecision::Half,
+            display,
+            aovs: vec![],
+            alpha_mode: None,
+            ..Default::default()
+        };
+        save::save_film(&film, &settings).map_err(|e| io::Error::other(e.to_string()))?;
+        println!("Saved as '{}'", settings.path);
+
+        // The frame which was interrupted has been saved, the rest are left for another run
+        if cancel::interrupted() {
+            println!("Interrupted, stopping after frame {}", frame);
+            break;
+        }
+    }
+
+    return Ok(render_stats);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{colour::Colour, hittable::HitRecord, interval::Interval, material::Lambertian, ray::Ray, sphere::Sphere};
+
+    #[test]
+    fn animated_objects_keep_their_id_every_frame() {
+        let material = Box::new(Lambertian::new(Colour::from(0.5, 0.5, 0.5)));
+        let mut scene = AnimatedScene::new(HittableList::new());
+        let still = Transform::new(Vector3::new(), Vector3::new(), Vector3::from(1.0, 1.0, 1.0));
+        scene.add_animated(Box::new(Sphere::new(Vector3::new(), 0.5, material)), Track::new().key(0.0, still, Interpolation::Step));
+
+        let ray = Ray::from(Vector3::from(0.0, 0.0, -5.0), Vector3::from(0.0, 0.0, 1.0));
+        let ids: Vec<u32> = (0..3)
+            .map(|frame| {
+                let mut record = HitRecord::new();
+                assert!(scene.world_at(frame as f64).hit(&ray, Interval::from(0.001, f64::INFINITY), &mut record));
+                return record.object_id;
+            })
+            .collect();
+
+        assert_eq!(ids, vec![1, 1, 1]);
+    }
+
+    #[test]
+    fn frame_ranges_can_be_negative() {
+        assert_eq!(parse_frame_range("1-48"), Ok(1..=48));
+        assert_eq!(parse_frame_range("-10-5"), Ok(-10..=5));
+        assert_eq!(parse_frame_range("-10--1"), Ok(-10..=-1));
+        assert_eq!(parse_frame_range("7-7"), Ok(7..=7));
+    }
+
+    #[test]
+    fn backwards_or_malformed_frame_ranges_are_rejected() {
+        assert!(parse_frame_range("48-1").is_err());
+        assert!(parse_frame_range("12").is_err());
+        assert!(parse_frame_range("-12").is_err());
+        assert!(parse_frame_range("1-").is_err());
+        assert!(parse_frame_range("").is_err());
+    }
+
+    #[test]
+    fn step_holds_each_key_until_the_next() {
+        let track = Track::new().key(0.0, 1.0, Interpolation::Step).key(10.0, 5.0, Interpolation::Step);
+
+        assert_eq!(track.sample(0.0), 1.0);
+        assert_eq!(track.sample(9.9), 1.0);
+        assert_eq!(track.sample(10.0), 5.0);
+    }
+
+    #[test]
+    fn linear_moves_at_a_constant_speed() {
+        let track = Track::new().key(0.0, 1.0, Interpolation::Linear).key(10.0, 5.0, Interpolation::Linear);
+
+        assert!((track.sample(2.5) - 2.0).abs() < 1e-12);
+        assert_eq!(track.sample(-3.0), 1.0);
+        assert_eq!(track.sample(20.0), 5.0);
+    }
+
+    #[test]
+    fn frames_share_the_static_world() {
+        let scene = AnimatedScene::new(HittableList::new());
+        let frames = [scene.world_at(0.0), scene.world_at(1.0)];
+
+        assert_eq!(Arc::strong_count(&scene.static_world), 1 + frames.len());
+    }
+}
@@ -0,0 +1,383 @@
+use crate::{colour::Colour, hittable::HitRecord, material::Material, vector3::Vector3};
+
+/// Arbitrary output variables, extra images rendered alongside the beauty image for compositing and denoising
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Aov {
+    /// Base colour of the first surface hit
+    Albedo,
+    /// Shading normal of the first surface hit in world space
+    Normal,
+    /// Distance from the camera to the first surface hit, 0 where nothing was hit
+    Depth,
+    /// Id of the object which was hit first
+    ObjectId,
+    /// Id of the material which was hit first, identical materials share an id
+    MaterialId,
+    /// Diffusely scattered light which came straight from an emitter or the sky
+    DirectDiffuse,
+    /// Diffusely scattered light which bounced off something else first
+    IndirectDiffuse,
+    /// Light reflected or refracted by specular materials
+    Specular,
+    /// Light emitted by the first surface hit, or the sky when nothing is hit
+    Emission,
+    /// How much of the pixel is covered by objects
+    Alpha,
+    /// How many samples were taken in the pixel, which shows where adaptive sampling spent its time
+    SampleCount,
+}
+
+impl Aov {
+    pub const ALL: [Aov; 11] = [
+        Aov::Albedo,
+        Aov::Normal,
+        Aov::Depth,
+        Aov::ObjectId,
+        Aov::MaterialId,
+        Aov::DirectDiffuse,
+        Aov::IndirectDiffuse,
+        Aov::Specular,
+        Aov::Emission,
+        Aov::Alpha,
+        Aov::SampleCount,
+    ];
+
+    /// Finds a pass from its name, as used on the command line and in file names
+    pub fn from_name(name: &str) -> Option<Self> {
+        return Aov::ALL.iter().copied().find(|aov| aov.name() == name.to_lowercase());
+    }
+
+    /// Parses a comma separated list of passes, "all" selects every pass
+    pub fn parse_list(list: &str) -> Result<Vec<Self>, String> {
+        if list == "all" {
+            return Ok(Aov::ALL.to_vec());
+        }
+
+        let mut aovs = vec![];
+        for name in list.split(',').map(|n| n.trim()).filter(|n| !n.is_empty()) {
+            match Aov::from_name(name) {
+                Some(aov) => aovs.push(aov),
+                None => return Err(format!("unknown render pass '{}'", name)),
+            }
+        }
+
+        return Ok(aovs);
+    }
+
+    pub fn name(&self) -> &'static str {
+        return match self {
+            Aov::Albedo => "albedo",
+            Aov::Normal => "normal",
+            Aov::Depth => "depth",
+            Aov::ObjectId => "object-id",
+            Aov::MaterialId => "material-id",
+            Aov::DirectDiffuse => "direct-diffuse",
+            Aov::IndirectDiffuse => "indirect-diffuse",
+            Aov::Specular => "specular",
+            Aov::Emission => "emission",
+            Aov::Alpha => "alpha",
+            Aov::SampleCount => "samples",
+        };
+    }
+
+    /// Names of the channels the pass is stored in, passes with one channel only use the first component of their value
+    pub fn channel_names(&self) -> &'static [&'static str] {
+        return match self {
+            Aov::Normal => &["X", "Y", "Z"],
+            Aov::Depth => &["Z"],
+            Aov::ObjectId | Aov::MaterialId => &["id"],
+            Aov::Alpha => &["A"],
+            Aov::SampleCount => &["count"],
+            _ => &["R", "G", "B"],
+        };
+    }
+
+    /// Returns whether the pass holds light which should be exposed and tone mapped like the beauty image
+    pub fn is_radiance(&self) -> bool {
+        return matches!(self, Aov::DirectDiffuse | Aov::IndirectDiffuse | Aov::Specular | Aov::Emission);
+    }
+}
+
+/// Everything one camera sample found out about the scene
+#[derive(Copy, Clone, Debug)]
+pub struct PathSample {
+    /// Total light arriving along the camera ray
+    pub beauty: Colour,
+    pub albedo: Colour,
+    pub normal: Vector3,
+    /// Distance to the first hit, infinite if nothing was hit
+    pub depth: f64,
+    pub object_id: u32,
+    pub material_id: u32,
+    pub direct_diffuse: Colour,
+    pub indirect_diffuse: Colour,
+    pub specular: Colour,
+    pub emission: Colour,
+    /// 1 if the camera ray hit something, otherwise 0
+    pub alpha: f64,
+}
+
+impl Default for PathSample {
+    fn default() -> Self {
+        Self {
+            beauty: Colour::new(),
+            albedo: Colour::new(),
+            normal: Vector3::new(),
+            depth: f64::INFINITY,
+            object_id: 0,
+            material_id: 0,
+            direct_diffuse: Colour::new(),
+            indirect_diffuse: Colour::new(),
+            specular: Colour::new(),
+            emission: Colour::new(),
+            alpha: 0.0,
+        }
+    }
+}
+
+/// Running sums of every pass in a pixel.
+/// Ids can't be averaged so they are taken from the first sample which hit something
+#[derive(Copy, Clone, Debug, Default)]
+pub struct AovPixel {
+    pub albedo: [f32; 3],
+    pub normal: [f32; 3],
+    pub direct_diffuse: [f32; 3],
+    pub indirect_diffuse: [f32; 3],
+    pub specular: [f32; 3],
+    pub emission: [f32; 3],
+    /// Sum of the depth of every sample which hit something
+    pub depth: f32,
+    /// Number of samples which hit something
+    pub coverage: f32,
+    pub object_id: u32,
+    pub material_id: u32,
+    pub samples: u32,
+}
+
+fn accumulate(sum: &mut [f32; 3], value: Colour) {
+    sum[0] += value.x as f32;
+    sum[1] += value.y as f32;
+    sum[2] += value.z as f32;
+}
+
+impl AovPixel {
+    /// Adds a sample to the pixel
+    pub fn add_sample(&mut self, sample: &PathSample) {
+        accumulate(&mut self.albedo, sample.albedo);
+        accumulate(&mut self.normal, sample.normal);
+        accumulate(&mut self.direct_diffuse, sample.direct_diffuse);
+        accumulate(&mut self.indirect_diffuse, sample.indirect_diffuse);
+        accumulate(&mut self.specular, sample.specular);
+        accumulate(&mut self.emission, sample.emission);
+
+        if sample.alpha > 0.0 {
+            if self.coverage == 0.0 {
+                self.object_id = sample.object_id;
+                self.material_id = sample.material_id;
+            }
+            self.depth += sample.depth as f32;
+            self.coverage += sample.alpha as f32;
+        }
+
+        self.samples += 1;
+    }
+
+    /// Adds the sums of another set of samples of the same pixel, keeping this pixel's ids unless it never hit anything
+    pub fn merge(&mut self, other: &AovPixel) {
+        for (sum, other_sum) in [
+            (&mut self.albedo, other.albedo),
+            (&mut self.normal, other.normal),
+            (&mut self.direct_diffuse, other.direct_diffuse),
+            (&mut self.indirect_diffuse, other.indirect_diffuse),
+            (&mut self.specular, other.specular),
+            (&mut self.emission, other.emission),
+        ] {
+            for (channel, other_channel) in sum.iter_mut().zip(other_sum) {
+                *channel += other_channel;
+            }
+        }
+
+        if self.coverage == 0.0 {
+            self.object_id = other.object_id;
+            self.material_id = other.material_id;
+        }
+        self.depth += other.depth;
+        self.coverage += other.coverage;
+        self.samples += other.samples;
+    }
+
+    /// Returns the value of a pass in the pixel, passes with a single channel are stored in every component
+    pub fn value(&self, aov: Aov) -> Colour {
+        if self.samples == 0 {
+            return Colour::new();
+        }
+
+        let average = |sum: [f32; 3]| Colour::from(sum[0] as f64, sum[1] as f64, sum[2] as f64) / self.samples as f64;
+        let scalar = |value: f64| Colour::from(value, value, value);
+
+        return match aov {
+            Aov::Albedo => average(self.albedo),
+            Aov::Normal => {
+                let normal = average(self.normal);
+                if normal.near_zero() { normal } else { normal.unit() }
+            }
+            Aov::Depth => scalar(if self.coverage > 0.0 { (self.depth / self.coverage) as f64 } else { 0.0 }),
+            Aov::ObjectId => scalar(self.object_id as f64),
+            Aov::MaterialId => scalar(self.material_id as f64),
+            Aov::DirectDiffuse => average(self.direct_diffuse),
+            Aov::IndirectDiffuse => average(self.indirect_diffuse),
+            Aov::Specular => average(self.specular),
+            Aov::Emission => average(self.emission),
+            Aov::Alpha => scalar(self.coverage as f64 / self.samples as f64),
+            Aov::SampleCount => scalar(self.samples as f64),
+        };
+    }
+}
+
+/// Returns an id for a material made from its type and albedo, so identical materials on different objects share an id.
+/// Ids are never 0, which is kept for samples which hit nothing, and fit in 24 bits so a float channel stores them exactly
+///
+/// ## Arguments
+/// - `material` Material which was hit
+/// - `record` Where it was hit
+pub fn material_id(material: &dyn Material, record: &HitRecord) -> u32 {
+    let albedo = material.albedo(record.u, record.v, record.point);
+
+    // FNV-1a, which gives the same ids on every run unlike the standard library's hasher
+    let mut hash: u32 = 0x811c9dc5;
+    let bytes = material.type_name().bytes().chain([albedo.x, albedo.y, albedo.z].into_iter().flat_map(|c| (c as f32).to_le_bytes()));
+    for byte in bytes {
+        hash ^= byte as u32;
+        hash = hash.wrapping_mul(0x01000193);
+    }
+
+    return (hash & 0xFFFFFF).max(1);
+}
+
+/// Maps a value from 0 to 1 onto a heatmap going from black through red and yellow to white
+pub fn heat_colour(value: f64) -> Colour {
+    let t = value.clamp(0.0, 1.0);
+    return Colour::from((3.0 * t).min(1.0), (3.0 * t - 1.0).clamp(0.0, 1.0), (3.0 * t - 2.0).clamp(0.0, 1.0));
+}
+
+/// Turns an id into a bright colour which is easy to tell apart from its neighbours, 0 stays black
+pub fn id_colour(id: u32) -> Colour {
+    if id == 0 {
+        return Colour::new();
+    }
+
+    // Scramble the bits so consecutive ids get unrelated colours
+    let mut x = id.wrapping_mul(0x9E3779B1);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x85EBCA77);
+    x ^= x >> 13;
+
+    let channel = |shift: u32| 0.2 + 0.8 * ((x >> shift) & 0xFF) as f64 / 255.0;
+    return Colour::from(channel(0), channel(8), channel(16));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{Lambertian, Metal};
+
+    fn hit_sample(depth: f64, object_id: u32, albedo: f64) -> PathSample {
+        return PathSample {
+            albedo: Colour::from(albedo, albedo, albedo),
+            normal: Vector3::from(0.0, 2.0, 0.0),
+            depth,
+            object_id,
+            material_id: object_id + 100,
+            alpha: 1.0,
+            ..Default::default()
+        };
+    }
+
+    fn assert_close(a: Colour, b: Colour) {
+        assert!((a - b).length() < 1e-6, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn values_average_the_samples() {
+        let mut pixel = AovPixel::default();
+        pixel.add_sample(&hit_sample(2.0, 7, 0.2));
+        pixel.add_sample(&hit_sample(4.0, 8, 0.6));
+        pixel.add_sample(&PathSample::default());
+        pixel.add_sample(&PathSample::default());
+
+        assert_close(pixel.value(Aov::Albedo), Colour::from(0.2, 0.2, 0.2));
+        assert_close(pixel.value(Aov::Normal), Vector3::from(0.0, 1.0, 0.0));
+        assert_close(pixel.value(Aov::Alpha), Colour::from(0.5, 0.5, 0.5));
+        assert_close(pixel.value(Aov::SampleCount), Colour::from(4.0, 4.0, 4.0));
+
+        // Depth only averages the samples which hit something and ids come from the first of them
+        assert_close(pixel.value(Aov::Depth), Colour::from(3.0, 3.0, 3.0));
+        assert_close(pixel.value(Aov::ObjectId), Colour::from(7.0, 7.0, 7.0));
+        assert_close(pixel.value(Aov::MaterialId), Colour::from(107.0, 107.0, 107.0));
+    }
+
+    #[test]
+    fn empty_pixels_are_zero() {
+        let pixel = AovPixel::default();
+        for aov in Aov::ALL {
+            assert_close(pixel.value(aov), Colour::new());
+        }
+    }
+
+    #[test]
+    fn merging_matches_adding_every_sample_to_one_pixel() {
+        let samples = [PathSample::default(), hit_sample(1.0, 3, 0.5), hit_sample(5.0, 4, 0.1), hit_sample(2.0, 5, 0.9)];
+
+        let mut whole = AovPixel::default();
+        let mut first_half = AovPixel::default();
+        let mut second_half = AovPixel::default();
+        for (i, sample) in samples.iter().enumerate() {
+            whole.add_sample(sample);
+            if i < 2 { first_half.add_sample(sample) } else { second_half.add_sample(sample) }
+        }
+        first_half.merge(&second_half);
+
+        for aov in Aov::ALL {
+            assert_close(first_half.value(aov), whole.value(aov));
+        }
+    }
+
+    #[test]
+    fn merging_takes_the_ids_of_the_other_pixel_only_if_this_one_hit_nothing() {
+        let mut missed = AovPixel::default();
+        missed.add_sample(&PathSample::default());
+        let mut hit = AovPixel::default();
+        hit.add_sample(&hit_sample(1.0, 9, 0.5));
+
+        let mut merged = missed;
+        merged.merge(&hit);
+        assert_eq!(merged.object_id, 9);
+
+        let mut other = AovPixel::default();
+        other.add_sample(&hit_sample(1.0, 2, 0.5));
+        merged.merge(&other);
+        assert_eq!(merged.object_id, 9);
+    }
+
+    #[test]
+    fn identical_materials_share_an_id_wherever_they_are_hit() {
+        let mut record = HitRecord::new();
+        let first = material_id(&Lambertian::new(Colour::from(0.4, 0.2, 0.1)), &record);
+
+        record.point = Vector3::from(3.0, -2.0, 7.0);
+        record.u = 0.75;
+        let second = material_id(&Lambertian::new(Colour::from(0.4, 0.2, 0.1)), &record);
+
+        assert_eq!(first, second);
+        assert!(first != 0 && first < 1 << 24);
+    }
+
+    #[test]
+    fn different_materials_get_different_ids() {
+        let record = HitRecord::new();
+        let lambertian = material_id(&Lambertian::new(Colour::from(0.4, 0.2, 0.1)), &record);
+
+        assert_ne!(lambertian, material_id(&Lambertian::new(Colour::from(0.4, 0.2, 0.2)), &record));
+        assert_ne!(lambertian, material_id(&Metal::new(Colour::from(0.4, 0.2, 0.1), 0.0), &record));
+    }
+}
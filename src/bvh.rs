@@ -0,0 +1,131 @@
+use crate::{
+    aabb::Aabb,
+    hittable::{HitRecord, Hittable},
+    hittable_list::HittableList,
+    interval::Interval,
+    ray::Ray,
+    stats,
+};
+
+/// Node of a bounding volume hierarchy, a binary tree of bounding boxes which lets rays skip every object in a box they miss.
+/// Moving objects report boxes covering their whole path so they are never skipped by mistake
+#[derive(Clone)]
+pub struct BvhNode {
+    pub left: Box<dyn Hittable>,
+    pub right: Box<dyn Hittable>,
+    bbox: Aabb,
+}
+
+impl BvhNode {
+    /// Builds a hierarchy containing every object in the list
+    pub fn from_list(list: HittableList) -> Self {
+        return BvhNode::from_objects(list.objects);
+    }
+
+    /// Builds a hierarchy by splitting the objects in half along the longest axis of their combined bounding box
+    ///
+    /// ## Arguments
+    /// - `objects` Objects to put in the hierarchy, with none the node is empty and never hit
+    pub fn from_objects(mut objects: Vec<Box<dyn Hittable>>) -> Self {
+        let mut bbox = Aabb::new();
+        for object in &objects {
+            bbox = Aabb::from_boxes(bbox, object.bounding_box());
+        }
+
+        let axis = bbox.longest_axis();
+
+        let (left, right): (Box<dyn Hittable>, Box<dyn Hittable>) = match objects.len() {
+            0 => (Box::new(HittableList::new()), Box::new(HittableList::new())),
+            1 => (objects.remove(0), Box::new(HittableList::new())),
+            2 => {
+                let right = objects.remove(1);
+                (objects.remove(0), right)
+            }
+            _ => {
+                objects.sort_by(|a, b| a.bounding_box().axis(axis).min.total_cmp(&b.bounding_box().axis(axis).min));
+
+                let right_objects = objects.split_off(objects.len() / 2);
+                (Box::new(BvhNode::from_objects(objects)), Box::new(BvhNode::from_objects(right_objects)))
+            }
+        };
+
+        Self {
+            left,
+            right,
+            bbox,
+        }
+    }
+}
+
+impl Hittable for BvhNode {
+    fn hit(&self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        stats::count_bvh_node_visit();
+        if !self.bbox.hit(ray, ray_t) {
+            return false;
+        }
+
+        let hit_left = self.left.hit(ray, ray_t, rec);
+        let right_max = if hit_left { rec.t } else { ray_t.max };
+        let hit_right = self.right.hit(ray, Interval::from(ray_t.min, right_max), rec);
+
+        return hit_left || hit_right;
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        return self.bbox;
+    }
+
+    fn assign_object_ids(&mut self, next_id: &mut u32) {
+        self.left.assign_object_ids(next_id);
+        self.right.assign_object_ids(next_id);
+    }
+
+    fn memory_size(&self) -> usize {
+        return size_of_val(self) + self.left.memory_size() + self.right.memory_size();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{colour::Colour, material::Lambertian, sphere::Sphere, vector3::Vector3};
+
+    #[test]
+    fn empty_hierarchy_is_never_hit() {
+        let node = BvhNode::from_objects(vec![]);
+        let ray = Ray::from(Vector3::new(), Vector3::from(0.0, 0.0, 1.0));
+
+        assert!(!node.hit(&ray, Interval::from(0.0, f64::INFINITY), &mut HitRecord::new()));
+    }
+
+    #[test]
+    fn hierarchy_finds_the_closest_object() {
+        let material = Box::new(Lambertian::new(Colour::from(0.5, 0.5, 0.5)));
+        let objects: Vec<Box<dyn Hittable>> = (0..5)
+            .map(|i| Box::new(Sphere::new(Vector3::from(0.0, 0.0, 10.0 - 2.0 * i as f64), 0.5, material.clone())) as Box<dyn Hittable>)
+            .collect();
+        let node = BvhNode::from_objects(objects);
+
+        let ray = Ray::from(Vector3::new(), Vector3::from(0.0, 0.0, 1.0));
+        let mut rec = HitRecord::new();
+        assert!(node.hit(&ray, Interval::from(0.0, f64::INFINITY), &mut rec));
+        assert!((rec.t - 1.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn moving_objects_are_found_anywhere_along_their_path() {
+        let material = Box::new(Lambertian::new(Colour::from(0.5, 0.5, 0.5)));
+        let objects: Vec<Box<dyn Hittable>> = vec![
+            Box::new(Sphere::new(Vector3::from(-4.0, 0.0, 5.0), 0.5, material.clone())),
+            Box::new(Sphere::moving(Vector3::from(0.0, 0.0, 5.0), Vector3::from(4.0, 0.0, 5.0), 0.5, material.clone())),
+            Box::new(Sphere::new(Vector3::from(8.0, 0.0, 5.0), 0.5, material)),
+        ];
+        let node = BvhNode::from_objects(objects);
+
+        // Aimed at where the moving sphere ends up, so it is only there when the shutter closes
+        let direction = Vector3::from(4.0, 0.0, 5.0);
+        let mut rec = HitRecord::new();
+        assert!(node.hit(&Ray::with_time(Vector3::new(), direction, 1.0), Interval::from(0.0, f64::INFINITY), &mut rec));
+        assert!(!node.hit(&Ray::with_time(Vector3::new(), direction, 0.0), Interval::from(0.0, f64::INFINITY), &mut rec));
+    }
+}
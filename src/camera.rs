@@ -1,19 +1,75 @@
+use std::collections::BTreeMap;
 use std::sync::{mpsc, Mutex, Arc};
-use std::io::Write;
 use std::thread::{self, JoinHandle};
-use std::time::Instant;
+use std::time::{Duration, Instant};
+use rand::{thread_rng, Rng};
+use crate::aov::{self, AovPixel, PathSample};
+use crate::cancel::CancelToken;
+use crate::checkpoint;
+use crate::constant_medium::Atmosphere;
+use crate::film::{Film, FilmPixel, FilmSettings, PixelVariance};
+use crate::filter::Filter;
+use crate::progress::{ConsoleProgress, Progress, ProgressReporter};
+use crate::random;
+use crate::sampler::{Sampler, SamplerKind};
+use crate::scene::SceneKind;
+use crate::stats::{self, RenderStats};
 use crate::hittable_list::HittableList;
-use crate::save;
-use crate::vector3::{cross_product, random_in_unit_disk};
-use crate::viewer::show_image;
-use crate::{hittable::{Hittable, HitRecord}, colour::Colour, interval::Interval, ray::Ray, vector3::Vector3, random::random_f64};
+use crate::save::{self, OutputSettings};
+use crate::vector3::{cross_product, dot_product, random_in_unit_disk};
+use crate::viewer::{show_film_live, show_film_navigable, Navigation};
+use crate::{hittable::{Hittable, HitRecord}, colour::Colour, interval::Interval, ray::Ray, vector3::Vector3, random::{random_2d, random_f64}};
+
+/// Number of samples taken between checks of whether an adaptively sampled pixel has converged
+const ADAPTIVE_BATCH: i64 = 8;
+
+/// Settings for spending more samples on noisy pixels than on smooth ones.
+/// Pixels take at least `min_samples`, then keep sampling until their estimated error drops below the threshold
+/// or they reach the camera's `samples_per_pixel`
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AdaptiveSampling {
+    pub min_samples: i64,
+    /// Largest acceptable error, see `PixelVariance::error`
+    pub threshold: f64,
+}
+
+/// What the camera ray through a pixel hit, shown when the pixel is clicked in the viewer
+#[derive(Clone, Debug)]
+pub struct Inspection {
+    pub object_id: u32,
+    /// Description of the material and its parameters
+    pub material: String,
+    pub point: Vector3,
+    pub normal: Vector3,
+    /// Distance from the camera to the hit
+    pub distance: f64,
+    /// Distance from the camera to the hit along the view direction, which is what `focus_dist` measures
+    pub focus_dist: f64,
+}
+
+/// Some samples to take in every pixel of a row, sent to a render thread
+struct RowJob {
+    row: usize,
+    first_sample: i64,
+    samples: i64,
+    /// Render passes of the row so far, which the new samples are added to
+    aovs: Vec<AovPixel>,
+    /// Statistics of the row so far, which adaptive sampling uses to skip pixels that are done
+    variance: Vec<PixelVariance>,
+}
 
 /// Represents a scanline being transfered between threads
-/// id is which row of the image the scanline is from
+/// id is which row of the image the scanline is from.
+/// Samples spread into the rows around the scanline, so `splats` holds every row from `first_row` that they reached
 #[derive(Clone)]
 struct ScanlineResult {
     id: usize,
-    scanline: Vec<u32>,
+    first_row: usize,
+    splats: Vec<FilmPixel>,
+    aovs: Vec<AovPixel>,
+    variance: Vec<PixelVariance>,
+    /// Samples taken across the row, fewer than asked for if pixels converged or the render was cancelled
+    samples: u64,
 }
 
 /// Represents a camera in the raytracer
@@ -39,6 +95,35 @@ pub struct Camera {
     pub focus_dist: f64,
     pub defocus_disk_u: Vector3,
     pub defocus_disk_v: Vector3,
+    pub atmosphere: Option<Atmosphere>,
+    /// Time the shutter opens, moving objects are positioned over times 0 to 1
+    pub shutter_open: f64,
+    /// Time the shutter closes
+    pub shutter_close: f64,
+    /// Reconstruction filter used to weight samples into the pixels around them
+    pub filter: Filter,
+    /// How the samples of each pixel are spread out
+    pub sampler: SamplerKind,
+    /// Spends samples where they are needed instead of evenly, `samples_per_pixel` becomes the most a pixel can take
+    pub adaptive: Option<AdaptiveSampling>,
+    /// Seed every pixel's random numbers are derived from, a random one is picked for each render if it isn't set
+    pub seed: Option<u64>,
+    /// Threads to render with, one per core if it isn't set. The image is the same for any number of threads
+    pub threads: Option<usize>,
+    /// Scene that is rendered. This, the scene seed and the volume aren't used to render, but films remember them so different scenes aren't merged
+    pub scene: SceneKind,
+    /// Seed the scene was built from
+    pub scene_seed: u64,
+    /// Fingerprint of the volume grid the scene was built with, see `VoxelGrid::fingerprint`
+    pub volume: Option<u64>,
+    /// Leaves the sky out of pixels where camera rays miss everything so the render can be composited over another image.
+    /// Bounced rays still pick up light from the sky
+    pub transparent_background: bool,
+    /// Stops adding passes once the render has taken this long, the last pass is cut short if it runs over.
+    /// Without a sample budget as well, passes carry on past `samples_per_pixel` until the time is spent
+    pub time_budget: Option<Duration>,
+    /// Stops adding passes once this many camera samples have been taken across the whole image
+    pub sample_budget: Option<u64>,
 }
 
 impl Default for Camera {
@@ -64,6 +149,20 @@ impl Default for Camera {
             focus_dist: 10.0,
             defocus_disk_u: Vector3::new(),
             defocus_disk_v: Vector3::new(),
+            atmosphere: None,
+            shutter_open: 0.0,
+            shutter_close: 1.0,
+            filter: Filter::default(),
+            sampler: SamplerKind::Independent,
+            adaptive: None,
+            seed: None,
+            threads: None,
+            scene: SceneKind::Spheres,
+            scene_seed: 0,
+            volume: None,
+            transparent_background: false,
+            time_budget: None,
+            sample_budget: None,
         } 
     }
 }
@@ -107,111 +206,667 @@ impl Camera {
         self.defocus_disk_v = self.v * defocus_radius;
     }
     
-    /// Render the scene
+    /// Turns the camera around the point it looks at
+    ///
+    /// ## Arguments
+    /// - `yaw` Degrees to turn around the up direction
+    /// - `pitch` Degrees to tilt over the top or under the bottom, stopping just short of looking straight along the up direction
+    pub fn orbit(&mut self, yaw: f64, pitch: f64) {
+        let up = self.up.unit();
+        let mut offset = rotate_about(self.look_from - self.look_at, up, yaw.to_radians());
+
+        let right = cross_product(up, offset);
+        if !right.near_zero() {
+            let tilted = rotate_about(offset, right.unit(), -pitch.to_radians());
+            let cos_to_up = dot_product(tilted.unit(), up);
+
+            if cos_to_up.abs() < 0.999 {
+                offset = tilted;
+            }
+        }
+
+        self.look_from = self.look_at + offset;
+    }
+
+    /// Slides the camera and the point it looks at across the view
+    ///
+    /// ## Arguments
+    /// - `right` Distance to move right, as a fraction of the distance to the point the camera looks at
+    /// - `up` Distance to move up, as a fraction of the distance to the point the camera looks at
+    pub fn pan(&mut self, right: f64, up: f64) {
+        let forward = self.look_at - self.look_from;
+        let distance = forward.length();
+        let right_dir = cross_product(forward, self.up).unit();
+        let up_dir = cross_product(right_dir, forward).unit();
+
+        let offset = distance * (right * right_dir + up * up_dir);
+        self.look_from += offset;
+        self.look_at += offset;
+    }
+
+    /// Moves the camera towards or away from the point it looks at, never passing it
+    ///
+    /// ## Arguments
+    /// - `amount` Fraction of the distance to move in by, negative values move out
+    pub fn dolly(&mut self, amount: f64) {
+        let offset = self.look_from - self.look_at;
+        self.look_from = self.look_at + offset * (1.0 - amount).max(0.05);
+    }
+
+    /// Moves the camera and the point it looks at together, like walking through the scene
+    ///
+    /// ## Arguments
+    /// - `forward` Distance to move along the view direction
+    /// - `right` Distance to move to the right
+    /// - `up` Distance to move along the up direction
+    pub fn fly(&mut self, forward: f64, right: f64, up: f64) {
+        let forward_dir = (self.look_at - self.look_from).unit();
+        let right_dir = cross_product(forward_dir, self.up).unit();
+
+        let offset = forward * forward_dir + right * right_dir + up * self.up.unit();
+        self.look_from += offset;
+        self.look_at += offset;
+    }
+
+    /// Finds what the camera ray through a point on the film hits first, None if it hits nothing
+    ///
+    /// ## Arguments
+    /// - `world` Objects in the scene
+    /// - `x` Horizontal position on the film in pixels, whole numbers are the centres of pixels
+    /// - `y` Vertical position on the film in pixels
+    pub fn inspect(&mut self, world: &dyn Hittable, x: f64, y: f64) -> Option<Inspection> {
+        self.init();
+
+        // A ray from the centre of the lens in the middle of the shutter, so clicking the same pixel always gives the same answer
+        let pixel = self.pixel00_loc + (x * self.pixel_delta_u) + (y * self.pixel_delta_v);
+        let time = (self.shutter_open + self.shutter_close) / 2.0;
+        let ray = Ray::with_time(self.center, pixel - self.center, time);
+        let mut record = HitRecord::new();
+        if !world.hit(&ray, Interval::from(0.001, f64::INFINITY), &mut record) {
+            return None;
+        }
+
+        return Some(Inspection {
+            object_id: record.object_id,
+            material: record.material.describe(),
+            point: record.point,
+            normal: record.normal,
+            distance: (record.point - ray.origin).length(),
+            focus_dist: dot_product(record.point - self.center, -self.w),
+        });
+    }
+
+    /// Explores the scene in a window, mouse and keyboard input moves the camera and starts the render again from the new view.
+    /// When the window is closed the last view is saved
     ///
     /// ## Arguments
     ///
     /// - `world` HittableList of objects in the scene
-    pub fn render(&mut self, world: HittableList) {
+    /// - `output` Where and how to save the final view
+    pub fn explore(&mut self, world: HittableList, output: &OutputSettings) -> Film {
+        self.init();
+
+        let (preview_tx, preview_rx) = mpsc::sync_channel::<Film>(1);
+        let (camera_tx, camera_rx) = mpsc::channel::<Camera>();
+        let cancel = CancelToken::new();
+
+        let world = Arc::new(world);
+        let mut camera = *self;
+        let render_cancel = cancel.clone();
+        let navigation = Navigation { camera: *self, world: Arc::clone(&world), changes: camera_tx };
+
+        let renderer = thread::spawn(move || {
+            loop {
+                let mut moved = None;
+                let film = camera.render_passes(&world, 1, &render_cancel, &mut ConsoleProgress::new("rows"), |film| {
+                    let _ = preview_tx.try_send(film.clone());
+
+                    // A new view throws away what has been rendered so far
+                    if let Some(new_camera) = camera_rx.try_iter().last() {
+                        moved = Some(new_camera);
+                        return false;
+                    }
+                    return true;
+                });
+
+                if render_cancel.is_cancelled() {
+                    return film;
+                }
+
+                camera = match moved {
+                    Some(new_camera) => new_camera,
+                    None => {
+                        let _ = preview_tx.send(film.clone());
+
+                        // The render is finished, so wait until the view changes or the window is closed
+                        match camera_rx.recv() {
+                            Ok(new_camera) => camera_rx.try_iter().last().unwrap_or(new_camera),
+                            Err(_) => return film,
+                        }
+                    }
+                };
+            }
+        });
+
+        show_film_navigable(self.image_width as usize, self.image_height as usize, preview_rx, output.display, output.directory(), Some(navigation));
+        cancel.cancel();
+
+        let film = renderer.join().unwrap();
+        match save::save_film(&film, output) {
+            Ok(()) => println!("Saved as '{}'", output.path),
+            Err(err) => eprintln!("ERROR: could not save '{}': {}", output.path, err),
+        }
+
+        return film;
+    }
+
+    /// Render the scene progressively while showing it in a window, then save it.
+    /// The window is redrawn after every pass and closing it stops the render early, keeping the samples taken so far.
+    /// If the output has a checkpoint it is written every `checkpoint_interval` and once the render stops
+    ///
+    /// ## Arguments
+    ///
+    /// - `world` HittableList of objects in the scene
+    /// - `output` Where and how to save the render
+    /// - `resume` Film from a checkpoint to carry on adding samples to, instead of starting from nothing
+    pub fn render(&mut self, world: HittableList, output: &OutputSettings, resume: Option<Film>) -> Film {
+        self.init();
+
+        // Only the newest film matters to the window, so a pass finishing while it is still drawing the last one is skipped
+        let (preview_tx, preview_rx) = mpsc::sync_channel::<Film>(1);
+        let cancel = CancelToken::new();
+
+        let world = Arc::new(world);
+        let mut camera = *self;
+        let render_cancel = cancel.clone();
+        let output_clone = output.clone();
+
+        let renderer = thread::spawn(move || {
+            let mut last_checkpoint = Instant::now();
+            let on_pass = |film: &Film| {
+                let _ = preview_tx.try_send(film.clone());
+
+                if let Some(path) = &output_clone.checkpoint {
+                    if last_checkpoint.elapsed() >= output_clone.checkpoint_interval {
+                        checkpoint::try_save_checkpoint(path, film);
+                        last_checkpoint = Instant::now();
+                    }
+                }
+                return true;
+            };
+
+            let mut progress = ConsoleProgress::new("rows");
+            let film = match resume {
+                Some(film) => camera.continue_passes(&world, film, 1, &render_cancel, &mut progress, on_pass),
+                None => camera.render_passes(&world, 1, &render_cancel, &mut progress, on_pass),
+            };
+
+            if let Some(path) = &output_clone.checkpoint {
+                checkpoint::try_save_checkpoint(path, &film);
+                println!("Checkpoint written to '{}'", path);
+            }
+
+            match save::save_film(&film, &output_clone) {
+                Ok(()) => println!("Saved as '{}'", output_clone.path),
+                Err(err) => eprintln!("ERROR: could not save '{}': {}", output_clone.path, err),
+            }
+
+            // Make sure the window ends up showing the finished film
+            let _ = preview_tx.send(film.clone());
+            return film;
+        });
+
+        show_film_live(self.image_width as usize, self.image_height as usize, preview_rx, output.display, output.directory());
+        cancel.cancel();
+
+        return renderer.join().unwrap();
+    }
+
+    /// Render the scene into a high dynamic range film.
+    /// With a time or sample budget the samples are added one pass at a time so stopping early still covers the whole image
+    ///
+    /// ## Arguments
+    ///
+    /// - `world` HittableList of objects in the scene
+    pub fn render_film(&mut self, world: &Arc<HittableList>) -> Film {
+        let budgeted = self.time_budget.is_some() || self.sample_budget.is_some();
+        let samples_per_pass = if budgeted { 1 } else { self.samples_per_pixel };
+
+        return self.render_passes(world, samples_per_pass, &CancelToken::new(), &mut ConsoleProgress::new("rows"), |_| true);
+    }
+
+    /// Render the scene into a high dynamic range film a few samples per pixel at a time
+    ///
+    /// ## Arguments
+    ///
+    /// - `world` HittableList of objects in the scene
+    /// - `samples_per_pass` How many samples every pixel takes in each pass
+    /// - `cancel` Stops the render as soon as possible, keeping the samples taken so far. The time budget is added to it
+    /// - `progress` Told every time a row of a pass is finished
+    /// - `on_pass` Called with the film after every pass, the render stops early if it returns false
+    pub fn render_passes(&mut self, world: &Arc<HittableList>, samples_per_pass: i64, cancel: &CancelToken, progress: &mut dyn ProgressReporter, on_pass: impl FnMut(&Film) -> bool) -> Film {
+        let film = self.new_film();
+        return self.continue_passes(world, film, samples_per_pass, cancel, progress, on_pass);
+    }
+
+    /// Creates an empty film the size of the image, using the camera's seed or a random one if it doesn't have one
+    pub fn new_film(&mut self) -> Film {
+        self.init();
+
+        // Linear radiance is accumulated here, each thread sends back whole rows so there are no race conditions
+        let mut film = Film::new(self.image_width as usize, self.image_height as usize);
+        film.transparent = self.transparent_background;
+        film.settings = FilmSettings {
+            scene: self.scene,
+            scene_seed: self.scene_seed,
+            volume: self.volume,
+            fog: self.atmosphere.map(|fog| (fog.density, fog.height)),
+            sampler: self.sampler,
+            filter: self.filter,
+            samples_per_pixel: self.samples_per_pixel.max(0) as u64,
+            adaptive: self.adaptive,
+        };
+        film.seed = self.seed.unwrap_or_else(|| thread_rng().gen());
+
+        return film;
+    }
+
+    /// Returns how many samples a pixel can take before the render stops on its own.
+    /// A render with only a time budget is stopped by its deadline, so it has no limit
+    fn sample_limit(&self) -> i64 {
+        if self.time_budget.is_some() && self.sample_budget.is_none() {
+            return u32::MAX as i64;
+        }
+        return self.samples_per_pixel;
+    }
+
+    /// Carries on adding samples to a film, such as one loaded from a checkpoint, until every pixel has `samples_per_pixel`,
+    /// or until the time budget runs out if that is the only budget.
+    /// The film's seed and next sample are used so the new samples follow on from the old ones exactly
+    ///
+    /// ## Arguments
+    ///
+    /// - `world` HittableList of objects in the scene, which must be the one the film was started with
+    /// - `film` Film to add to, it must be the size of the image
+    /// - `samples_per_pass` How many samples every pixel takes in each pass
+    /// - `cancel` Stops the render as soon as possible, keeping the samples taken so far. The time budget is added to it
+    /// - `progress` Told every time a row of a pass is finished, rows are the units of work
+    /// - `on_pass` Called with the film after every pass, the render stops early if it returns false
+    pub fn continue_passes(&mut self, world: &Arc<HittableList>, film: Film, samples_per_pass: i64, cancel: &CancelToken, progress: &mut dyn ProgressReporter, mut on_pass: impl FnMut(&Film) -> bool) -> Film {
         let start_time = Instant::now();
         self.init();
 
-        // Represents final pixel data
-        // I used a 2D Vector because I wanted to ensure there wouldn't be any race conditions and each thread will know where to
-        // output it's data
-        let mut data: Vec<Vec<u32>> = vec![Vec::new(); self.image_height as usize];
+        let cancel = match self.time_budget {
+            Some(budget) => cancel.with_deadline(start_time + budget),
+            None => cancel.clone(),
+        };
 
-        // List of thread handles that we can loop over and join
-        let mut handles: Vec<JoinHandle<_>> = vec![];
+        let mut film = film;
+        let width = film.width;
+        let seed = film.seed;
+        let start_sample = film.next_sample as i64;
 
-        println!("\nStarting Render at {}x{} pixels with {} samples", self.image_width, self.image_height, self.samples_per_pixel);
+        let sample_limit = self.sample_limit();
+        match (self.time_budget, sample_limit == self.samples_per_pixel) {
+            (Some(budget), false) => println!("\nStarting Render at {}x{} pixels for {:.1} seconds", self.image_width, self.image_height, budget.as_secs_f64()),
+            _ => println!("\nStarting Render at {}x{} pixels with {} samples", self.image_width, self.image_height, self.samples_per_pixel),
+        }
+        println!("Scene seed: {}, sample seed: {}", film.settings.scene_seed, seed);
+        if start_sample > 0 {
+            println!("Resuming from sample {}", start_sample);
+        }
+
+        // Rows are handed out to the threads, which all share the world
+        let (job_tx, job_rx) = mpsc::channel::<RowJob>();
+        let job_rx = Arc::new(Mutex::new(job_rx));
         // Create a channel to send pixel data between threads
         let (pixel_tx, pixel_rx) = mpsc::channel::<ScanlineResult>();
 
-        for j in 0..self.image_height {
+        // List of thread handles that we can loop over and join
+        let mut handles: Vec<JoinHandle<_>> = vec![];
+
+        let threads = self.threads.unwrap_or_else(|| thread::available_parallelism().map_or(4, |n| n.get()));
+        for _ in 0..threads.max(1) {
             // Clone variables in order to be used in different threads
-            let camera_clone = self.clone();
-            let mut world_clone = world.clone();
-            let pixel_transmitter = Arc::new(Mutex::new(pixel_tx.clone()));
-            let mut colour_data: Vec<u32> = vec![];
+            let camera_clone = *self;
+            let world_clone = Arc::clone(world);
+            let jobs = job_rx.clone();
+            let pixel_transmitter = pixel_tx.clone();
+            let worker_cancel = cancel.clone();
 
             let handle = thread::spawn(move || {
-                colour_data.clear();
-                for i in 0..camera_clone.image_width { 
-                    let mut pixel_colour = Colour::new();
-                    for _ in 0..camera_clone.samples_per_pixel {
-                        let r = camera_clone.get_ray(i, j);
-                        pixel_colour += camera_clone.ray_colour(&r, camera_clone.depth_limit, &mut world_clone);
+                random::set_sampler(Some(Sampler::new(camera_clone.sampler, camera_clone.samples_per_pixel as u32, seed)));
+                stats::take_counters();
+                // Only time spent on rows counts as busy, waiting for the next job doesn't
+                let mut busy = Duration::ZERO;
+
+                loop {
+                    let job = jobs.lock().unwrap().recv();
+                    match job {
+                        Ok(job) => {
+                            let row_start = Instant::now();
+                            let result = camera_clone.render_row(job, &world_clone, &worker_cancel);
+                            busy += row_start.elapsed();
+                            pixel_transmitter.send(result).unwrap();
+                        }
+                        Err(_) => break,
                     }
-                    
-                    // Write the pixel data to the temporary colour data buffer
-                    pixel_colour.write_colour_pixels(&mut colour_data, camera_clone.samples_per_pixel);             
-               }
-                // Send scanline back to main thread
-                pixel_transmitter.lock().unwrap().send(ScanlineResult { id: j as usize, scanline: colour_data }).unwrap();
+                }
+
+                return (busy, stats::take_counters());
             });
 
             handles.push(handle);
         }
 
-        let mut current_complete_count = 1;
-        for handle in handles {
-            handle.join().unwrap();
+        let samples_per_pass = samples_per_pass.clamp(1, self.samples_per_pixel.max(1));
+        let remaining = (sample_limit - start_sample).max(0);
+        let passes = (remaining + samples_per_pass - 1) / samples_per_pass;
+
+        let mut current = Progress {
+            total: passes as u64 * film.height as u64,
+            time_budget: self.time_budget.filter(|_| sample_limit != self.samples_per_pixel),
+            ..Default::default()
+        };
+        let mut stopped = None;
+
+        for pass in 0..passes {
+            let first_sample = start_sample + pass * samples_per_pass;
+
+            for row in 0..film.height {
+                job_tx.send(RowJob {
+                    row,
+                    first_sample,
+                    samples: samples_per_pass.min(sample_limit - first_sample),
+                    aovs: film.aovs[row * width..(row + 1) * width].to_vec(),
+                    variance: film.variance[row * width..(row + 1) * width].to_vec(),
+                }).unwrap();
+            }
+
+            // Rows are merged in order, whichever thread finishes first, so floating point sums come out the same on every run
+            let mut finished = BTreeMap::new();
+            let mut next_row = 0;
+
+            for _ in 0..film.height {
+                // Recieve pixel data
+                let result = pixel_rx.recv().unwrap();
+                current.completed += 1;
+                current.samples += result.samples;
+                current.elapsed = start_time.elapsed();
+                progress.update(&current);
+
+                finished.insert(result.id, result);
+                while let Some(result) = finished.remove(&next_row) {
+                    film.add_rows(result.first_row, &result.splats);
+                    film.set_pixel_row(result.id, &result.aovs, &result.variance);
+                    next_row += 1;
+                }
+            }
+
+            // Pixels skipped by a cancelled pass never take those samples, so carrying on after them can't repeat one
+            film.next_sample = (first_sample + samples_per_pass).min(sample_limit) as u64;
+            let keep_going = on_pass(&film);
 
-            // Recieve pixel data
-            let result = pixel_rx.recv().unwrap();
-            print!("\rScanlines Completed: {}/{}", current_complete_count, self.image_height);
-            std::io::stdout().flush().unwrap();
-            current_complete_count += 1;
+            let stop_reason = if cancel.deadline_passed() {
+                Some("the time budget ran out")
+            } else if cancel.is_cancelled() {
+                Some("the render was cancelled")
+            } else if self.sample_budget.is_some_and(|budget| film.total_samples() >= budget) {
+                Some("the sample budget was used up")
+            } else {
+                None
+            };
 
-            // Append it to the 2D array used to represent the image
-            for colour in result.scanline {
-                data[result.id].push(colour);
+            if let Some(reason) = stop_reason {
+                let of_passes = if current.time_budget.is_some() { String::new() } else { format!(" of {}", passes) };
+                stopped = Some(format!("Stopped after {}{} passes because {}", pass + 1, of_passes, reason));
+                break;
+            }
+            if !keep_going {
+                stopped = Some(format!("Stopped after {} of {} passes", pass + 1, passes));
+                break;
             }
         }
 
-        let end_time = start_time.elapsed();
-        let mut final_data: Vec<u32> = vec![];
+        current.elapsed = start_time.elapsed();
+        progress.finish(&current);
+        if let Some(message) = stopped {
+            println!("{}", message);
+        }
 
-        // Convert 2D array into 1D array
-        for mut row in data {
-            final_data.append(&mut row);
+        // Closing the job channel lets the threads finish
+        drop(job_tx);
+        let mut render_stats = RenderStats::default();
+        for handle in handles {
+            let (busy, counters) = handle.join().unwrap();
+            render_stats.counters.add(&counters);
+            render_stats.thread_busy.push(busy);
         }
 
-        println!("\nRendered in {} seconds", end_time.as_secs());
-        print!("\x1B[0m");
+        let end_time = start_time.elapsed();
+        render_stats.render_time = end_time;
+        film.stats.merge(&render_stats);
 
-        save::save_u32_vector_to_png("render.png", self.image_width as u32, self.image_height as u32, &final_data).unwrap();
-        println!("Saved as 'render.png'");
+        println!("Rendered in {} seconds", end_time.as_secs());
+        if self.adaptive.is_some() {
+            println!("Average samples per pixel: {:.1}", film.average_samples());
+        }
+        print!("\x1B[0m");
 
-        show_image(&mut final_data, self.image_width as usize, self.image_height as usize);
+        return film;
     }
 
-    fn ray_colour(&self, r: &Ray, depth_limit: u64, world: &mut dyn Hittable) -> Colour {
-        if depth_limit <= 0 { return Colour::new() }
+    /// Takes a job's samples in every pixel of its row
+    ///
+    /// ## Arguments
+    /// - `job` Which row and samples to take, along with the row's passes and statistics so far
+    /// - `world` Objects in the scene
+    /// - `cancel` Leaves the rest of the row out once the render is cancelled
+    fn render_row(&self, job: RowJob, world: &dyn Hittable, cancel: &CancelToken) -> ScanlineResult {
+        let j = job.row as i64;
+        let reach = self.filter.radius.ceil() as i64;
+        let first_row = (j - reach).max(0);
+        let last_row = (j + reach).min(self.image_height - 1);
 
-        let mut record = HitRecord::new();
+        let mut splats = vec![FilmPixel::default(); (self.image_width * (last_row - first_row + 1)) as usize];
+        let mut aovs = job.aovs;
+        let mut variance = job.variance;
+        let mut samples_taken = 0;
+
+        for (i, (aov, stats)) in aovs.iter_mut().zip(variance.iter_mut()).enumerate() {
+            if cancel.is_cancelled() {
+                break;
+            }
 
-        if world.hit(r, Interval::from(0.001, f64::INFINITY), &mut record) {
-           let mut scattered = Ray::new();
-           let mut attenuation = Colour::new();
-           let mut material = record.material.clone();
+            for s in job.first_sample..job.first_sample + job.samples {
+                if self.pixel_converged(stats) {
+                    break;
+                }
 
-           if material.scatter(r, &mut record, &mut attenuation, &mut scattered) { 
-               return attenuation * self.ray_colour(&scattered, depth_limit - 1, world);
-           } 
+                random::start_pixel_sample(i as u32, j as u32, s as u32);
+                let (dx, dy) = self.pixel_sample_offset();
+                let (x, y) = (i as f64 + dx, j as f64 + dy);
 
-           return Colour::new();
+                let r = self.get_ray(x, y);
+                let sample = self.trace_sample(&r, world);
+                self.splat(&mut splats, first_row, x, y, &sample);
+                aov.add_sample(&sample);
+                stats.add_sample(sample.beauty);
+                samples_taken += 1;
+            }
         }
+
+        return ScanlineResult { id: job.row, first_row: first_row as usize, splats, aovs, variance, samples: samples_taken };
+    }
+
+    /// Finds where a ray next interacts with the scene, either by hitting a surface or scattering in the atmosphere
+    ///
+    /// ## Arguments
+    /// - `r` The ray to follow
+    /// - `world` Objects in the scene
+    /// - `record` Where the interaction is written, a scatter in the atmosphere uses its phase function as the material
+    fn intersect(&self, r: &Ray, world: &dyn Hittable, record: &mut HitRecord) -> bool {
+        let hit_anything = world.hit(r, Interval::from(0.001, f64::INFINITY), record);
+
+        // Scatter in the scene wide fog if it happens before the ray reaches an object
+        if let Some(atmosphere) = self.atmosphere {
+            let t_max = if hit_anything { record.t } else { f64::INFINITY };
+
+            if let Some(scatter_t) = atmosphere.sample_scatter_t(r, t_max) {
+                *record = HitRecord::new();
+                record.t = scatter_t;
+                record.point = r.at(scatter_t);
+                record.front_face = true;
+                record.material = Box::new(atmosphere.phase_function);
+                return true;
+            }
+        }
+
+        return hit_anything;
+    }
+
+    /// Colour of the sky in the direction of a ray
+    fn background(&self, r: &Ray) -> Colour {
         let unit_dir = r.direction.unit();
         let a = 0.5 * (unit_dir.y + 1.0);
 
         return (1.0-a) * Colour::from(1.0, 1.0, 1.0) + a * Colour::from(0.5, 0.7, 1.0);
     }
 
-    fn get_ray(&self, i: i64, j: i64) -> Ray {
-        let pixel_center = self.pixel00_loc + (i * self.pixel_delta_u) + (j * self.pixel_delta_v);
-        let pixel_sample = pixel_center + self.pixel_sample_square();
+    fn ray_colour(&self, r: &Ray, depth_limit: u64, world: &dyn Hittable) -> Colour {
+        if depth_limit == 0 { return Colour::new() }
+        stats::count_secondary_ray();
+
+        let mut record = HitRecord::new();
+        if !self.intersect(r, world, &mut record) {
+            return self.background(r);
+        }
+
+        let mut scattered = Ray::new();
+        let mut attenuation = Colour::new();
+        let mut material = record.material.clone();
+        let emitted = material.emitted(record.u, record.v, record.point);
+
+        if material.scatter(r, &record, &mut attenuation, &mut scattered) { 
+            return emitted + attenuation * self.ray_colour(&scattered, depth_limit - 1, world);
+        } 
+
+        return emitted;
+    }
+
+    /// Finds the light arriving along a ray after a bounce, split into the part emitted where the ray lands and the total
+    fn incoming_light(&self, r: &Ray, depth_limit: u64, world: &dyn Hittable) -> (Colour, Colour) {
+        if depth_limit == 0 { return (Colour::new(), Colour::new()) }
+        stats::count_secondary_ray();
+
+        let mut record = HitRecord::new();
+        if !self.intersect(r, world, &mut record) {
+            let background = self.background(r);
+            return (background, background);
+        }
+
+        let mut scattered = Ray::new();
+        let mut attenuation = Colour::new();
+        let mut material = record.material.clone();
+        let emitted = material.emitted(record.u, record.v, record.point);
+
+        if material.scatter(r, &record, &mut attenuation, &mut scattered) {
+            return (emitted, emitted + attenuation * self.ray_colour(&scattered, depth_limit - 1, world));
+        }
+
+        return (emitted, emitted);
+    }
+
+    /// Traces a camera ray, filling in the beauty colour along with every render pass.
+    /// The light at the first hit is split by the kind of bounce so the lighting passes add up to the beauty colour
+    ///
+    /// ## Arguments
+    /// - `r` Ray leaving the camera
+    /// - `world` Objects in the scene
+    fn trace_sample(&self, r: &Ray, world: &dyn Hittable) -> PathSample {
+        let mut sample = PathSample::default();
+        if self.depth_limit == 0 { return sample }
+        stats::count_primary_ray();
+
+        let mut record = HitRecord::new();
+        if !self.intersect(r, world, &mut record) {
+            if !self.transparent_background {
+                sample.beauty = self.background(r);
+                sample.emission = sample.beauty;
+            }
+            return sample;
+        }
+
+        let mut scattered = Ray::new();
+        let mut attenuation = Colour::new();
+        let mut material = record.material.clone();
+
+        sample.albedo = material.albedo(record.u, record.v, record.point);
+        sample.normal = record.normal;
+        sample.depth = record.t * r.direction.length();
+        sample.object_id = record.object_id;
+        sample.material_id = aov::material_id(material.as_ref(), &record);
+        sample.alpha = 1.0;
+        sample.emission = material.emitted(record.u, record.v, record.point);
+        sample.beauty = sample.emission;
+
+        if material.scatter(r, &record, &mut attenuation, &mut scattered) {
+            let (direct, total) = self.incoming_light(&scattered, self.depth_limit - 1, world);
+
+            if material.is_specular() {
+                sample.specular = attenuation * total;
+            } else {
+                sample.direct_diffuse = attenuation * direct;
+                sample.indirect_diffuse = attenuation * (total - direct);
+            }
+            sample.beauty += attenuation * total;
+        }
+
+        return sample;
+    }
+
+    /// Returns whether an adaptively sampled pixel has had enough samples, always false without adaptive sampling
+    fn pixel_converged(&self, stats: &PixelVariance) -> bool {
+        let Some(adaptive) = self.adaptive else { return false };
+        let samples = stats.samples as i64;
+
+        return samples >= adaptive.min_samples && samples % ADAPTIVE_BATCH == 0 && stats.error() < adaptive.threshold;
+    }
+
+    /// Adds a sample to every pixel within the reconstruction filter's radius of where it was taken
+    ///
+    /// ## Arguments
+    /// - `pixels` Block of whole rows of the film
+    /// - `first_row` Row of the image the block starts at
+    /// - `x` Horizontal position of the sample on the film in pixels
+    /// - `y` Vertical position of the sample on the film in pixels
+    /// - `sample` What the sample found
+    fn splat(&self, pixels: &mut [FilmPixel], first_row: i64, x: f64, y: f64, sample: &PathSample) {
+        let radius = self.filter.radius;
+        let rows = pixels.len() as i64 / self.image_width;
+
+        let min_x = ((x - radius).ceil() as i64).max(0);
+        let max_x = ((x + radius).floor() as i64).min(self.image_width - 1);
+        let min_y = ((y - radius).ceil() as i64).max(first_row);
+        let max_y = ((y + radius).floor() as i64).min(first_row + rows - 1);
+
+        for py in min_y..=max_y {
+            for px in min_x..=max_x {
+                let weight = self.filter.weight(px as f64 - x, py as f64 - y);
+                if weight != 0.0 {
+                    pixels[((py - first_row) * self.image_width + px) as usize].add_sample(sample.beauty, sample.alpha, weight);
+                }
+            }
+        }
+    }
+
+    /// Creates a ray through a point on the film
+    ///
+    /// ## Arguments
+    /// - `x` Horizontal position on the film in pixels, whole numbers are the centres of pixels
+    /// - `y` Vertical position on the film in pixels
+    fn get_ray(&self, x: f64, y: f64) -> Ray {
+        let pixel_sample = self.pixel00_loc + (x * self.pixel_delta_u) + (y * self.pixel_delta_v);
 
         let ray_origin: Vector3;
 
@@ -222,15 +877,16 @@ impl Camera {
         }
 
         let ray_dir = pixel_sample - ray_origin;
+        let ray_time = self.shutter_open + (self.shutter_close - self.shutter_open) * random_f64();
 
-        return Ray::from(ray_origin, ray_dir); 
+        return Ray::with_time(ray_origin, ray_dir, ray_time); 
     }
 
-    fn pixel_sample_square(&self) -> Vector3 {
-        let px = -0.5 + random_f64();
-        let py = -0.5 + random_f64();
+    /// Random offset from a pixel's centre, anywhere within the pixel
+    fn pixel_sample_offset(&self) -> (f64, f64) {
+        let (px, py) = random_2d();
 
-        return (px * self.pixel_delta_u) + (py * self.pixel_delta_v);
+        return (px - 0.5, py - 0.5);
     }
 
     fn defocus_disk_sample(&self) -> Vector3 {
@@ -238,3 +894,172 @@ impl Camera {
         return self.center + (p.x * self.defocus_disk_u) + (p.y * self.defocus_disk_v);
     }
 }
+
+/// Rotates a vector around an axis using Rodrigues' formula
+///
+/// ## Arguments
+/// - `v` Vector to rotate
+/// - `axis` Unit vector to rotate around
+/// - `angle` Angle in radians, anticlockwise when looking down the axis
+fn rotate_about(v: Vector3, axis: Vector3, angle: f64) -> Vector3 {
+    let (sin, cos) = angle.sin_cos();
+    return v * cos + cross_product(axis, v) * sin + axis * dot_product(axis, v) * (1.0 - cos);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        material::{Dieletric, Lambertian, Metal},
+        quad::Plane,
+        sphere::Sphere,
+    };
+
+    /// A ground plane with a diffuse, a glass and a rough metal ball, so every material draws random numbers
+    fn world() -> HittableList {
+        let mut world = HittableList::new();
+        world.add(Box::new(Plane::new(Vector3::new(), Vector3::from(0.0, 1.0, 0.0), Box::new(Lambertian::new(Colour::from(0.5, 0.5, 0.5))))));
+        world.add(Box::new(Sphere::new(Vector3::from(-1.1, 0.5, 0.0), 0.5, Box::new(Lambertian::new(Colour::from(0.7, 0.2, 0.1))))));
+        world.add(Box::new(Sphere::new(Vector3::from(0.0, 0.5, 0.0), 0.5, Box::new(Dieletric::new(1.5)))));
+        world.add(Box::new(Sphere::new(Vector3::from(1.1, 0.5, 0.0), 0.5, Box::new(Metal::new(Colour::from(0.8, 0.8, 0.8), 0.3)))));
+        world.assign_object_ids(&mut 1);
+        return world;
+    }
+
+    fn camera(threads: usize) -> Camera {
+        return Camera {
+            aspect_ratio: 2.0,
+            image_width: 12,
+            samples_per_pixel: 8,
+            depth_limit: 8,
+            vfov: 40.0,
+            look_from: Vector3::from(0.0, 1.0, 4.0),
+            look_at: Vector3::from(0.0, 0.5, 0.0),
+            defocus_angle: 1.0,
+            focus_dist: 4.0,
+            sampler: SamplerKind::Stratified,
+            seed: Some(1234),
+            threads: Some(threads),
+            ..Default::default()
+        };
+    }
+
+    /// Every number stored in the film's pixels as bits, so renders can be compared exactly
+    fn pixel_bits(film: &Film) -> Vec<[u32; 6]> {
+        let mut bits = vec![];
+        for y in 0..film.height {
+            for x in 0..film.width {
+                let p = film.pixel(x, y);
+                bits.push([p.r.to_bits(), p.g.to_bits(), p.b.to_bits(), p.a.to_bits(), p.weight.to_bits(), p.samples]);
+            }
+        }
+        return bits;
+    }
+
+    /// Statistics of a pixel whose samples alternate between two luminances
+    fn pixel_samples(samples: u32, low: f64, high: f64) -> PixelVariance {
+        let mut stats = PixelVariance::default();
+        for i in 0..samples {
+            let luminance = if i % 2 == 0 { low } else { high };
+            stats.add_sample(Colour::from(luminance, luminance, luminance));
+        }
+        return stats;
+    }
+
+    fn adaptive_camera() -> Camera {
+        return Camera {
+            adaptive: Some(AdaptiveSampling { min_samples: 16, threshold: 0.01 }),
+            ..Default::default()
+        };
+    }
+
+    #[test]
+    fn pixels_never_converge_without_adaptive_sampling() {
+        let cam = Camera::default();
+        assert!(!cam.pixel_converged(&pixel_samples(64, 0.5, 0.5)));
+    }
+
+    #[test]
+    fn smooth_pixels_converge_after_the_minimum_samples() {
+        let cam = adaptive_camera();
+
+        assert!(!cam.pixel_converged(&pixel_samples(8, 0.5, 0.5)), "below the minimum");
+        assert!(cam.pixel_converged(&pixel_samples(16, 0.5, 0.5)));
+        assert!(cam.pixel_converged(&pixel_samples(24, 0.5, 0.5)));
+    }
+
+    #[test]
+    fn pixels_only_converge_at_the_end_of_a_batch() {
+        let cam = adaptive_camera();
+
+        assert!(!cam.pixel_converged(&pixel_samples(17, 0.5, 0.5)));
+        assert!(!cam.pixel_converged(&pixel_samples(23, 0.5, 0.5)));
+    }
+
+    #[test]
+    fn noisy_pixels_keep_sampling() {
+        let cam = adaptive_camera();
+
+        assert!(!cam.pixel_converged(&pixel_samples(16, 0.0, 1.0)));
+        assert!(!cam.pixel_converged(&pixel_samples(256, 0.0, 1.0)));
+        // Enough samples bring the error of the same noise under the threshold
+        assert!(cam.pixel_converged(&pixel_samples(8192, 0.0, 1.0)));
+    }
+
+    #[test]
+    fn smooth_sky_stops_early_with_adaptive_sampling() {
+        let mut cam = camera(2);
+        cam.samples_per_pixel = 256;
+        cam.defocus_angle = 0.0;
+        cam.adaptive = Some(AdaptiveSampling { min_samples: 16, threshold: 0.01 });
+        let film = cam.render_film(&Arc::new(HittableList::new()));
+
+        assert_eq!(film.next_sample, 256);
+        assert!(film.variance.iter().all(|stats| stats.samples == 16), "every pixel of the sky should stop at the minimum");
+    }
+
+    #[test]
+    fn inspecting_a_pixel_always_gives_the_same_hit() {
+        let world = world();
+        let mut cam = camera(1);
+        cam.defocus_angle = 10.0;
+        cam.shutter_close = 1.0;
+
+        let first = cam.inspect(&world, 6.0, 3.0).expect("the middle of the image shows the glass ball");
+        for _ in 0..16 {
+            let again = cam.inspect(&world, 6.0, 3.0).unwrap();
+            assert_eq!(again.object_id, first.object_id);
+            assert_eq!(again.distance.to_bits(), first.distance.to_bits());
+        }
+    }
+
+    #[test]
+    fn renders_with_the_same_seed_are_identical() {
+        let world = Arc::new(world());
+        let first = camera(2).render_film(&world);
+        let second = camera(2).render_film(&world);
+
+        assert_eq!(first.seed, 1234);
+        assert_eq!(pixel_bits(&first), pixel_bits(&second));
+        assert!(pixel_bits(&first).iter().any(|p| p[0] != 0), "the render should not be black");
+    }
+
+    #[test]
+    fn renders_do_not_depend_on_the_thread_count() {
+        let world = Arc::new(world());
+        let single = pixel_bits(&camera(1).render_film(&world));
+
+        for threads in [3, 8] {
+            assert_eq!(pixel_bits(&camera(threads).render_film(&world)), single, "{} threads", threads);
+        }
+    }
+
+    #[test]
+    fn different_seeds_give_different_noise() {
+        let world = Arc::new(world());
+        let mut other = camera(2);
+        other.seed = Some(4321);
+
+        assert_ne!(pixel_bits(&camera(2).render_film(&world)), pixel_bits(&other.render_film(&world)));
+    }
+}
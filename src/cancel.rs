@@ -0,0 +1,95 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Set by the Ctrl+C handler, every token treats it as a request to stop
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Asks a render to stop early, keeping the samples taken so far.
+/// Clones share the same flag so one can be handed to every thread
+#[derive(Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+    /// Time after which the token counts as cancelled on its own
+    deadline: Option<Instant>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    /// Returns a token sharing this one's flag which also cancels itself once a deadline passes
+    ///
+    /// ## Arguments
+    /// - `deadline` When to stop, the earlier deadline wins if this token already has one
+    pub fn with_deadline(&self, deadline: Instant) -> Self {
+        return Self {
+            cancelled: self.cancelled.clone(),
+            deadline: Some(self.deadline.map_or(deadline, |d| d.min(deadline))),
+        };
+    }
+
+    /// Asks everything holding a clone of this token to stop
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns whether the render should stop, because it was cancelled, Ctrl+C was pressed or the deadline has passed
+    pub fn is_cancelled(&self) -> bool {
+        return self.cancelled.load(Ordering::Relaxed) || interrupted() || self.deadline_passed();
+    }
+
+    /// Returns whether the token has a deadline and it has passed
+    pub fn deadline_passed(&self) -> bool {
+        return self.deadline.is_some_and(|deadline| Instant::now() >= deadline);
+    }
+}
+
+/// Returns whether Ctrl+C has been pressed while the handler was installed
+pub fn interrupted() -> bool {
+    return INTERRUPTED.load(Ordering::Relaxed);
+}
+
+#[cfg(unix)]
+extern "C" fn handle_interrupt(_signal: libc::c_int) {
+    // A second Ctrl+C means the user doesn't want to wait for the partial image to be saved
+    if INTERRUPTED.swap(true, Ordering::Relaxed) {
+        unsafe { libc::_exit(130) };
+    }
+}
+
+/// Keeps Ctrl+C stopping renders gracefully for as long as it is alive, afterwards Ctrl+C kills the process again
+pub struct InterruptHandler {
+    _private: (),
+}
+
+/// Makes Ctrl+C stop renders gracefully instead of killing the process, so the partial image is still saved.
+/// Pressing it a second time exits straight away. Only hold the returned handler around rendering,
+/// since blocking calls such as reading stdin or waiting for a connection carry on after the signal
+#[cfg(unix)]
+pub fn catch_interrupts() -> InterruptHandler {
+    INTERRUPTED.store(false, Ordering::Relaxed);
+
+    let handler = handle_interrupt as extern "C" fn(libc::c_int);
+    unsafe {
+        libc::signal(libc::SIGINT, handler as libc::sighandler_t);
+    }
+
+    return InterruptHandler { _private: () };
+}
+
+/// Ctrl+C keeps its default behaviour where there are no POSIX signals
+#[cfg(not(unix))]
+pub fn catch_interrupts() -> InterruptHandler {
+    return InterruptHandler { _private: () };
+}
+
+impl Drop for InterruptHandler {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        unsafe {
+            libc::signal(libc::SIGINT, libc::SIG_DFL);
+        }
+    }
+}
@@ -0,0 +1,180 @@
+use std::f64::consts::PI;
+
+use crate::{
+    aabb::Aabb,
+    hittable::{spans_from_roots, HitRecord, HitSpan, Hittable},
+    interval::Interval,
+    material::Material,
+    onb::Onb,
+    ray::Ray,
+    solver::solve_quadratic,
+    vector3::{dot_product, Vector3},
+};
+
+/// A cylinder with hemispherical ends, made of every point within `radius` of the segment from `a` to `b`.
+/// U goes around the segment and V goes from the tip of the `a` end to the tip of the `b` end
+#[derive(Clone)]
+pub struct Capsule {
+    pub a: Vector3,
+    pub b: Vector3,
+    pub radius: f64,
+    pub material: Box<dyn Material>,
+    basis: Onb,
+}
+
+impl Capsule {
+    /// Creates a capsule
+    ///
+    /// ## Arguments
+    /// - `a` Center of the first end
+    /// - `b` Center of the second end
+    /// - `radius` Radius of the capsule
+    /// - `material` Material of the capsule
+    pub fn new(a: Vector3, b: Vector3, radius: f64, material: Box<dyn Material>) -> Self {
+        // When both ends meet the capsule is a sphere, any axis will do but `b - a` has no direction
+        let axis = if (b - a).length_squared() > 0.0 { b - a } else { Vector3::from(0.0, 1.0, 0.0) };
+
+        Self {
+            a,
+            b,
+            radius,
+            material,
+            basis: Onb::from_w(axis),
+        }
+    }
+
+    /// Returns every t value where the ray crosses the surface of the capsule in ascending order
+    pub fn intersections(&self, ray: &Ray) -> Vec<f64> {
+        let length = (self.b - self.a).length();
+        let o = self.basis.world_to_local(ray.origin - self.a);
+        let d = self.basis.world_to_local(ray.direction);
+        let r2 = self.radius * self.radius;
+
+        // Side of the capsule, only valid between the two ends
+        let mut roots: Vec<f64> = solve_quadratic(d.x * d.x + d.y * d.y, 2.0 * (o.x * d.x + o.y * d.y), o.x * o.x + o.y * o.y - r2)
+            .into_iter()
+            .filter(|t| (0.0..=length).contains(&(o.z + t * d.z)))
+            .collect();
+
+        // Each hemisphere, only valid beyond its end of the segment
+        for (center_z, outside) in [(0.0, -1.0), (length, 1.0)] {
+            let oc = o - Vector3::from(0.0, 0.0, center_z);
+            for t in solve_quadratic(d.length_squared(), 2.0 * dot_product(oc, d), oc.length_squared() - r2) {
+                if (oc.z + t * d.z) * outside >= 0.0 {
+                    roots.push(t);
+                }
+            }
+        }
+
+        roots.sort_by(|a, b| a.total_cmp(b));
+
+        // A ray crossing where the side meets a hemisphere finds the same point in both
+        roots.dedup_by(|a, b| (*a - *b).abs() < 1e-9);
+        return roots;
+    }
+
+    /// Fills in the hit record for a hit at `t`
+    pub fn fill_record(&self, ray: &Ray, t: f64, rec: &mut HitRecord) {
+        rec.t = t;
+        rec.point = ray.at(t);
+
+        let length = (self.b - self.a).length();
+        let p = self.basis.world_to_local(rec.point - self.a);
+        let closest = Vector3::from(0.0, 0.0, p.z.clamp(0.0, length));
+        let outward_normal = self.basis.local(p - closest) / self.radius;
+
+        rec.u = (p.y.atan2(p.x) + PI) / (2.0 * PI);
+        rec.v = ((p.z + self.radius) / (length + 2.0 * self.radius)).clamp(0.0, 1.0);
+
+        rec.set_face_normal(ray, outward_normal);
+        rec.material = self.material.clone();
+    }
+}
+
+impl Hittable for Capsule {
+    fn hit(&self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        match self.intersections(ray).into_iter().find(|t| ray_t.surrounds(*t)) {
+            Some(t) => {
+                self.fill_record(ray, t, rec);
+                return true;
+            }
+            None => return false,
+        }
+    }
+
+    fn hit_spans(&self, ray: &Ray, spans: &mut Vec<HitSpan>) -> bool {
+        spans.append(&mut spans_from_roots(ray, &self.intersections(ray), |r, t, rec| self.fill_record(r, t, rec)));
+        return true;
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let radius_vec = Vector3::from(self.radius, self.radius, self.radius);
+        return Aabb::from_boxes(
+            Aabb::from_points(self.a - radius_vec, self.a + radius_vec),
+            Aabb::from_points(self.b - radius_vec, self.b + radius_vec),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colour::Colour;
+    use crate::material::Lambertian;
+
+    fn unit_capsule(a: Vector3, b: Vector3) -> Capsule {
+        return Capsule::new(a, b, 1.0, Box::new(Lambertian::new(Colour::from(0.5, 0.5, 0.5))));
+    }
+
+    fn normal_at(capsule: &Capsule, ray: &Ray, t: f64) -> Vector3 {
+        let mut rec = HitRecord::new();
+        capsule.fill_record(ray, t, &mut rec);
+        return rec.normal;
+    }
+
+    #[test]
+    fn ray_along_the_axis_crosses_both_hemispheres() {
+        let capsule = unit_capsule(Vector3::new(), Vector3::from(0.0, 2.0, 0.0));
+        let ray = Ray::from(Vector3::from(0.0, -3.0, 0.0), Vector3::from(0.0, 1.0, 0.0));
+        let roots = capsule.intersections(&ray);
+
+        assert_eq!(roots.len(), 2, "{:?}", roots);
+        assert!((roots[0] - 2.0).abs() < 1e-9);
+        assert!((roots[1] - 6.0).abs() < 1e-9);
+        assert!((normal_at(&capsule, &ray, roots[0]) - Vector3::from(0.0, -1.0, 0.0)).length() < 1e-9);
+    }
+
+    #[test]
+    fn ray_where_the_side_meets_a_hemisphere_has_an_even_number_of_crossings() {
+        // Crosses the axis at the top end, entering through the side and the hemisphere at once
+        let capsule = unit_capsule(Vector3::new(), Vector3::from(0.0, 2.0, 0.0));
+        let ray = Ray::from(Vector3::from(-3.0, 2.0, 0.0), Vector3::from(1.0, 0.0, 0.0));
+        let roots = capsule.intersections(&ray);
+
+        assert_eq!(roots.len(), 2, "{:?}", roots);
+        assert!((roots[0] - 2.0).abs() < 1e-9);
+        assert!((roots[1] - 4.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn side_normal_points_away_from_the_axis() {
+        let capsule = unit_capsule(Vector3::new(), Vector3::from(0.0, 2.0, 0.0));
+        let ray = Ray::from(Vector3::from(-3.0, 1.0, 0.0), Vector3::from(1.0, 0.0, 0.0));
+        let roots = capsule.intersections(&ray);
+
+        assert_eq!(roots.len(), 2, "{:?}", roots);
+        assert!((normal_at(&capsule, &ray, roots[0]) - Vector3::from(-1.0, 0.0, 0.0)).length() < 1e-9);
+    }
+
+    #[test]
+    fn capsule_with_both_ends_together_is_a_sphere() {
+        let capsule = unit_capsule(Vector3::new(), Vector3::new());
+        let ray = Ray::from(Vector3::from(0.0, 0.0, -3.0), Vector3::from(0.0, 0.0, 1.0));
+        let roots = capsule.intersections(&ray);
+
+        assert_eq!(roots.len(), 2, "{:?}", roots);
+        assert!((roots[0] - 2.0).abs() < 1e-9);
+        assert!((roots[1] - 4.0).abs() < 1e-9);
+        assert!((normal_at(&capsule, &ray, roots[0]) - Vector3::from(0.0, 0.0, -1.0)).length() < 1e-9);
+    }
+}
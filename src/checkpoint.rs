@@ -0,0 +1,251 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Write},
+};
+
+use crate::{
+    aov::AovPixel,
+    camera::AdaptiveSampling,
+    film::{Film, FilmPixel, FilmSettings, PixelVariance},
+    filter::{Filter, FilterKind},
+    sampler::SamplerKind,
+    scene::SceneKind,
+};
+
+/// Identifies checkpoint files, the last two bytes are the version of the layout
+const MAGIC: &[u8; 8] = b"RTCKPT02";
+
+/// Writes everything needed to carry on with a render: the HDR film, every pixel's render passes and statistics,
+/// the settings the film was rendered with, and the seed and sample range which give the sampler's state.
+/// The file is written next to its destination then renamed over it, so a crash part way through leaves the last checkpoint intact
+///
+/// ## Arguments
+/// - `path` File to write
+/// - `film` Film to store
+pub fn save_checkpoint(path: &str, film: &Film) -> io::Result<()> {
+    let temporary_path = format!("{}.tmp", path);
+    let mut writer = BufWriter::new(File::create(&temporary_path)?);
+    write_film(&mut writer, film)?;
+
+    writer.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+    return fs::rename(&temporary_path, path);
+}
+
+/// Writes a film in the checkpoint format, which is also how films are sent between distributed render processes
+///
+/// ## Arguments
+/// - `writer` Where to write the film
+/// - `film` Film to write
+pub fn write_film(writer: &mut impl Write, film: &Film) -> io::Result<()> {
+    writer.write_all(MAGIC)?;
+    write_u64(writer, film.width as u64)?;
+    write_u64(writer, film.height as u64)?;
+    writer.write_all(&[film.transparent as u8])?;
+    write_settings(writer, &film.settings)?;
+    write_u64(writer, film.seed)?;
+    write_u64(writer, film.first_sample)?;
+    write_u64(writer, film.next_sample)?;
+
+    for ((pixel, aov), stats) in film.pixels.iter().zip(&film.aovs).zip(&film.variance) {
+        write_f32s(writer, &[pixel.r, pixel.g, pixel.b, pixel.a, pixel.weight])?;
+        write_u32(writer, pixel.samples)?;
+
+        for sum in [aov.albedo, aov.normal, aov.direct_diffuse, aov.indirect_diffuse, aov.specular, aov.emission] {
+            write_f32s(writer, &sum)?;
+        }
+        write_f32s(writer, &[aov.depth, aov.coverage])?;
+        write_u32(writer, aov.object_id)?;
+        write_u32(writer, aov.material_id)?;
+        write_u32(writer, aov.samples)?;
+
+        writer.write_all(&stats.sum.to_le_bytes())?;
+        writer.write_all(&stats.sum_squared.to_le_bytes())?;
+        write_u32(writer, stats.samples)?;
+    }
+
+    return writer.flush();
+}
+
+/// Writes a checkpoint, only warning if it fails since the render can carry on without it
+///
+/// ## Arguments
+/// - `path` File to write
+/// - `film` Film to store
+pub fn try_save_checkpoint(path: &str, film: &Film) {
+    if let Err(err) = save_checkpoint(path, film) {
+        eprintln!("\nWARNING: could not write checkpoint '{}': {}", path, err);
+    }
+}
+
+/// Reads a film written by `save_checkpoint`
+///
+/// ## Arguments
+/// - `path` File to read
+pub fn load_checkpoint(path: &str) -> io::Result<Film> {
+    return read_film(&mut BufReader::new(File::open(path)?));
+}
+
+/// Reads a film written by `write_film`
+///
+/// ## Arguments
+/// - `reader` Where to read the film from
+pub fn read_film(reader: &mut impl Read) -> io::Result<Film> {
+    let mut magic = [0; 8];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not a checkpoint"));
+    }
+
+    let width = read_u64(reader)? as usize;
+    let height = read_u64(reader)? as usize;
+    // A damaged header shouldn't make us try to allocate an absurd film
+    if width.checked_mul(height).is_none_or(|pixels| pixels > 1 << 28) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("invalid size of {}x{}", width, height)));
+    }
+    let mut film = Film::new(width, height);
+
+    let mut transparent = [0; 1];
+    reader.read_exact(&mut transparent)?;
+    film.transparent = transparent[0] != 0;
+    film.settings = read_settings(reader)?;
+    film.seed = read_u64(reader)?;
+    film.first_sample = read_u64(reader)?;
+    film.next_sample = read_u64(reader)?;
+
+    for i in 0..width * height {
+        let [r, g, b, a, weight] = read_f32s(reader)?;
+        film.pixels[i] = FilmPixel { r, g, b, a, weight, samples: read_u32(reader)? };
+
+        let mut aov = AovPixel::default();
+        for sum in [&mut aov.albedo, &mut aov.normal, &mut aov.direct_diffuse, &mut aov.indirect_diffuse, &mut aov.specular, &mut aov.emission] {
+            *sum = read_f32s(reader)?;
+        }
+        [aov.depth, aov.coverage] = read_f32s(reader)?;
+        aov.object_id = read_u32(reader)?;
+        aov.material_id = read_u32(reader)?;
+        aov.samples = read_u32(reader)?;
+        film.aovs[i] = aov;
+
+        film.variance[i] = PixelVariance {
+            sum: f64::from_le_bytes(read_bytes(reader)?),
+            sum_squared: f64::from_le_bytes(read_bytes(reader)?),
+            samples: read_u32(reader)?,
+        };
+    }
+
+    return Ok(film);
+}
+
+fn write_settings(writer: &mut impl Write, settings: &FilmSettings) -> io::Result<()> {
+    let scene = SceneKind::ALL.iter().position(|&kind| kind == settings.scene).unwrap_or(0);
+    writer.write_all(&[scene as u8, settings.volume.is_some() as u8, settings.fog.is_some() as u8])?;
+    write_u64(writer, settings.scene_seed)?;
+    write_u64(writer, settings.volume.unwrap_or(0))?;
+    let (density, height) = settings.fog.unwrap_or((0.0, 0.0));
+    writer.write_all(&density.to_le_bytes())?;
+    writer.write_all(&height.to_le_bytes())?;
+    let sampler = SamplerKind::ALL.iter().position(|&kind| kind == settings.sampler).unwrap_or(0);
+    let filter = FilterKind::ALL.iter().position(|&kind| kind == settings.filter.kind).unwrap_or(0);
+    writer.write_all(&[sampler as u8, filter as u8])?;
+    writer.write_all(&settings.filter.radius.to_le_bytes())?;
+    write_u64(writer, settings.samples_per_pixel)?;
+
+    let adaptive = settings.adaptive.unwrap_or(AdaptiveSampling { min_samples: 0, threshold: 0.0 });
+    writer.write_all(&[settings.adaptive.is_some() as u8])?;
+    write_u64(writer, adaptive.min_samples as u64)?;
+    return writer.write_all(&adaptive.threshold.to_le_bytes());
+}
+
+fn read_settings(reader: &mut impl Read) -> io::Result<FilmSettings> {
+    let invalid = |what: &str| io::Error::new(io::ErrorKind::InvalidData, format!("invalid {}", what));
+    let [scene, has_volume, has_fog] = read_bytes(reader)?;
+    let scene = *SceneKind::ALL.get(scene as usize).ok_or_else(|| invalid("scene"))?;
+    let scene_seed = read_u64(reader)?;
+    let volume = read_u64(reader)?;
+    let density = f64::from_le_bytes(read_bytes(reader)?);
+    let height = f64::from_le_bytes(read_bytes(reader)?);
+
+    let [sampler, filter] = read_bytes(reader)?;
+    let sampler = *SamplerKind::ALL.get(sampler as usize).ok_or_else(|| invalid("sampler"))?;
+    let kind = *FilterKind::ALL.get(filter as usize).ok_or_else(|| invalid("filter"))?;
+    let radius = f64::from_le_bytes(read_bytes(reader)?);
+    let samples_per_pixel = read_u64(reader)?;
+
+    let [adaptive] = read_bytes(reader)?;
+    let min_samples = read_u64(reader)? as i64;
+    let threshold = f64::from_le_bytes(read_bytes(reader)?);
+
+    return Ok(FilmSettings {
+        scene,
+        scene_seed,
+        volume: if has_volume != 0 { Some(volume) } else { None },
+        fog: if has_fog != 0 { Some((density, height)) } else { None },
+        sampler,
+        filter: Filter { kind, radius },
+        samples_per_pixel,
+        adaptive: if adaptive != 0 { Some(AdaptiveSampling { min_samples, threshold }) } else { None },
+    });
+}
+
+fn write_u32(writer: &mut impl Write, value: u32) -> io::Result<()> {
+    return writer.write_all(&value.to_le_bytes());
+}
+
+fn write_u64(writer: &mut impl Write, value: u64) -> io::Result<()> {
+    return writer.write_all(&value.to_le_bytes());
+}
+
+fn write_f32s(writer: &mut impl Write, values: &[f32]) -> io::Result<()> {
+    for value in values {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+    return Ok(());
+}
+
+fn read_bytes<const N: usize>(reader: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    reader.read_exact(&mut bytes)?;
+    return Ok(bytes);
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    return Ok(u32::from_le_bytes(read_bytes(reader)?));
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    return Ok(u64::from_le_bytes(read_bytes(reader)?));
+}
+
+fn read_f32s<const N: usize>(reader: &mut impl Read) -> io::Result<[f32; N]> {
+    let mut values = [0.0; N];
+    for value in values.iter_mut() {
+        *value = f32::from_le_bytes(read_bytes(reader)?);
+    }
+    return Ok(values);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn film_settings_survive_a_round_trip() {
+        let mut film = Film::new(2, 1);
+        film.settings = FilmSettings {
+            scene: SceneKind::Volumes,
+            scene_seed: 42,
+            volume: Some(0xfeed),
+            fog: Some((0.05, 2.0)),
+            sampler: SamplerKind::Sobol,
+            filter: Filter::new(FilterKind::Mitchell),
+            samples_per_pixel: 64,
+            adaptive: Some(AdaptiveSampling { min_samples: 8, threshold: 0.01 }),
+        };
+
+        let mut bytes = Vec::new();
+        write_film(&mut bytes, &film).unwrap();
+        let read = read_film(&mut bytes.as_slice()).unwrap();
+
+        assert_eq!(read.settings, film.settings);
+    }
+}
@@ -0,0 +1,100 @@
+use std::{collections::HashMap, fmt::Display, str::FromStr};
+
+/// Command line arguments in the form `raytracer [command] [--flag value]...`.
+/// A flag without a value is stored as "true"
+pub struct Args {
+    pub command: Option<String>,
+    flags: HashMap<String, String>,
+}
+
+impl Args {
+    /// Parses the arguments after the program name
+    pub fn parse(args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut command = None;
+        let mut flags = HashMap::new();
+        let mut args = args.peekable();
+
+        while let Some(arg) = args.next() {
+            if let Some(name) = arg.strip_prefix("--") {
+                let value = match args.peek() {
+                    Some(next) if !next.starts_with("--") => args.next().unwrap(),
+                    _ => String::from("true"),
+                };
+                flags.insert(name.to_string(), value);
+            } else if command.is_none() {
+                command = Some(arg);
+            } else {
+                return Err(format!("unexpected argument '{}'", arg));
+            }
+        }
+
+        return Ok(Self { command, flags });
+    }
+
+    /// Returns an error naming the first flag which isn't in any of the allowed groups, so typos aren't silently ignored
+    ///
+    /// ## Arguments
+    /// - `allowed` Groups of flag names the command reads, without the leading dashes
+    pub fn expect_flags(&self, allowed: &[&[&str]]) -> Result<(), String> {
+        let mut unknown: Vec<&String> = self.flags.keys().filter(|name| !allowed.iter().any(|group| group.contains(&name.as_str()))).collect();
+        unknown.sort();
+
+        match unknown.first() {
+            Some(name) => {
+                let command = self.command.as_deref().unwrap_or("the interactive render");
+                return Err(format!("unknown flag --{} for {}, see --help", name, command));
+            }
+            None => return Ok(()),
+        }
+    }
+
+    /// Returns whether a flag was given
+    pub fn has(&self, name: &str) -> bool {
+        return self.flags.contains_key(name);
+    }
+
+    /// Returns the raw value of a flag
+    pub fn value(&self, name: &str) -> Option<&str> {
+        return self.flags.get(name).map(|v| v.as_str());
+    }
+
+    /// Parses the value of a flag, or returns `default` if it wasn't given
+    ///
+    /// ## Arguments
+    /// - `name` Name of the flag without the leading dashes
+    /// - `default` Value to use if the flag is missing
+    pub fn get<T: FromStr>(&self, name: &str, default: T) -> Result<T, String>
+    where
+        T::Err: Display,
+    {
+        match self.flags.get(name) {
+            Some(value) => return value.parse::<T>().map_err(|e| format!("invalid value '{}' for --{}: {}", value, name, e)),
+            None => return Ok(default),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Args {
+        return Args::parse(line.split_whitespace().map(String::from)).unwrap();
+    }
+
+    #[test]
+    fn flags_take_the_next_argument_as_their_value() {
+        let args = parse("explore --width 200 --transparent --exposure -1");
+        assert_eq!(args.command.as_deref(), Some("explore"));
+        assert_eq!(args.get("width", 0).unwrap(), 200);
+        assert!(args.has("transparent"));
+        assert_eq!(args.value("exposure"), Some("-1"));
+    }
+
+    #[test]
+    fn unknown_flags_are_rejected() {
+        let args = parse("explore --widht 200");
+        assert!(args.expect_flags(&[&["width", "samples"]]).is_err());
+        assert!(args.expect_flags(&[&["width"], &["widht"]]).is_ok());
+    }
+}
@@ -2,8 +2,12 @@ use crate::{vector3::Vector3, interval::Interval};
 
 pub type Colour = Vector3;
 
-fn linear_to_gamma(linear_component: f64) -> f64 {
-    return linear_component.sqrt();
+/// Applies the sRGB transfer function to a linear value
+pub fn linear_to_gamma(linear_component: f64) -> f64 {
+    if linear_component <= 0.0031308 {
+        return 12.92 * linear_component.max(0.0);
+    }
+    return 1.055 * linear_component.powf(1.0 / 2.4) - 0.055;
 }
 
 impl Colour {
@@ -34,7 +38,7 @@ impl Colour {
     ///
     /// ## Arguments
     /// - `output` A reference to the vector where you want to output the values
-    /// = `samples` How many samples you want in the anti_aliasing
+    /// - `samples` How many samples you want in the anti_aliasing
     pub fn write_colour_pixels(&self, output: &mut Vec<u32>, samples: i64) {
         let (mut r, mut g, mut b) = (self.x , self.y, self.z);
 
@@ -69,4 +73,41 @@ impl Colour {
             z: blue
         }
     }
+
+    /// Approximates the normalised colour of a black body at a temperature
+    ///
+    /// ## Arguments
+    /// - `kelvin` Temperature in Kelvin
+    pub fn from_temperature(kelvin: f64) -> Self {
+        // Curve fit of the black body spectrum by Tanner Helland, valid from 1000K to 40000K
+        let t = kelvin.clamp(1000.0, 40000.0) / 100.0;
+
+        let red = if t <= 66.0 { 255.0 } else { 329.698727446 * (t - 60.0).powf(-0.1332047592) };
+        let green = if t <= 66.0 { 99.4708025861 * t.ln() - 161.1195681661 } else { 288.1221695283 * (t - 60.0).powf(-0.0755148492) };
+        let blue = if t >= 66.0 { 255.0 } else if t <= 19.0 { 0.0 } else { 138.5177312231 * (t - 10.0).ln() - 305.0447927307 };
+
+        let channel = |c: f64| c.clamp(0.0, 255.0) / 255.0;
+        Self {
+            x: channel(red),
+            y: channel(green),
+            z: channel(blue),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn srgb_transfer_is_linear_near_black_and_a_power_curve_above() {
+        assert_eq!(linear_to_gamma(0.0), 0.0);
+        assert_eq!(linear_to_gamma(-0.5), 0.0);
+        assert!((linear_to_gamma(0.002) - 12.92 * 0.002).abs() < 1e-12);
+        assert!((linear_to_gamma(1.0) - 1.0).abs() < 1e-12);
+
+        // Mid grey encodes to the familiar 0.46, and the two pieces meet at the threshold
+        assert!((linear_to_gamma(0.18) - 0.4614).abs() < 1e-3);
+        assert!((linear_to_gamma(0.0031308) - linear_to_gamma(0.0031309)).abs() < 1e-5);
+    }
 }
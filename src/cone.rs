@@ -0,0 +1,192 @@
+use std::f64::consts::PI;
+
+use crate::{
+    aabb::Aabb,
+    hittable::{spans_from_roots, HitRecord, HitSpan, Hittable},
+    interval::Interval,
+    material::Material,
+    onb::Onb,
+    ray::Ray,
+    solver::solve_quadratic,
+    vector3::Vector3,
+};
+
+/// A cone with its base centered on `base` and its tip `height` along `axis`.
+/// U goes around the axis, V goes from the base to the tip on the side and from the center to the rim on the cap
+#[derive(Clone)]
+pub struct Cone {
+    pub base: Vector3,
+    pub axis: Vector3,
+    pub radius: f64,
+    pub height: f64,
+    pub capped: bool,
+    pub material: Box<dyn Material>,
+    basis: Onb,
+}
+
+impl Cone {
+    /// Creates a cone
+    ///
+    /// ## Arguments
+    /// - `base` Center of the base of the cone
+    /// - `axis` Direction from the base to the tip, does not need to be a unit vector
+    /// - `radius` Radius of the base
+    /// - `height` Distance from the base to the tip
+    /// - `capped` Whether the base is closed with a disk, it always is when the cone is used in CSG
+    /// - `material` Material of the cone
+    pub fn new(base: Vector3, axis: Vector3, radius: f64, height: f64, capped: bool, material: Box<dyn Material>) -> Self {
+        Self {
+            base,
+            axis: axis.unit(),
+            radius,
+            height,
+            capped,
+            material,
+            basis: Onb::from_w(axis),
+        }
+    }
+
+    /// Returns every t value where the ray crosses the surface of the cone in ascending order
+    ///
+    /// ## Arguments
+    /// - `ray` The ray to intersect
+    /// - `capped` Whether to close the base, which CSG needs even if the cone is drawn open
+    pub fn intersections(&self, ray: &Ray, capped: bool) -> Vec<f64> {
+        let o = self.basis.world_to_local(ray.origin - self.base);
+        let d = self.basis.world_to_local(ray.direction);
+
+        // The radius shrinks linearly from `radius` at the base to zero at the tip: x² + y² = (r - kz)²
+        let k = self.radius / self.height;
+        let ro = self.radius - k * o.z;
+
+        let a = d.x * d.x + d.y * d.y - k * k * d.z * d.z;
+        let b = 2.0 * (o.x * d.x + o.y * d.y + k * d.z * ro);
+        let c = o.x * o.x + o.y * o.y - ro * ro;
+
+        let mut roots: Vec<f64> = solve_quadratic(a, b, c)
+            .into_iter()
+            .filter(|t| (0.0..=self.height).contains(&(o.z + t * d.z)))
+            .collect();
+
+        if capped && d.z.abs() > 1e-12 {
+            let t = -o.z / d.z;
+            let x = o.x + t * d.x;
+            let y = o.y + t * d.y;
+            if x * x + y * y <= self.radius * self.radius {
+                roots.push(t);
+            }
+        }
+
+        roots.sort_by(|a, b| a.total_cmp(b));
+
+        // A ray through the tip touches both nappes at the same point
+        roots.dedup_by(|a, b| (*a - *b).abs() < 1e-9);
+        return roots;
+    }
+
+    /// Fills in the hit record for a hit at `t`, working out whether the side or the cap was hit from the hit point
+    pub fn fill_record(&self, ray: &Ray, t: f64, capped: bool, rec: &mut HitRecord) {
+        rec.t = t;
+        rec.point = ray.at(t);
+
+        let p = self.basis.world_to_local(rec.point - self.base);
+        let rho = (p.x * p.x + p.y * p.y).sqrt();
+        let phi = p.y.atan2(p.x) + PI;
+        let k = self.radius / self.height;
+
+        // Distance along the slant to the side compared with distance to the base plane
+        let side_error = (rho - (self.radius - k * p.z)).abs() / (1.0 + k * k).sqrt();
+        let cap_error = p.z.abs();
+
+        rec.u = phi / (2.0 * PI);
+        let outward_normal = if capped && cap_error < side_error {
+            rec.v = rho / self.radius;
+            -self.axis
+        } else {
+            rec.v = p.z / self.height;
+            let radial = if rho > 1e-12 { Vector3::from(p.x / rho, p.y / rho, 0.0) } else { Vector3::from(1.0, 0.0, 0.0) };
+            self.basis.local(Vector3::from(radial.x, radial.y, k)).unit()
+        };
+
+        rec.set_face_normal(ray, outward_normal);
+        rec.material = self.material.clone();
+    }
+}
+
+impl Hittable for Cone {
+    fn hit(&self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        match self.intersections(ray, self.capped).into_iter().find(|t| ray_t.surrounds(*t)) {
+            Some(t) => {
+                self.fill_record(ray, t, self.capped, rec);
+                return true;
+            }
+            None => return false,
+        }
+    }
+
+    fn hit_spans(&self, ray: &Ray, spans: &mut Vec<HitSpan>) -> bool {
+        // An open surface has no inside, so CSG always treats the cone as closed
+        spans.append(&mut spans_from_roots(ray, &self.intersections(ray, true), |r, t, rec| self.fill_record(r, t, true, rec)));
+        return true;
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let tip = self.base + self.height * self.axis;
+
+        // The extent of a circle along each axis is radius * sqrt(1 - n²) for that axis
+        let extent = |n: f64| self.radius * (1.0 - n * n).max(0.0).sqrt();
+        let e = Vector3::from(extent(self.axis.x), extent(self.axis.y), extent(self.axis.z));
+
+        return Aabb::from_boxes(Aabb::from_points(self.base - e, self.base + e), Aabb::from_points(tip, tip)).pad();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colour::Colour;
+    use crate::material::Lambertian;
+
+    fn unit_cone() -> Cone {
+        return Cone::new(Vector3::new(), Vector3::from(0.0, 1.0, 0.0), 1.0, 1.0, true, Box::new(Lambertian::new(Colour::from(0.5, 0.5, 0.5))));
+    }
+
+    fn normal_at(cone: &Cone, ray: &Ray, t: f64) -> Vector3 {
+        let mut rec = HitRecord::new();
+        cone.fill_record(ray, t, true, &mut rec);
+        return rec.normal;
+    }
+
+    #[test]
+    fn ray_along_the_axis_crosses_the_cap_and_the_tip() {
+        let cone = unit_cone();
+        let ray = Ray::from(Vector3::from(0.0, -1.0, 0.0), Vector3::from(0.0, 1.0, 0.0));
+        let roots = cone.intersections(&ray, true);
+
+        assert_eq!(roots.len(), 2, "{:?}", roots);
+        assert!((roots[0] - 1.0).abs() < 1e-9);
+        assert!((roots[1] - 2.0).abs() < 1e-9);
+        assert!((normal_at(&cone, &ray, roots[0]) - Vector3::from(0.0, -1.0, 0.0)).length() < 1e-9);
+    }
+
+    #[test]
+    fn side_normal_leans_towards_the_tip() {
+        // Halfway up the side, where the slope is 45 degrees
+        let cone = unit_cone();
+        let ray = Ray::from(Vector3::from(-2.0, 0.5, 0.0), Vector3::from(1.0, 0.0, 0.0));
+        let roots = cone.intersections(&ray, true);
+
+        assert_eq!(roots.len(), 2, "{:?}", roots);
+        assert!((roots[0] - 1.5).abs() < 1e-9);
+        assert!((roots[1] - 2.5).abs() < 1e-9);
+
+        let expected = Vector3::from(-1.0, 1.0, 0.0).unit();
+        assert!((normal_at(&cone, &ray, roots[0]) - expected).length() < 1e-9);
+    }
+
+    #[test]
+    fn ray_above_the_tip_misses() {
+        let ray = Ray::from(Vector3::from(-2.0, 1.5, 0.0), Vector3::from(1.0, 0.0, 0.0));
+        assert!(unit_cone().intersections(&ray, true).is_empty());
+    }
+}
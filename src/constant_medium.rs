@@ -0,0 +1,235 @@
+use crate::{
+    aabb::Aabb,
+    colour::Colour,
+    hittable::{HitRecord, Hittable},
+    interval::Interval,
+    material::{HenyeyGreenstein, Isotropic, Material},
+    random::random_f64,
+    ray::Ray,
+    vector3::Vector3,
+};
+
+/// A volume of fog or smoke with the same density everywhere inside a closed boundary object.
+/// Rays travelling through it scatter at a random distance based on the density
+#[derive(Clone)]
+pub struct ConstantMedium {
+    pub boundary: Box<dyn Hittable>,
+    pub density: f64,
+    pub phase_function: Box<dyn Material>,
+}
+
+impl ConstantMedium {
+    /// Creates a medium which scatters equally in every direction
+    ///
+    /// ## Arguments
+    /// - `boundary` Closed object which contains the medium
+    /// - `density` How thick the medium is
+    /// - `albedo` Colour of the medium
+    pub fn new(boundary: Box<dyn Hittable>, density: f64, albedo: Colour) -> Self {
+        Self {
+            boundary,
+            density,
+            phase_function: Box::new(Isotropic::new(albedo)),
+        }
+    }
+
+    /// Creates a medium which uses the Henyey-Greenstein phase function
+    ///
+    /// ## Arguments
+    /// - `boundary` Closed object which contains the medium
+    /// - `density` How thick the medium is
+    /// - `albedo` Colour of the medium
+    /// - `g` Scattering direction, from -1 (backwards) to 1 (forwards)
+    pub fn with_henyey_greenstein(boundary: Box<dyn Hittable>, density: f64, albedo: Colour, g: f64) -> Self {
+        Self {
+            boundary,
+            density,
+            phase_function: Box::new(HenyeyGreenstein::new(albedo, g)),
+        }
+    }
+}
+
+impl Hittable for ConstantMedium {
+    fn hit(&self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        let mut rec1 = HitRecord::new();
+        let mut rec2 = HitRecord::new();
+
+        // Find where the ray enters and leaves the boundary, this also works if the ray starts inside it
+        if !self.boundary.hit(ray, Interval::UNIVERSE, &mut rec1) {
+            return false;
+        }
+
+        if !self.boundary.hit(ray, Interval::from(rec1.t + 0.0001, f64::INFINITY), &mut rec2) {
+            return false;
+        }
+
+        rec1.t = rec1.t.max(ray_t.min);
+        rec2.t = rec2.t.min(ray_t.max);
+
+        if rec1.t >= rec2.t {
+            return false;
+        }
+
+        rec1.t = rec1.t.max(0.0);
+
+        let ray_length = ray.direction.length();
+        let distance_inside_boundary = (rec2.t - rec1.t) * ray_length;
+        let hit_distance = -random_f64().ln() / self.density;
+
+        if hit_distance > distance_inside_boundary {
+            return false;
+        }
+
+        rec.t = rec1.t + hit_distance / ray_length;
+        rec.point = ray.at(rec.t);
+
+        // The normal and face are arbitrary since the ray scatters inside the volume
+        rec.normal = Vector3::from(1.0, 0.0, 0.0);
+        rec.front_face = true;
+        rec.u = 0.0;
+        rec.v = 0.0;
+        rec.material = self.phase_function.clone();
+
+        return true;
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        return self.boundary.bounding_box();
+    }
+
+    fn memory_size(&self) -> usize {
+        return size_of_val(self) + self.boundary.memory_size();
+    }
+}
+
+/// Homogeneous fog which fills the whole scene up to a height, so rays which leave the scene upwards still reach the sky
+#[derive(Copy, Clone)]
+pub struct Atmosphere {
+    pub density: f64,
+    pub height: f64,
+    pub phase_function: HenyeyGreenstein,
+}
+
+impl Atmosphere {
+    /// Creates an atmosphere
+    ///
+    /// ## Arguments
+    /// - `density` How thick the fog is
+    /// - `height` Y coordinate of the top of the fog
+    /// - `albedo` Colour of the fog
+    /// - `g` Scattering direction, from -1 (backwards) to 1 (forwards)
+    pub fn new(density: f64, height: f64, albedo: Colour, g: f64) -> Self {
+        Self {
+            density,
+            height,
+            phase_function: HenyeyGreenstein::new(albedo, g),
+        }
+    }
+
+    /// Samples where along the ray, as a t value, the ray scatters in the fog before `t_max`.
+    /// Returns None if the ray gets through, a ray which never goes below the top of the fog always does
+    ///
+    /// ## Arguments
+    /// - `ray` The ray travelling through the fog
+    /// - `t_max` Where the ray stops, infinity for rays which miss the scene
+    pub fn sample_scatter_t(&self, ray: &Ray, t_max: f64) -> Option<f64> {
+        // The fog is the half space below `height`, which the ray is inside of for one stretch of t values
+        let mut fog_t = Interval::from(0.0, t_max);
+        if ray.direction.y > 0.0 {
+            fog_t.max = fog_t.max.min((self.height - ray.origin.y) / ray.direction.y);
+        } else if ray.direction.y < 0.0 {
+            fog_t.min = fog_t.min.max((self.height - ray.origin.y) / ray.direction.y);
+        } else if ray.origin.y > self.height {
+            return None;
+        }
+
+        if fog_t.min >= fog_t.max {
+            return None;
+        }
+
+        let scatter_t = fog_t.min - random_f64().ln() / self.density / ray.direction.length();
+        if scatter_t >= fog_t.max {
+            return None;
+        }
+        return Some(scatter_t);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{aa_box::AxisAlignedBox, material::Dieletric};
+
+    const SAMPLES: usize = 20000;
+
+    /// Fraction of light which gets through a distance of a medium according to the Beer-Lambert law
+    fn beer_lambert(density: f64, distance: f64) -> f64 {
+        return (-density * distance).exp();
+    }
+
+    fn fog() -> Atmosphere {
+        return Atmosphere::new(0.5, 2.0, Colour::from(0.8, 0.8, 0.8), 0.0);
+    }
+
+    fn fraction_through(mut scatters: impl FnMut() -> bool) -> f64 {
+        return (0..SAMPLES).filter(|_| !scatters()).count() as f64 / SAMPLES as f64;
+    }
+
+    #[test]
+    fn constant_medium_lets_through_the_beer_lambert_fraction() {
+        let boundary = Box::new(AxisAlignedBox::new(Vector3::from(0.0, -1.0, -1.0), Vector3::from(2.0, 1.0, 1.0), Box::new(Dieletric::new(1.0))));
+        let medium = ConstantMedium::new(boundary, 0.5, Colour::from(0.8, 0.8, 0.8));
+
+        // A direction longer than a unit vector checks the distance is measured in world units rather than t
+        let ray = Ray::from(Vector3::from(-1.0, 0.0, 0.0), Vector3::from(2.0, 0.0, 0.0));
+        let through = fraction_through(|| medium.hit(&ray, Interval::from(0.001, f64::INFINITY), &mut HitRecord::new()));
+
+        assert!((through - beer_lambert(0.5, 2.0)).abs() < 0.02, "{}", through);
+    }
+
+    #[test]
+    fn atmosphere_lets_through_the_beer_lambert_fraction_before_a_hit() {
+        let ray = Ray::from(Vector3::from(0.0, 1.0, 0.0), Vector3::from(0.0, 0.0, 3.0));
+        let through = fraction_through(|| fog().sample_scatter_t(&ray, 1.0).is_some());
+
+        assert!((through - beer_lambert(0.5, 3.0)).abs() < 0.02, "{}", through);
+    }
+
+    #[test]
+    fn rays_which_miss_are_fogged_up_to_the_height() {
+        // Straight up from the ground, the ray is in the fog for 2 units and then goes on to the sky
+        let ray = Ray::from(Vector3::new(), Vector3::from(0.0, 1.0, 0.0));
+        let through = fraction_through(|| fog().sample_scatter_t(&ray, f64::INFINITY).is_some());
+        assert!((through - beer_lambert(0.5, 2.0)).abs() < 0.02, "{}", through);
+
+        for _ in 0..100 {
+            if let Some(t) = fog().sample_scatter_t(&ray, f64::INFINITY) {
+                assert!(ray.at(t).y <= 2.0);
+            }
+        }
+    }
+
+    #[test]
+    fn rays_from_above_are_fogged_once_they_reach_the_height() {
+        let ray = Ray::from(Vector3::from(0.0, 4.0, 0.0), Vector3::from(0.0, -1.0, 0.0));
+        let through = fraction_through(|| match fog().sample_scatter_t(&ray, 6.0) {
+            Some(t) => {
+                assert!((2.0..6.0).contains(&t), "{}", t);
+                true
+            }
+            None => false,
+        });
+
+        assert!((through - beer_lambert(0.5, 4.0)).abs() < 0.02, "{}", through);
+    }
+
+    #[test]
+    fn rays_above_the_fog_never_scatter() {
+        let level = Ray::from(Vector3::from(0.0, 3.0, 0.0), Vector3::from(1.0, 0.0, 0.0));
+        let rising = Ray::from(Vector3::from(0.0, 3.0, 0.0), Vector3::from(1.0, 1.0, 0.0));
+        for _ in 0..100 {
+            assert!(fog().sample_scatter_t(&level, f64::INFINITY).is_none());
+            assert!(fog().sample_scatter_t(&rising, f64::INFINITY).is_none());
+        }
+    }
+}
@@ -0,0 +1,247 @@
+use crate::{
+    aabb::Aabb,
+    hittable::{HitRecord, HitSpan, Hittable},
+    interval::Interval,
+    ray::Ray,
+};
+
+/// Boolean operation used to combine the two objects of a CSG node
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CsgOperation {
+    /// Inside either object
+    Union,
+    /// Inside both objects
+    Intersection,
+    /// Inside the left object but not the right object
+    Difference,
+}
+
+impl CsgOperation {
+    fn inside(&self, in_left: bool, in_right: bool) -> bool {
+        match self {
+            CsgOperation::Union => in_left || in_right,
+            CsgOperation::Intersection => in_left && in_right,
+            CsgOperation::Difference => in_left && !in_right,
+        }
+    }
+}
+
+/// Combines two sorted lists of spans with a boolean operation by sweeping over every boundary in order along the ray
+/// and tracking whether we are inside each object
+///
+/// ## Arguments
+/// - `left` Spans of the left object
+/// - `right` Spans of the right object
+/// - `operation` How to combine the spans
+pub fn combine_spans(left: Vec<HitSpan>, right: Vec<HitSpan>, operation: CsgOperation) -> Vec<HitSpan> {
+    // (record, is_left, is_entering)
+    let mut events: Vec<(HitRecord, bool, bool)> = vec![];
+    for (spans, is_left) in [(left, true), (right, false)] {
+        for span in spans {
+            events.push((span.enter, is_left, true));
+            events.push((span.exit, is_left, false));
+        }
+    }
+    events.sort_by(|a, b| a.0.t.total_cmp(&b.0.t));
+
+    let mut result = vec![];
+    let mut in_left = false;
+    let mut in_right = false;
+    let mut current_enter: Option<HitRecord> = None;
+
+    for (mut record, is_left, is_entering) in events {
+        let was_inside = operation.inside(in_left, in_right);
+        if is_left { in_left = is_entering; } else { in_right = is_entering; }
+        let is_inside = operation.inside(in_left, in_right);
+
+        if was_inside == is_inside {
+            continue;
+        }
+
+        // The surface of a subtracted object faces the other way once it becomes part of the result
+        if operation == CsgOperation::Difference && !is_left {
+            record.front_face = !record.front_face;
+        }
+
+        if is_inside {
+            current_enter = Some(record);
+        } else if let Some(enter) = current_enter.take() {
+            result.push(HitSpan { enter, exit: record });
+        }
+    }
+
+    return result;
+}
+
+/// Constructive solid geometry node which combines two closed objects with a boolean operation.
+/// Both objects must support `Hittable::hit_spans`, open cylinders and cones are closed off with caps
+#[derive(Clone)]
+pub struct Csg {
+    pub operation: CsgOperation,
+    pub left: Box<dyn Hittable>,
+    pub right: Box<dyn Hittable>,
+}
+
+impl Csg {
+    /// Creates a CSG node
+    ///
+    /// ## Arguments
+    /// - `operation` How to combine the objects
+    /// - `left` The first object
+    /// - `right` The second object
+    pub fn new(operation: CsgOperation, left: Box<dyn Hittable>, right: Box<dyn Hittable>) -> Self {
+        Self {
+            operation,
+            left,
+            right,
+        }
+    }
+
+    /// Creates a node containing everything inside either object
+    pub fn union(left: Box<dyn Hittable>, right: Box<dyn Hittable>) -> Self {
+        return Csg::new(CsgOperation::Union, left, right);
+    }
+
+    /// Creates a node containing everything inside both objects
+    pub fn intersection(left: Box<dyn Hittable>, right: Box<dyn Hittable>) -> Self {
+        return Csg::new(CsgOperation::Intersection, left, right);
+    }
+
+    /// Creates a node containing everything inside the left object which isn't inside the right object
+    pub fn difference(left: Box<dyn Hittable>, right: Box<dyn Hittable>) -> Self {
+        return Csg::new(CsgOperation::Difference, left, right);
+    }
+}
+
+impl Hittable for Csg {
+    fn hit(&self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        if !self.bounding_box().hit(ray, ray_t) {
+            return false;
+        }
+
+        let mut spans = vec![];
+        if !self.hit_spans(ray, &mut spans) {
+            return false;
+        }
+
+        for span in spans {
+            for boundary in [span.enter, span.exit] {
+                if ray_t.surrounds(boundary.t) {
+                    *rec = boundary;
+                    return true;
+                }
+            }
+        }
+
+        return false;
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let left = self.left.bounding_box();
+        let right = self.right.bounding_box();
+
+        match self.operation {
+            CsgOperation::Union => return Aabb::from_boxes(left, right),
+            CsgOperation::Intersection => {
+                let overlap = |a: Interval, b: Interval| Interval::from(a.min.max(b.min), a.max.min(b.max));
+                return Aabb::from(overlap(left.x, right.x), overlap(left.y, right.y), overlap(left.z, right.z));
+            }
+            CsgOperation::Difference => return left,
+        }
+    }
+
+    fn hit_spans(&self, ray: &Ray, spans: &mut Vec<HitSpan>) -> bool {
+        let mut left = vec![];
+        let mut right = vec![];
+
+        if !self.left.hit_spans(ray, &mut left) || !self.right.hit_spans(ray, &mut right) {
+            return false;
+        }
+
+        spans.append(&mut combine_spans(left, right, self.operation));
+        return true;
+    }
+
+    fn assign_object_ids(&mut self, next_id: &mut u32) {
+        self.left.assign_object_ids(next_id);
+        self.right.assign_object_ids(next_id);
+    }
+
+    fn memory_size(&self) -> usize {
+        return size_of_val(self) + self.left.memory_size() + self.right.memory_size();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{colour::Colour, cylinder::Cylinder, material::Lambertian, sphere::Sphere, vector3::Vector3};
+
+    fn record(t: f64) -> HitRecord {
+        let mut record = HitRecord::new();
+        record.t = t;
+        record.front_face = true;
+        return record;
+    }
+
+    fn spans(ranges: &[(f64, f64)]) -> Vec<HitSpan> {
+        return ranges.iter().map(|&(enter, exit)| HitSpan { enter: record(enter), exit: record(exit) }).collect();
+    }
+
+    fn ranges(spans: &[HitSpan]) -> Vec<(f64, f64)> {
+        return spans.iter().map(|span| (span.enter.t, span.exit.t)).collect();
+    }
+
+    #[test]
+    fn union_joins_overlapping_spans() {
+        let result = combine_spans(spans(&[(1.0, 3.0)]), spans(&[(2.0, 4.0)]), CsgOperation::Union);
+        assert_eq!(ranges(&result), vec![(1.0, 4.0)]);
+    }
+
+    #[test]
+    fn union_keeps_separate_spans_apart() {
+        let result = combine_spans(spans(&[(1.0, 2.0), (5.0, 6.0)]), spans(&[(3.0, 4.0)]), CsgOperation::Union);
+        assert_eq!(ranges(&result), vec![(1.0, 2.0), (3.0, 4.0), (5.0, 6.0)]);
+    }
+
+    #[test]
+    fn intersection_keeps_the_overlap() {
+        let result = combine_spans(spans(&[(1.0, 3.0)]), spans(&[(2.0, 4.0)]), CsgOperation::Intersection);
+        assert_eq!(ranges(&result), vec![(2.0, 3.0)]);
+
+        let result = combine_spans(spans(&[(1.0, 2.0)]), spans(&[(3.0, 4.0)]), CsgOperation::Intersection);
+        assert!(result.is_empty());
+    }
+
+    #[test]
+    fn difference_cuts_a_hole() {
+        let result = combine_spans(spans(&[(1.0, 4.0)]), spans(&[(2.0, 3.0)]), CsgOperation::Difference);
+        assert_eq!(ranges(&result), vec![(1.0, 2.0), (3.0, 4.0)]);
+
+        // The hole's walls come from the subtracted object, so they face the other way
+        assert!(result[0].enter.front_face);
+        assert!(!result[0].exit.front_face);
+        assert!(!result[1].enter.front_face);
+        assert!(result[1].exit.front_face);
+    }
+
+    #[test]
+    fn difference_trims_an_end() {
+        let result = combine_spans(spans(&[(1.0, 3.0)]), spans(&[(0.0, 2.0)]), CsgOperation::Difference);
+        assert_eq!(ranges(&result), vec![(2.0, 3.0)]);
+    }
+
+    #[test]
+    fn open_cylinder_is_closed_for_csg() {
+        let material = Box::new(Lambertian::new(Colour::from(0.5, 0.5, 0.5)));
+        let sphere = Box::new(Sphere::new(Vector3::new(), 1.0, material.clone()));
+        let cylinder = Box::new(Cylinder::new(Vector3::from(0.0, -2.0, 0.0), Vector3::from(0.0, 1.0, 0.0), 0.5, 4.0, false, material));
+        let node = Csg::intersection(sphere, cylinder);
+
+        // Straight down the axis the ray only meets the sphere, so the cylinder has to have an inside for anything to remain
+        let ray = Ray::from(Vector3::from(0.0, 5.0, 0.0), Vector3::from(0.0, -1.0, 0.0));
+        let mut rec = HitRecord::new();
+        assert!(node.hit(&ray, Interval::from(0.001, f64::INFINITY), &mut rec));
+        assert!((rec.t - 4.0).abs() < 1e-9);
+    }
+}
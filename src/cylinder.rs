@@ -0,0 +1,170 @@
+use std::f64::consts::PI;
+
+use crate::{
+    aabb::Aabb,
+    hittable::{spans_from_roots, HitRecord, HitSpan, Hittable},
+    interval::Interval,
+    material::Material,
+    onb::Onb,
+    ray::Ray,
+    solver::solve_quadratic,
+    vector3::Vector3,
+};
+
+/// A cylinder standing on `base` and extending `height` along `axis`.
+/// U goes around the axis, V goes from the base to the top on the side and from the center to the rim on the caps
+#[derive(Clone)]
+pub struct Cylinder {
+    pub base: Vector3,
+    pub axis: Vector3,
+    pub radius: f64,
+    pub height: f64,
+    pub capped: bool,
+    pub material: Box<dyn Material>,
+    basis: Onb,
+}
+
+impl Cylinder {
+    /// Creates a cylinder
+    ///
+    /// ## Arguments
+    /// - `base` Center of the bottom of the cylinder
+    /// - `axis` Direction the cylinder extends in, does not need to be a unit vector
+    /// - `radius` Radius of the cylinder
+    /// - `height` Length of the cylinder along the axis
+    /// - `capped` Whether the ends are closed with disks, they always are when the cylinder is used in CSG
+    /// - `material` Material of the cylinder
+    pub fn new(base: Vector3, axis: Vector3, radius: f64, height: f64, capped: bool, material: Box<dyn Material>) -> Self {
+        Self {
+            base,
+            axis: axis.unit(),
+            radius,
+            height,
+            capped,
+            material,
+            basis: Onb::from_w(axis),
+        }
+    }
+
+    /// Returns every t value where the ray crosses the surface of the cylinder in ascending order
+    ///
+    /// ## Arguments
+    /// - `ray` The ray to intersect
+    /// - `capped` Whether to close the ends, which CSG needs even if the cylinder is drawn open
+    pub fn intersections(&self, ray: &Ray, capped: bool) -> Vec<f64> {
+        let o = self.basis.world_to_local(ray.origin - self.base);
+        let d = self.basis.world_to_local(ray.direction);
+
+        let a = d.x * d.x + d.y * d.y;
+        let b = 2.0 * (o.x * d.x + o.y * d.y);
+        let c = o.x * o.x + o.y * o.y - self.radius * self.radius;
+
+        let mut roots: Vec<f64> = solve_quadratic(a, b, c)
+            .into_iter()
+            .filter(|t| (0.0..=self.height).contains(&(o.z + t * d.z)))
+            .collect();
+
+        if capped && d.z.abs() > 1e-12 {
+            for cap_z in [0.0, self.height] {
+                let t = (cap_z - o.z) / d.z;
+                let x = o.x + t * d.x;
+                let y = o.y + t * d.y;
+                if x * x + y * y <= self.radius * self.radius {
+                    roots.push(t);
+                }
+            }
+        }
+
+        roots.sort_by(|a, b| a.total_cmp(b));
+
+        // A ray through the rim crosses the side and a cap at the same point, counting it twice would leave an odd number of crossings
+        roots.dedup_by(|a, b| (*a - *b).abs() < 1e-9);
+        return roots;
+    }
+
+    /// Fills in the hit record for a hit at `t`, working out whether the side or a cap was hit from the hit point
+    pub fn fill_record(&self, ray: &Ray, t: f64, capped: bool, rec: &mut HitRecord) {
+        rec.t = t;
+        rec.point = ray.at(t);
+
+        let p = self.basis.world_to_local(rec.point - self.base);
+        let rho = (p.x * p.x + p.y * p.y).sqrt();
+        let phi = p.y.atan2(p.x) + PI;
+
+        let side_error = (rho - self.radius).abs();
+        let cap_error = p.z.abs().min((p.z - self.height).abs());
+
+        let outward_normal = if capped && cap_error < side_error {
+            rec.u = phi / (2.0 * PI);
+            rec.v = rho / self.radius;
+            if p.z < self.height / 2.0 { -self.axis } else { self.axis }
+        } else {
+            rec.u = phi / (2.0 * PI);
+            rec.v = p.z / self.height;
+            self.basis.local(Vector3::from(p.x, p.y, 0.0)) / self.radius
+        };
+
+        rec.set_face_normal(ray, outward_normal);
+        rec.material = self.material.clone();
+    }
+}
+
+impl Hittable for Cylinder {
+    fn hit(&self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        match self.intersections(ray, self.capped).into_iter().find(|t| ray_t.surrounds(*t)) {
+            Some(t) => {
+                self.fill_record(ray, t, self.capped, rec);
+                return true;
+            }
+            None => return false,
+        }
+    }
+
+    fn hit_spans(&self, ray: &Ray, spans: &mut Vec<HitSpan>) -> bool {
+        // An open surface has no inside, so CSG always treats the cylinder as closed
+        spans.append(&mut spans_from_roots(ray, &self.intersections(ray, true), |r, t, rec| self.fill_record(r, t, true, rec)));
+        return true;
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let top = self.base + self.height * self.axis;
+
+        // The extent of a circle along each axis is radius * sqrt(1 - n²) for that axis
+        let extent = |n: f64| self.radius * (1.0 - n * n).max(0.0).sqrt();
+        let e = Vector3::from(extent(self.axis.x), extent(self.axis.y), extent(self.axis.z));
+
+        return Aabb::from_boxes(Aabb::from_points(self.base - e, self.base + e), Aabb::from_points(top - e, top + e)).pad();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colour::Colour;
+    use crate::material::Lambertian;
+
+    fn unit_cylinder() -> Cylinder {
+        return Cylinder::new(Vector3::new(), Vector3::from(0.0, 1.0, 0.0), 1.0, 1.0, true, Box::new(Lambertian::new(Colour::from(0.5, 0.5, 0.5))));
+    }
+
+    #[test]
+    fn ray_through_the_rim_has_an_even_number_of_crossings() {
+        // Enters exactly where the side meets the top cap and leaves through the middle of the bottom cap
+        let ray = Ray::from(Vector3::from(-2.0, 2.0, 0.0), Vector3::from(1.0, -1.0, 0.0));
+        let roots = unit_cylinder().intersections(&ray, true);
+
+        assert_eq!(roots.len(), 2, "{:?}", roots);
+        assert!((roots[0] - 1.0).abs() < 1e-9);
+        assert!((roots[1] - 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn ray_along_the_axis_crosses_both_caps() {
+        let ray = Ray::from(Vector3::from(0.0, -1.0, 0.0), Vector3::from(0.0, 1.0, 0.0));
+        let roots = unit_cylinder().intersections(&ray, true);
+
+        assert_eq!(roots.len(), 2, "{:?}", roots);
+        assert!((roots[0] - 1.0).abs() < 1e-9);
+        assert!((roots[1] - 2.0).abs() < 1e-9);
+    }
+}
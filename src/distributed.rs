@@ -0,0 +1,426 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    sync::{mpsc::{self, Sender}, Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    cancel,
+    checkpoint,
+    cli::Args,
+    film::Film,
+    progress::{Progress, ProgressReporter},
+};
+
+/// Samples for a worker to take in every pixel, the scene and seed are the same for every chunk of a render
+#[derive(Copy, Clone, Debug)]
+pub struct SampleChunk {
+    pub first_sample: u64,
+    pub samples: u64,
+}
+
+/// Splits a render's samples into chunks of at most `chunk_size`
+///
+/// ## Arguments
+/// - `samples_per_pixel` Samples every pixel takes in the whole render
+/// - `chunk_size` Most samples in one chunk
+pub fn split_samples(samples_per_pixel: u64, chunk_size: u64) -> Vec<SampleChunk> {
+    let chunk_size = chunk_size.max(1);
+
+    return (0..samples_per_pixel)
+        .step_by(chunk_size as usize)
+        .map(|first_sample| SampleChunk { first_sample, samples: chunk_size.min(samples_per_pixel - first_sample) })
+        .collect();
+}
+
+/// Most arguments a job can have, so a garbled job can't make a worker allocate without limit
+const MAX_JOB_ARGS: usize = 256;
+/// Longest argument of a job in bytes
+const MAX_JOB_ARG_LEN: usize = 4096;
+
+/// Sends a job as its number of arguments followed by each argument's length and bytes, so arguments can hold spaces
+///
+/// ## Arguments
+/// - `writer` Where to send the job
+/// - `job` Command line arguments of the job
+fn write_job(writer: &mut impl Write, job: &[String]) -> io::Result<()> {
+    writer.write_all(&(job.len() as u32).to_le_bytes())?;
+    for arg in job {
+        writer.write_all(&(arg.len() as u32).to_le_bytes())?;
+        writer.write_all(arg.as_bytes())?;
+    }
+    return Ok(());
+}
+
+/// Reads a job sent by `write_job`, returning None if the connection was closed before another job started
+fn read_job(reader: &mut impl BufRead) -> io::Result<Option<Vec<String>>> {
+    if reader.fill_buf()?.is_empty() {
+        return Ok(None);
+    }
+
+    let invalid = |what: String| io::Error::new(io::ErrorKind::InvalidData, what);
+    let mut length = [0; 4];
+    reader.read_exact(&mut length)?;
+    let count = u32::from_le_bytes(length) as usize;
+    if count > MAX_JOB_ARGS {
+        return Err(invalid(format!("a job can't have {} arguments", count)));
+    }
+
+    let mut job = Vec::with_capacity(count);
+    for _ in 0..count {
+        reader.read_exact(&mut length)?;
+        let length = u32::from_le_bytes(length) as usize;
+        if length > MAX_JOB_ARG_LEN {
+            return Err(invalid(format!("a job argument can't be {} bytes long", length)));
+        }
+
+        let mut bytes = vec![0; length];
+        reader.read_exact(&mut bytes)?;
+        job.push(String::from_utf8(bytes).map_err(|_| invalid(String::from("a job argument isn't UTF-8")))?);
+    }
+
+    return Ok(Some(job));
+}
+
+/// How often a worker thread waiting for a slow chunk checks whether the render has finished without it
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Chunks still waiting for a worker, shared by every connection
+struct Work {
+    queue: VecDeque<(usize, SampleChunk)>,
+    /// Chunks which haven't come back yet, including ones a worker is busy with
+    remaining: usize,
+    /// Which chunks have come back, so a chunk which was handed out twice is only merged once
+    done: Vec<bool>,
+}
+
+impl Work {
+    /// Takes the next chunk which hasn't come back yet
+    fn next_chunk(&mut self) -> Option<(usize, SampleChunk)> {
+        while let Some((index, chunk)) = self.queue.pop_front() {
+            if !self.done[index] {
+                return Some((index, chunk));
+            }
+        }
+        return None;
+    }
+
+    /// Puts a chunk back in the queue for another worker, unless it is already waiting there or has come back
+    fn requeue(&mut self, index: usize, chunk: SampleChunk) {
+        if !self.done[index] && !self.queue.iter().any(|(queued, _)| *queued == index) {
+            self.queue.push_back((index, chunk));
+        }
+    }
+
+    /// Marks a chunk as finished, returning false if another worker already finished it
+    fn finish(&mut self, index: usize) -> bool {
+        if self.done[index] {
+            return false;
+        }
+        self.done[index] = true;
+        self.remaining -= 1;
+        return true;
+    }
+}
+
+/// Waits for coordinators to connect and renders whatever they ask for, one connection at a time.
+/// Each job is a list of command line flags describing the scene and the chunk of samples to take, see `write_job`.
+/// The answer is a line saying "OK" followed by the film in the checkpoint format, or "ERROR" followed by what went wrong
+///
+/// ## Arguments
+/// - `address` Address to listen on, such as "127.0.0.1:7878". Anyone who can reach it can make the worker render,
+///   so only listen on other interfaces inside a trusted network
+/// - `render` Renders the film for a job
+pub fn serve(address: &str, render: impl Fn(&Args) -> Result<Film, String>) -> io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    let local_address = listener.local_addr()?;
+    println!("Worker listening on {}", local_address);
+    if !local_address.ip().is_loopback() {
+        eprintln!("WARNING: there is no authentication, anyone who can reach {} can send this worker jobs", local_address);
+    }
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                eprintln!("WARNING: could not accept a connection: {}", err);
+                continue;
+            }
+        };
+
+        let peer = stream.peer_addr().map_or(String::from("unknown"), |address| address.to_string());
+        println!("Coordinator connected from {}", peer);
+
+        // A coordinator going away only ends its own connection, the worker keeps waiting for the next one
+        match serve_connection(stream, &render) {
+            Ok(()) => println!("Coordinator {} finished", peer),
+            Err(err) => eprintln!("WARNING: lost connection to {}: {}", peer, err),
+        }
+    }
+
+    return Ok(());
+}
+
+/// Answers jobs from one coordinator until it closes the connection
+fn serve_connection(stream: TcpStream, render: &impl Fn(&Args) -> Result<Film, String>) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+
+    while let Some(job) = read_job(&mut reader)? {
+        match Args::parse(job.into_iter()).and_then(|job| render(&job)) {
+            Ok(film) => {
+                writer.write_all(b"OK\n")?;
+                checkpoint::write_film(&mut writer, &film)?;
+            }
+            Err(err) => writer.write_all(format!("ERROR {}\n", err).as_bytes())?,
+        }
+        writer.flush()?;
+    }
+
+    return Ok(());
+}
+
+/// Hands chunks of a render out to workers and merges the films they send back.
+/// A worker which can't be reached or drops out has its chunk handed to another one, so the render only fails if every worker is lost.
+/// A chunk which takes too long is handed to another worker as well, whichever worker finishes it first is used.
+/// If Ctrl+C is pressed no more chunks are handed out and the chunks which have come back are merged into a partial film
+///
+/// ## Arguments
+/// - `workers` Addresses of the workers, such as "192.168.1.20:7878"
+/// - `job` Flags describing the scene and their values, sent with every chunk
+/// - `chunks` Samples to hand out
+/// - `timeouts` How long to wait for a worker's connection and for its chunks
+/// - `progress` Told every time a chunk comes back, chunks are the units of work
+pub fn render_distributed(workers: &[String], job: &[String], chunks: Vec<SampleChunk>, timeouts: Timeouts, progress: &mut dyn ProgressReporter) -> Result<Film, String> {
+    let start_time = Instant::now();
+    let total = chunks.len();
+    let work = Arc::new(Mutex::new(Work {
+        queue: chunks.into_iter().enumerate().collect(),
+        remaining: total,
+        done: vec![false; total],
+    }));
+    let (result_tx, result_rx) = mpsc::channel::<(usize, Film)>();
+
+    let mut handles = vec![];
+    for address in workers {
+        let address = address.clone();
+        let job = job.to_vec();
+        let work = work.clone();
+        let results = result_tx.clone();
+
+        handles.push(thread::spawn(move || {
+            if let Err(err) = run_worker(&address, &job, &work, &results, timeouts) {
+                eprintln!("\nWARNING: worker {} dropped out: {}", address, err);
+            }
+        }));
+    }
+    // The results stop once every worker thread has finished or given up
+    drop(result_tx);
+
+    // Chunks are merged in order, whichever worker finished first, so floating point sums come out the same on every run
+    let mut film: Option<Film> = None;
+    let mut finished = BTreeMap::new();
+    let mut next_chunk = 0;
+    let mut current = Progress { total: total as u64, ..Default::default() };
+
+    for (index, chunk_film) in result_rx {
+        current.completed += 1;
+        current.samples += chunk_film.total_samples();
+        current.elapsed = start_time.elapsed();
+        progress.update(&current);
+
+        finished.insert(index, chunk_film);
+        while let Some(chunk_film) = finished.remove(&next_chunk) {
+            merge_into(&mut film, chunk_film)?;
+            next_chunk += 1;
+        }
+    }
+
+    current.elapsed = start_time.elapsed();
+    progress.finish(&current);
+
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    if next_chunk < total {
+        if !cancel::interrupted() {
+            return Err(format!("every worker dropped out with {} of {} chunks left", total - next_chunk, total));
+        }
+
+        println!("Interrupted with {} of {} chunks left", total - next_chunk - finished.len(), total);
+        for chunk_film in finished.into_values() {
+            merge_into(&mut film, chunk_film)?;
+        }
+    }
+
+    return film.ok_or(String::from("no chunks were rendered"));
+}
+
+/// Adds a chunk's film to the merged film, or starts it if this is the first chunk
+fn merge_into(film: &mut Option<Film>, chunk_film: Film) -> Result<(), String> {
+    match film {
+        Some(film) => return film.merge(&chunk_film),
+        None => *film = Some(chunk_film),
+    }
+    return Ok(());
+}
+
+/// How long the coordinator waits for workers
+#[derive(Copy, Clone, Debug)]
+pub struct Timeouts {
+    /// Longest wait to connect, or for a worker to take or send data once it has started, before it counts as lost
+    pub io: Duration,
+    /// How long a chunk can take before it is also handed to another worker. The slow worker keeps going in case it finishes first
+    pub chunk: Duration,
+}
+
+/// Keeps one worker busy until every chunk has come back.
+/// Once the queue is empty it waits in case another worker drops out and its chunk needs a new home
+fn run_worker(address: &str, job: &[String], work: &Mutex<Work>, results: &Sender<(usize, Film)>, timeouts: Timeouts) -> io::Result<()> {
+    let socket_address = address
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "address didn't resolve"))?;
+
+    let stream = TcpStream::connect_timeout(&socket_address, timeouts.io)?;
+    stream.set_write_timeout(Some(timeouts.io))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+
+    loop {
+        let next = {
+            let mut work = work.lock().unwrap();
+            if work.remaining == 0 || cancel::interrupted() {
+                return Ok(());
+            }
+            work.next_chunk()
+        };
+
+        let Some((index, chunk)) = next else {
+            thread::sleep(Duration::from_millis(100));
+            continue;
+        };
+
+        match render_chunk(&mut reader, &mut writer, job, (index, chunk), work, timeouts) {
+            Ok(Some(film)) => {
+                // The coordinator only stops listening if merging failed, which it reports itself
+                if work.lock().unwrap().finish(index) {
+                    let _ = results.send((index, film));
+                }
+            }
+            // Another worker finished the chunk first, and the render is over so this connection isn't needed any more
+            Ok(None) => return Ok(()),
+            Err(err) => {
+                work.lock().unwrap().requeue(index, chunk);
+                return Err(err);
+            }
+        }
+    }
+}
+
+/// Sends one chunk to a worker and waits for its film.
+/// Once the chunk deadline passes the chunk is put back in the queue for another worker while this one keeps going.
+/// Returns None if the render finished or was interrupted before the worker answered
+fn render_chunk(reader: &mut BufReader<TcpStream>, writer: &mut impl Write, job: &[String], (index, chunk): (usize, SampleChunk), work: &Mutex<Work>, timeouts: Timeouts) -> io::Result<Option<Film>> {
+    let mut chunk_job = job.to_vec();
+    chunk_job.extend([String::from("--first-sample"), chunk.first_sample.to_string(), String::from("--pass-samples"), chunk.samples.to_string()]);
+    write_job(writer, &chunk_job)?;
+    writer.flush()?;
+
+    // Nothing is sent while the worker renders, so wait for the answer to start a little at a time
+    let deadline = Instant::now() + timeouts.chunk;
+    let mut requeued = false;
+    reader.get_ref().set_read_timeout(Some(POLL_INTERVAL))?;
+
+    loop {
+        match reader.fill_buf() {
+            Ok(_) => break,
+            Err(err) if matches!(err.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => {
+                let mut work = work.lock().unwrap();
+                if work.remaining == 0 || cancel::interrupted() {
+                    return Ok(None);
+                }
+                if !requeued && Instant::now() >= deadline {
+                    work.requeue(index, chunk);
+                    requeued = true;
+                }
+            }
+            Err(err) => return Err(err),
+        }
+    }
+
+    // Once the answer has started the rest of it should arrive steadily
+    reader.get_ref().set_read_timeout(Some(timeouts.io))?;
+    let mut status = String::new();
+    if reader.read_line(&mut status)? == 0 {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the worker closed the connection"));
+    }
+
+    return match status.trim_end() {
+        "OK" => checkpoint::read_film(reader).map(Some),
+        status => Err(io::Error::other(status.strip_prefix("ERROR ").unwrap_or(status).to_string())),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn work(chunks: usize) -> Work {
+        let queue = split_samples(chunks as u64 * 4, 4).into_iter().enumerate().collect();
+        return Work { queue, remaining: chunks, done: vec![false; chunks] };
+    }
+
+    #[test]
+    fn chunks_cover_every_sample_once() {
+        let chunks = split_samples(10, 4);
+        let ranges: Vec<(u64, u64)> = chunks.iter().map(|c| (c.first_sample, c.samples)).collect();
+        assert_eq!(ranges, vec![(0, 4), (4, 4), (8, 2)]);
+    }
+
+    #[test]
+    fn jobs_keep_arguments_with_spaces() {
+        let job: Vec<String> = ["--volume", "/tmp/my clouds/cloud.vol", "--seed", "7"].map(String::from).to_vec();
+        let mut bytes = Vec::new();
+        write_job(&mut bytes, &job).unwrap();
+        write_job(&mut bytes, &[]).unwrap();
+
+        let mut reader = bytes.as_slice();
+        assert_eq!(read_job(&mut reader).unwrap(), Some(job));
+        assert_eq!(read_job(&mut reader).unwrap(), Some(vec![]));
+        assert_eq!(read_job(&mut reader).unwrap(), None);
+    }
+
+    #[test]
+    fn oversized_jobs_are_rejected() {
+        let mut bytes = (MAX_JOB_ARGS as u32 + 1).to_le_bytes().to_vec();
+        assert_eq!(read_job(&mut bytes.as_slice()).unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        bytes = 1_u32.to_le_bytes().to_vec();
+        bytes.extend_from_slice(&(MAX_JOB_ARG_LEN as u32 + 1).to_le_bytes());
+        assert_eq!(read_job(&mut bytes.as_slice()).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn slow_chunk_handed_out_twice_is_only_finished_once() {
+        let mut work = work(2);
+        let (index, chunk) = work.next_chunk().unwrap();
+
+        // The first worker is too slow, so the chunk goes back in the queue behind the other one
+        work.requeue(index, chunk);
+        work.requeue(index, chunk);
+        assert_eq!(work.queue.len(), 2);
+
+        assert!(work.finish(index));
+        assert!(!work.finish(index));
+        assert_eq!(work.remaining, 1);
+
+        // The queued copy is skipped now that the chunk has come back
+        assert_eq!(work.next_chunk().map(|(i, _)| i), Some(1));
+        assert!(work.next_chunk().is_none());
+    }
+}
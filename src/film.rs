@@ -0,0 +1,446 @@
+use crate::{aov::{Aov, AovPixel}, camera::AdaptiveSampling, colour::Colour, filter::Filter, sampler::SamplerKind, scene::SceneKind, stats::RenderStats, tonemap::DisplaySettings};
+
+/// How colour is stored alongside an alpha channel
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum AlphaMode {
+    /// Colour has already been multiplied by alpha, the usual convention for OpenEXR
+    Premultiplied,
+    /// Colour is independent of alpha, the usual convention for PNG
+    Straight,
+}
+
+impl AlphaMode {
+    /// Finds a mode from its name, as used on the command line
+    pub fn from_name(name: &str) -> Option<Self> {
+        return match name.to_lowercase().as_str() {
+            "premultiplied" | "premult" => Some(AlphaMode::Premultiplied),
+            "straight" | "unpremultiplied" => Some(AlphaMode::Straight),
+            _ => None,
+        };
+    }
+}
+
+/// Running statistics of the luminance of the samples taken in a pixel, used to decide when it has had enough samples
+#[derive(Copy, Clone, Debug, Default)]
+pub struct PixelVariance {
+    pub sum: f64,
+    pub sum_squared: f64,
+    pub samples: u32,
+}
+
+impl PixelVariance {
+    /// Adds a sample's colour to the statistics
+    pub fn add_sample(&mut self, colour: Colour) {
+        let luminance = 0.2126 * colour.x + 0.7152 * colour.y + 0.0722 * colour.z;
+        self.sum += luminance;
+        self.sum_squared += luminance * luminance;
+        self.samples += 1;
+    }
+
+    /// Returns the unbiased sample variance of the luminance
+    pub fn variance(&self) -> f64 {
+        if self.samples < 2 {
+            return 0.0;
+        }
+
+        let n = self.samples as f64;
+        let mean = self.sum / n;
+        return ((self.sum_squared - n * mean * mean) / (n - 1.0)).max(0.0);
+    }
+
+    /// Estimates how far the pixel's mean luminance is from the true value.
+    /// The standard error is divided by the square root of the mean, which follows how visible noise is better than a plain relative error
+    pub fn error(&self) -> f64 {
+        if self.samples < 2 {
+            return f64::INFINITY;
+        }
+
+        let n = self.samples as f64;
+        let mean = self.sum / n;
+        return (self.variance() / n).sqrt() / mean.max(1e-4).sqrt();
+    }
+
+    /// Adds the statistics of another set of samples of the same pixel
+    pub fn merge(&mut self, other: &PixelVariance) {
+        self.sum += other.sum;
+        self.sum_squared += other.sum_squared;
+        self.samples += other.samples;
+    }
+}
+
+/// Everything apart from the sample seed which decides what a film's samples look like.
+/// Films can only be merged, and checkpoints resumed, when these match
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct FilmSettings {
+    pub scene: SceneKind,
+    /// Seed the scene was built from
+    pub scene_seed: u64,
+    /// Fingerprint of the volume grid loaded for the scene, see `VoxelGrid::fingerprint`. The random cloud is decided by the scene seed instead
+    pub volume: Option<u64>,
+    /// Density and height of the fog, its colour and how it scatters are the same for every render
+    pub fog: Option<(f64, f64)>,
+    pub sampler: SamplerKind,
+    pub filter: Filter,
+    /// Samples per pixel the render was asked for, which the samplers plan their patterns around
+    pub samples_per_pixel: u64,
+    pub adaptive: Option<AdaptiveSampling>,
+}
+
+impl FilmSettings {
+    /// Describes the first setting which differs between two films, or returns None if they all match
+    pub fn difference(&self, other: &FilmSettings) -> Option<String> {
+        if self.scene != other.scene {
+            return Some(format!("scenes {:?} and {:?}", self.scene, other.scene));
+        }
+        if self.scene_seed != other.scene_seed {
+            return Some(format!("scene seeds {} and {}", self.scene_seed, other.scene_seed));
+        }
+        if self.volume != other.volume {
+            let describe = |volume: Option<u64>| volume.map_or(String::from("the random cloud"), |hash| format!("grid {:016x}", hash));
+            return Some(format!("volumes {} and {}", describe(self.volume), describe(other.volume)));
+        }
+        if self.fog != other.fog {
+            let describe = |fog: Option<(f64, f64)>| fog.map_or(String::from("none"), |(density, height)| format!("{} up to {}", density, height));
+            return Some(format!("fog {} and {}", describe(self.fog), describe(other.fog)));
+        }
+        if self.sampler != other.sampler {
+            return Some(format!("samplers {:?} and {:?}", self.sampler, other.sampler));
+        }
+        if self.filter != other.filter {
+            return Some(format!("filters {:?} and {:?}", self.filter, other.filter));
+        }
+        if self.samples_per_pixel != other.samples_per_pixel {
+            return Some(format!("{} and {} samples per pixel", self.samples_per_pixel, other.samples_per_pixel));
+        }
+        if self.adaptive != other.adaptive {
+            return Some(format!("adaptive sampling {:?} and {:?}", self.adaptive, other.adaptive));
+        }
+        return None;
+    }
+}
+
+/// Total filter weight below which a pixel counts as empty. Filters with negative lobes can leave a pixel's weight
+/// close to zero or below it, and dividing by that would give fireflies or negated colours
+const MIN_PIXEL_WEIGHT: f32 = 1e-4;
+
+/// Running sum of the filter weighted linear radiance of every sample which reached a pixel
+#[derive(Copy, Clone, Debug, Default)]
+pub struct FilmPixel {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+    /// Weighted sum of the coverage of every sample, 1 for samples which hit an object
+    pub a: f32,
+    /// Sum of the filter weights of every sample
+    pub weight: f32,
+    pub samples: u32,
+}
+
+impl FilmPixel {
+    /// Adds a sample to the pixel
+    ///
+    /// ## Arguments
+    /// - `colour` Light arriving along the camera ray
+    /// - `alpha` 1 if the camera ray hit an object, otherwise 0
+    /// - `weight` Reconstruction filter weight of the sample for this pixel
+    pub fn add_sample(&mut self, colour: Colour, alpha: f64, weight: f64) {
+        self.r += (weight * colour.x) as f32;
+        self.g += (weight * colour.y) as f32;
+        self.b += (weight * colour.z) as f32;
+        self.a += (weight * alpha) as f32;
+        self.weight += weight as f32;
+        self.samples += 1;
+    }
+
+    /// Adds every sample of another pixel to this one
+    pub fn merge(&mut self, other: &FilmPixel) {
+        self.r += other.r;
+        self.g += other.g;
+        self.b += other.b;
+        self.a += other.a;
+        self.weight += other.weight;
+        self.samples += other.samples;
+    }
+
+    /// Returns the weighted sum of every sample as a colour
+    pub fn sum(&self) -> Colour {
+        return Colour::from(self.r as f64, self.g as f64, self.b as f64);
+    }
+
+    /// Returns the weighted average linear radiance of the pixel, or black if its samples carry almost no weight
+    pub fn colour(&self) -> Colour {
+        if self.weight <= MIN_PIXEL_WEIGHT {
+            return Colour::new();
+        }
+        return self.sum() / self.weight as f64;
+    }
+
+    /// Returns the weighted fraction of samples which hit an object
+    pub fn alpha(&self) -> f64 {
+        if self.weight <= MIN_PIXEL_WEIGHT {
+            return 0.0;
+        }
+        return (self.a / self.weight).clamp(0.0, 1.0) as f64;
+    }
+
+    /// Returns the average colour and alpha of the pixel.
+    /// Samples which miss everything add no light when the background is transparent, so the colour starts off premultiplied
+    ///
+    /// ## Arguments
+    /// - `mode` How the returned colour relates to alpha
+    pub fn colour_with_alpha(&self, mode: AlphaMode) -> (Colour, f64) {
+        let alpha = self.alpha();
+
+        if mode == AlphaMode::Straight && alpha > 0.0 {
+            return (self.colour() / alpha, alpha);
+        }
+        return (self.colour(), alpha);
+    }
+}
+
+/// High dynamic range framebuffer which accumulates linear radiance.
+/// Nothing is clamped or quantised until the film is converted for output
+#[derive(Clone, Debug)]
+pub struct Film {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<FilmPixel>,
+    /// Render passes of every pixel, in the same order as `pixels`
+    pub aovs: Vec<AovPixel>,
+    /// Luminance statistics of the samples taken in each pixel, in the same order as `pixels`
+    pub variance: Vec<PixelVariance>,
+    /// Whether the background was left out, in which case the film is saved with an alpha channel
+    pub transparent: bool,
+    /// Scene and sampling settings the film was rendered with
+    pub settings: FilmSettings,
+    /// Seed the samples were derived from
+    pub seed: u64,
+    /// Index of the first sample taken in each pixel
+    pub first_sample: u64,
+    /// Index of the next sample each pixel would take, so a render can carry on from where it stopped.
+    /// Adaptively sampled pixels may have stopped short of it
+    pub next_sample: u64,
+    /// Rays cast and time spent rendering the film in this process, checkpoints don't keep them
+    pub stats: RenderStats,
+}
+
+impl Film {
+    /// Creates an empty film
+    ///
+    /// ## Arguments
+    /// - `width` Width of the image in pixels
+    /// - `height` Height of the image in pixels
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![FilmPixel::default(); width * height],
+            aovs: vec![AovPixel::default(); width * height],
+            variance: vec![PixelVariance::default(); width * height],
+            transparent: false,
+            settings: FilmSettings::default(),
+            seed: 0,
+            first_sample: 0,
+            next_sample: 0,
+            stats: RenderStats::default(),
+        }
+    }
+
+    /// Returns a pixel of the film
+    pub fn pixel(&self, x: usize, y: usize) -> &FilmPixel {
+        return &self.pixels[y * self.width + x];
+    }
+
+    /// Adds the samples of a block of whole rows to the film
+    ///
+    /// ## Arguments
+    /// - `first_row` Row the block starts at
+    /// - `pixels` Pixels of the block row by row, must be a whole number of rows
+    pub fn add_rows(&mut self, first_row: usize, pixels: &[FilmPixel]) {
+        let start = first_row * self.width;
+
+        for (pixel, other) in self.pixels[start..start + pixels.len()].iter_mut().zip(pixels) {
+            pixel.merge(other);
+        }
+    }
+
+    /// Overwrites the render passes and statistics of a row
+    ///
+    /// ## Arguments
+    /// - `y` Row to replace
+    /// - `aov_row` New render passes for the row, must be as long as the film is wide
+    /// - `variance_row` New statistics for the row, must be as long as the film is wide
+    pub fn set_pixel_row(&mut self, y: usize, aov_row: &[AovPixel], variance_row: &[PixelVariance]) {
+        self.aovs[y * self.width..(y + 1) * self.width].copy_from_slice(aov_row);
+        self.variance[y * self.width..(y + 1) * self.width].copy_from_slice(variance_row);
+    }
+
+    /// Checks that another film is of the same scene, seen the same way, so their samples can be added together
+    pub fn check_compatible(&self, other: &Film) -> Result<(), String> {
+        if self.width != other.width || self.height != other.height {
+            return Err(format!("the films are {}x{} and {}x{} pixels", self.width, self.height, other.width, other.height));
+        }
+        if self.transparent != other.transparent {
+            return Err(String::from("only one of the films has a transparent background"));
+        }
+        if let Some(difference) = self.settings.difference(&other.settings) {
+            return Err(format!("the films have different settings, {}", difference));
+        }
+        return Ok(());
+    }
+
+    /// Adds every sample of another film of the same scene to this one, giving an image with more samples.
+    /// Films with the same seed must have taken different samples, otherwise the same sample would be counted twice.
+    /// The merged film carries on with this film's seed, from after the other's samples if they share it
+    ///
+    /// ## Arguments
+    /// - `other` Film to add, it must match this one, see `check_compatible`
+    pub fn merge(&mut self, other: &Film) -> Result<(), String> {
+        self.check_compatible(other)?;
+
+        if self.seed == other.seed {
+            if self.first_sample < other.next_sample && other.first_sample < self.next_sample {
+                return Err(format!(
+                    "both films took samples {} to {} with seed {}",
+                    self.first_sample.max(other.first_sample),
+                    self.next_sample.min(other.next_sample) - 1,
+                    self.seed
+                ));
+            }
+            self.first_sample = self.first_sample.min(other.first_sample);
+            self.next_sample = self.next_sample.max(other.next_sample);
+        }
+
+        for (pixel, other) in self.pixels.iter_mut().zip(&other.pixels) {
+            pixel.merge(other);
+        }
+        for (aov, other) in self.aovs.iter_mut().zip(&other.aovs) {
+            aov.merge(other);
+        }
+        for (stats, other) in self.variance.iter_mut().zip(&other.variance) {
+            stats.merge(other);
+        }
+        self.stats.merge(&other.stats);
+
+        return Ok(());
+    }
+
+    /// Returns the average number of samples taken in each pixel
+    pub fn average_samples(&self) -> f64 {
+        return self.total_samples() as f64 / self.variance.len().max(1) as f64;
+    }
+
+    /// Returns how many camera samples have been taken across the whole film
+    pub fn total_samples(&self) -> u64 {
+        return self.variance.iter().map(|v| v.samples as u64).sum();
+    }
+
+    /// Returns the value of a render pass for every pixel, row by row
+    pub fn aov_values(&self, aov: Aov) -> Vec<Colour> {
+        return self.aovs.iter().map(|p| p.value(aov)).collect();
+    }
+
+    /// Converts the film into tone mapped, sRGB encoded 8-bit pixels packed as `0RGB` u32 values
+    ///
+    /// ## Arguments
+    /// - `display` Exposure and tone mapper to apply
+    pub fn to_packed_rgb(&self, display: &DisplaySettings) -> Vec<u32> {
+        let mut output = Vec::with_capacity(self.pixels.len());
+
+        for pixel in &self.pixels {
+            display.map(pixel.colour()).write_colour_pixels(&mut output, 1);
+        }
+
+        return output;
+    }
+
+    /// Converts the film into tone mapped, sRGB encoded 8-bit pixels with alpha packed as `ARGB` u32 values.
+    /// Tone mapping is applied to the straight colour so partly covered edges aren't darkened twice
+    ///
+    /// ## Arguments
+    /// - `display` Exposure and tone mapper to apply
+    /// - `mode` Whether the packed colour is premultiplied by alpha
+    pub fn to_packed_argb(&self, display: &DisplaySettings, mode: AlphaMode) -> Vec<u32> {
+        let mut output = Vec::with_capacity(self.pixels.len());
+
+        for pixel in &self.pixels {
+            let (colour, alpha) = pixel.colour_with_alpha(AlphaMode::Straight);
+            let mut mapped = display.map(colour);
+
+            if mode == AlphaMode::Premultiplied {
+                mapped *= alpha;
+            }
+
+            let mut rgb = vec![];
+            mapped.write_colour_pixels(&mut rgb, 1);
+            output.push(((256.0 * alpha.clamp(0.0, 0.999)) as u32) << 24 | rgb[0]);
+        }
+
+        return output;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negative_lobes_cancelling_out_leave_an_empty_pixel() {
+        let mut pixel = FilmPixel::default();
+        pixel.add_sample(Colour::from(1.0, 1.0, 1.0), 1.0, 0.3);
+        pixel.add_sample(Colour::from(5.0, 5.0, 5.0), 1.0, -0.29999);
+
+        assert_eq!(pixel.colour().length(), 0.0);
+        assert_eq!(pixel.alpha(), 0.0);
+    }
+
+    #[test]
+    fn negative_total_weight_does_not_negate_the_colour() {
+        let mut pixel = FilmPixel::default();
+        pixel.add_sample(Colour::from(1.0, 1.0, 1.0), 1.0, -0.1);
+
+        assert_eq!(pixel.colour().length(), 0.0);
+    }
+
+    #[test]
+    fn positive_weights_average_normally() {
+        let mut pixel = FilmPixel::default();
+        pixel.add_sample(Colour::from(1.0, 0.0, 0.0), 1.0, 1.0);
+        pixel.add_sample(Colour::from(0.0, 0.0, 0.0), 0.0, 1.0);
+
+        assert!((pixel.colour().x - 0.5).abs() < 1e-6);
+        assert!((pixel.alpha() - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn films_of_different_scenes_are_not_merged() {
+        let mut film = Film::new(2, 2);
+        let mut other = Film::new(2, 2);
+        other.settings.scene_seed = 1;
+
+        assert!(film.merge(&other).is_err());
+
+        other.settings.scene_seed = 0;
+        assert!(film.merge(&other).is_ok());
+    }
+
+    #[test]
+    fn films_with_different_volumes_or_fog_are_not_merged() {
+        let film = Film::new(2, 2);
+        let mut other = Film::new(2, 2);
+
+        other.settings.scene = SceneKind::Volumes;
+        assert!(film.check_compatible(&other).unwrap_err().contains("scenes"));
+        other.settings.scene = SceneKind::Spheres;
+
+        other.settings.volume = Some(0x1234);
+        assert!(film.check_compatible(&other).unwrap_err().contains("volumes"));
+        other.settings.volume = None;
+
+        other.settings.fog = Some((0.05, 2.0));
+        assert!(film.check_compatible(&other).unwrap_err().contains("fog"));
+        other.settings.fog = None;
+
+        assert!(film.check_compatible(&other).is_ok());
+    }
+}
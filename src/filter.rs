@@ -0,0 +1,148 @@
+use std::f64::consts::PI;
+
+/// Shape of a pixel reconstruction filter
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FilterKind {
+    /// Every sample in range counts equally, the sharpest filter but the most prone to aliasing
+    Box,
+    /// Weight falls off linearly with distance
+    Tent,
+    /// Smooth falloff which blurs slightly but never rings
+    Gaussian,
+    /// Mitchell-Netravali cubic with B = C = 1/3, a good balance of sharpness and ringing
+    Mitchell,
+    /// Windowed sinc, the sharpest of the smooth filters but it can ring around bright edges
+    Lanczos,
+}
+
+/// Smallest radius which still reaches a pixel centre from every point of the image
+pub const MIN_RADIUS: f64 = 0.5;
+
+/// Weights samples by their distance from a pixel's centre, so each sample contributes to every pixel within the radius
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Filter {
+    pub kind: FilterKind,
+    /// How far, in pixels, a sample reaches from where it was taken
+    pub radius: f64,
+}
+
+impl Default for Filter {
+    /// A box filter covering exactly one pixel, which is the same as averaging the samples taken in each pixel
+    fn default() -> Self {
+        Self {
+            kind: FilterKind::Box,
+            radius: 0.5,
+        }
+    }
+}
+
+impl FilterKind {
+    pub const ALL: [FilterKind; 5] = [FilterKind::Box, FilterKind::Tent, FilterKind::Gaussian, FilterKind::Mitchell, FilterKind::Lanczos];
+}
+
+impl Filter {
+    /// Creates a filter with the usual radius for its kind
+    pub fn new(kind: FilterKind) -> Self {
+        let radius = match kind {
+            FilterKind::Box => 0.5,
+            FilterKind::Tent => 1.0,
+            FilterKind::Gaussian => 1.5,
+            FilterKind::Mitchell => 2.0,
+            FilterKind::Lanczos => 3.0,
+        };
+
+        Self { kind, radius }
+    }
+
+    /// Changes how far the filter reaches
+    ///
+    /// ## Arguments
+    /// - `radius` Reach in pixels. It must be at least half a pixel, otherwise samples taken between pixel centres reach no pixel
+    pub fn with_radius(mut self, radius: f64) -> Result<Self, String> {
+        if radius.is_nan() || radius < MIN_RADIUS {
+            return Err(format!("filter radius must be at least {}, got {}", MIN_RADIUS, radius));
+        }
+
+        self.radius = radius;
+        return Ok(self);
+    }
+
+    /// Finds a filter kind from its name, as used on the command line
+    pub fn from_name(name: &str) -> Option<Self> {
+        let kind = match name.to_lowercase().as_str() {
+            "box" => FilterKind::Box,
+            "tent" | "triangle" => FilterKind::Tent,
+            "gaussian" => FilterKind::Gaussian,
+            "mitchell" => FilterKind::Mitchell,
+            "lanczos" => FilterKind::Lanczos,
+            _ => return None,
+        };
+
+        return Some(Filter::new(kind));
+    }
+
+    /// Weight of a sample at an offset from a pixel's centre, the filter is separable so this is the product of both axes
+    ///
+    /// ## Arguments
+    /// - `dx` Horizontal distance from the pixel's centre in pixels
+    /// - `dy` Vertical distance from the pixel's centre in pixels
+    pub fn weight(&self, dx: f64, dy: f64) -> f64 {
+        return self.weight_1d(dx) * self.weight_1d(dy);
+    }
+
+    fn weight_1d(&self, x: f64) -> f64 {
+        let x = x.abs();
+        if x >= self.radius {
+            return 0.0;
+        }
+
+        return match self.kind {
+            FilterKind::Box => 1.0,
+            FilterKind::Tent => 1.0 - x / self.radius,
+            FilterKind::Gaussian => {
+                // Shifted down so the weight reaches exactly zero at the radius instead of being cut off
+                let sigma = self.radius / 3.0;
+                let gaussian = |d: f64| (-d * d / (2.0 * sigma * sigma)).exp();
+                (gaussian(x) - gaussian(self.radius)).max(0.0)
+            }
+            FilterKind::Mitchell => mitchell(2.0 * x / self.radius, 1.0 / 3.0, 1.0 / 3.0),
+            FilterKind::Lanczos => sinc(x) * sinc(x / self.radius),
+        };
+    }
+}
+
+/// The Mitchell-Netravali cubic, which is non zero between -2 and 2
+fn mitchell(x: f64, b: f64, c: f64) -> f64 {
+    let x2 = x * x;
+    let x3 = x2 * x;
+
+    let weight = if x < 1.0 {
+        (12.0 - 9.0 * b - 6.0 * c) * x3 + (-18.0 + 12.0 * b + 6.0 * c) * x2 + (6.0 - 2.0 * b)
+    } else if x < 2.0 {
+        (-b - 6.0 * c) * x3 + (6.0 * b + 30.0 * c) * x2 + (-12.0 * b - 48.0 * c) * x + (8.0 * b + 24.0 * c)
+    } else {
+        0.0
+    };
+
+    return weight / 6.0;
+}
+
+/// Normalised sinc function, sin(πx) / πx
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-5 {
+        return 1.0;
+    }
+    return (PI * x).sin() / (PI * x);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn radius_below_half_a_pixel_is_rejected() {
+        assert!(Filter::new(FilterKind::Box).with_radius(0.4).is_err());
+        assert!(Filter::new(FilterKind::Box).with_radius(f64::NAN).is_err());
+        assert_eq!(Filter::new(FilterKind::Tent).with_radius(0.5).unwrap().radius, 0.5);
+    }
+}
@@ -0,0 +1,288 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::Aabb,
+    colour::Colour,
+    hittable::{HitRecord, Hittable},
+    interval::Interval,
+    material::{format_colour, HenyeyGreenstein, Material},
+    random::random_f64,
+    ray::Ray,
+    vector3::Vector3,
+    voxel_grid::{MajorantGrid, VoxelGrid},
+};
+
+/// Phase function used at a real collision inside a heterogeneous medium, which also carries the light emitted at that point
+#[derive(Clone)]
+struct VolumeCollision {
+    phase_function: HenyeyGreenstein,
+    emission: Colour,
+}
+
+impl Material for VolumeCollision {
+    fn scatter(&mut self, ray_in: &Ray, record: &HitRecord, attenuation: &mut Colour, scattered: &mut Ray) -> bool {
+        return self.phase_function.scatter(ray_in, record, attenuation, scattered);
+    }
+
+    fn emitted(&self, _u: f64, _v: f64, _point: Vector3) -> Colour {
+        return self.emission;
+    }
+
+    fn albedo(&self, _u: f64, _v: f64, _point: Vector3) -> Colour {
+        return self.phase_function.albedo;
+    }
+
+    fn describe(&self) -> String {
+        return format!("Heterogeneous medium, {}, emission {}", self.phase_function.describe(), format_colour(self.emission));
+    }
+}
+
+/// A volume of smoke or cloud whose density varies according to a voxel grid.
+/// Collisions are found with delta tracking against a majorant grid, so empty regions are skipped quickly.
+/// Place it in the world with a `transform::Instance`
+#[derive(Clone)]
+pub struct HeterogeneousMedium {
+    pub grid: Arc<VoxelGrid>,
+    pub majorants: Arc<MajorantGrid>,
+    pub density_scale: f64,
+    pub albedo: Colour,
+    pub g: f64,
+    pub emission_scale: f64,
+    pub temperature_scale: f64,
+}
+
+impl HeterogeneousMedium {
+    /// Creates a medium from a voxel grid
+    ///
+    /// ## Arguments
+    /// - `grid` Density grid of the medium
+    /// - `density_scale` Multiplier applied to every density in the grid
+    /// - `albedo` Colour of the medium
+    /// - `g` Scattering direction, from -1 (backwards) to 1 (forwards)
+    pub fn new(grid: VoxelGrid, density_scale: f64, albedo: Colour, g: f64) -> Self {
+        let majorants = MajorantGrid::build(&grid);
+
+        Self {
+            grid: Arc::new(grid),
+            majorants: Arc::new(majorants),
+            density_scale,
+            albedo,
+            g,
+            emission_scale: 1.0,
+            temperature_scale: 1.0,
+        }
+    }
+
+    /// Light emitted at a point from the emission and temperature channels of the grid
+    fn emission_at(&self, p: Vector3) -> Colour {
+        let mut emission = self.emission_scale * self.grid.emission(p);
+
+        let temperature = self.grid.temperature(p);
+        if temperature > 0.0 {
+            // Brightness follows the Stefan-Boltzmann law
+            emission += self.temperature_scale * (temperature / 1000.0).powi(4) * Colour::from_temperature(temperature);
+        }
+
+        return emission;
+    }
+
+    /// Walks the majorant cells the ray passes through in order using a 3D DDA, calling `visit` with the t range and majorant of each.
+    /// Stops early if `visit` returns true
+    fn traverse(&self, ray: &Ray, ray_t: Interval, mut visit: impl FnMut(f64, f64, f64) -> bool) {
+        let range = match self.majorants.clip(ray.origin, ray.direction, ray_t) {
+            Some(range) => range,
+            None => return,
+        };
+
+        let entry = ray.at(range.min);
+        let mut cell = [0i64; 3];
+        let mut step = [0i64; 3];
+        let mut next_t = [f64::INFINITY; 3];
+        let mut delta_t = [f64::INFINITY; 3];
+
+        for a in 0..3 {
+            let size = self.majorants.cell_size(a);
+            let min = self.majorants.bounds.axis(a).min;
+            let last = self.majorants.resolution[a] as i64 - 1;
+            cell[a] = (((entry[a] - min) / size).floor() as i64).clamp(0, last);
+
+            if ray.direction[a] > 0.0 {
+                step[a] = 1;
+                next_t[a] = range.min + (min + (cell[a] + 1) as f64 * size - entry[a]) / ray.direction[a];
+                delta_t[a] = size / ray.direction[a];
+            } else if ray.direction[a] < 0.0 {
+                step[a] = -1;
+                next_t[a] = range.min + (min + cell[a] as f64 * size - entry[a]) / ray.direction[a];
+                delta_t[a] = -size / ray.direction[a];
+            }
+        }
+
+        let mut t = range.min;
+        loop {
+            let axis = if next_t[0] < next_t[1] {
+                if next_t[0] < next_t[2] { 0 } else { 2 }
+            } else if next_t[1] < next_t[2] {
+                1
+            } else {
+                2
+            };
+            let cell_exit = next_t[axis].min(range.max);
+
+            if visit(t, cell_exit, self.majorants.get(cell) * self.density_scale) {
+                return;
+            }
+
+            t = cell_exit;
+            cell[axis] += step[axis];
+            next_t[axis] += delta_t[axis];
+
+            if t >= range.max || cell[axis] < 0 || cell[axis] >= self.majorants.resolution[axis] as i64 {
+                return;
+            }
+        }
+    }
+
+    /// Estimates the fraction of light which passes through the medium along the ray with ratio tracking.
+    /// The path tracer has no shadow rays to use this for yet
+    ///
+    /// ## Arguments
+    /// - `ray` The ray to follow
+    /// - `ray_t` The range of the ray to estimate over
+    #[allow(dead_code)]
+    pub fn transmittance(&self, ray: &Ray, ray_t: Interval) -> f64 {
+        let ray_length = ray.direction.length();
+        let mut transmittance = 1.0;
+
+        self.traverse(ray, ray_t, |start, end, majorant| {
+            if majorant <= 0.0 {
+                return false;
+            }
+
+            let mut t = start;
+            loop {
+                t += -(1.0 - random_f64()).ln() / (majorant * ray_length);
+                if t >= end {
+                    return false;
+                }
+
+                let density = self.grid.density(ray.at(t)) * self.density_scale;
+                transmittance *= 1.0 - density / majorant;
+            }
+        });
+
+        return transmittance;
+    }
+}
+
+impl Hittable for HeterogeneousMedium {
+    /// Finds a real collision along the ray with delta tracking
+    fn hit(&self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        let ray_length = ray.direction.length();
+        let mut collision = None;
+
+        self.traverse(ray, ray_t, |start, end, majorant| {
+            if majorant <= 0.0 {
+                return false;
+            }
+
+            let mut t = start;
+            loop {
+                t += -(1.0 - random_f64()).ln() / (majorant * ray_length);
+                if t >= end {
+                    return false;
+                }
+
+                // Accept the tentative collision with probability density / majorant, otherwise it is a null collision
+                let density = self.grid.density(ray.at(t)) * self.density_scale;
+                if random_f64() < density / majorant {
+                    collision = Some(t);
+                    return true;
+                }
+            }
+        });
+
+        let t = match collision {
+            Some(t) => t,
+            None => return false,
+        };
+
+        rec.t = t;
+        rec.point = ray.at(t);
+
+        // The normal and face are arbitrary since the ray scatters inside the volume
+        rec.normal = Vector3::from(1.0, 0.0, 0.0);
+        rec.front_face = true;
+        rec.u = 0.0;
+        rec.v = 0.0;
+
+        // Emission is weighted by the chance of absorption so the collision estimator stays unbiased
+        let absorption = Colour::from(1.0, 1.0, 1.0) - self.albedo;
+        rec.material = Box::new(VolumeCollision {
+            phase_function: HenyeyGreenstein::new(self.albedo, self.g),
+            emission: absorption * self.emission_at(rec.point),
+        });
+
+        return true;
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        return self.grid.bounds;
+    }
+
+    fn memory_size(&self) -> usize {
+        let grid = size_of::<VoxelGrid>() + self.grid.data.capacity() * size_of::<f32>();
+        let majorants = size_of::<MajorantGrid>() + self.majorants.values.capacity() * size_of::<f64>();
+        return size_of_val(self) + grid + majorants;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::random;
+
+    /// Averages many ratio tracking estimates, which are individually noisy
+    fn mean_transmittance(medium: &HeterogeneousMedium, ray: &Ray) -> f64 {
+        random::seed_thread(7);
+        let estimates = 20000;
+        let sum: f64 = (0..estimates).map(|_| medium.transmittance(ray, Interval::from(0.0, f64::INFINITY))).sum();
+        return sum / estimates as f64;
+    }
+
+    fn constant_medium(density: f32, density_scale: f64) -> HeterogeneousMedium {
+        let grid = VoxelGrid::from_density([4, 4, 4], vec![density; 64]).unwrap();
+        return HeterogeneousMedium::new(grid, density_scale, Colour::from(0.5, 0.5, 0.5), 0.0);
+    }
+
+    #[test]
+    fn constant_grid_follows_beer_lambert() {
+        let medium = constant_medium(1.0, 2.0);
+
+        // Crosses the whole unit cube, so the optical depth is density * distance = 2
+        let ray = Ray::from(Vector3::from(-1.0, 0.5, 0.5), Vector3::from(1.0, 0.0, 0.0));
+        let transmittance = mean_transmittance(&medium, &ray);
+        assert!((transmittance - (-2.0_f64).exp()).abs() < 0.01, "{}", transmittance);
+
+        // A diagonal crosses √2 units of the medium
+        let ray = Ray::from(Vector3::from(-1.0, -1.0, 0.5), Vector3::from(1.0, 1.0, 0.0));
+        let transmittance = mean_transmittance(&medium, &ray);
+        assert!((transmittance - (-2.0 * 2.0_f64.sqrt()).exp()).abs() < 0.01, "{}", transmittance);
+    }
+
+    #[test]
+    fn direction_length_does_not_change_transmittance() {
+        let medium = constant_medium(0.5, 1.0);
+
+        let ray = Ray::from(Vector3::from(0.5, 0.5, -1.0), Vector3::from(0.0, 0.0, 3.0));
+        let transmittance = mean_transmittance(&medium, &ray);
+        assert!((transmittance - (-0.5_f64).exp()).abs() < 0.01, "{}", transmittance);
+    }
+
+    #[test]
+    fn empty_grid_lets_everything_through() {
+        let medium = constant_medium(0.0, 1.0);
+
+        let ray = Ray::from(Vector3::from(-1.0, 0.5, 0.5), Vector3::from(1.0, 0.0, 0.0));
+        assert_eq!(medium.transmittance(&ray, Interval::from(0.0, f64::INFINITY)), 1.0);
+    }
+}
@@ -1,12 +1,19 @@
-use crate::{vector3::{Vector3, dot_product}, ray::Ray, interval::Interval, material::{Material, Lambertian}, colour::Colour};
+use std::sync::Arc;
+
+use crate::{vector3::{Vector3, dot_product}, ray::Ray, interval::Interval, material::{Material, Lambertian}, colour::Colour, aabb::Aabb, stats};
 
 /// Represents a hit from a ray
+#[derive(Clone)]
 pub struct HitRecord {
     pub point: Vector3,
     pub normal: Vector3,
     pub t: f64,
+    pub u: f64,
+    pub v: f64,
     pub front_face: bool,
     pub material: Box<dyn Material>,
+    /// Id of the innermost object added to a `HittableList` which was hit, 0 if it was never added to one
+    pub object_id: u32,
 }
 
 impl HitRecord {
@@ -31,17 +38,77 @@ impl HitRecord {
             point: Vector3::new(),
             normal: Vector3::new(),
             t: 0.0,
+            u: 0.0,
+            v: 0.0,
             front_face: false,
             material: Box::new(Lambertian::new(Colour::new())),
+            object_id: 0,
         }
     }
 }
 
+/// A stretch of a ray which is inside an object, bounded by the hits where the ray enters and exits it
+#[derive(Clone)]
+pub struct HitSpan {
+    pub enter: HitRecord,
+    pub exit: HitRecord,
+}
+
+/// Pairs up the sorted surface crossings of a closed object into spans.
+/// A ray which grazes the surface can lose one root of a pair to rounding, so a root left over at the end is
+/// kept as a tangent touch where the ray enters and exits at once
+///
+/// ## Arguments
+/// - `ray` The ray which was cast
+/// - `roots` Every t value where the ray crosses the surface in ascending order
+/// - `fill_record` Fills in a hit record for the crossing at a given t value
+pub fn spans_from_roots(ray: &Ray, roots: &[f64], fill_record: impl Fn(&Ray, f64, &mut HitRecord)) -> Vec<HitSpan> {
+    let mut spans = vec![];
+
+    for pair in roots.chunks(2) {
+        let mut enter = HitRecord::new();
+        let mut exit = HitRecord::new();
+        fill_record(ray, pair[0], &mut enter);
+        fill_record(ray, pair[pair.len() - 1], &mut exit);
+
+        spans.push(HitSpan { enter, exit });
+    }
+
+    return spans;
+}
+
 pub trait Hittable: Send + Sync + HittableClone  {
-    fn hit(&mut self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool;
+    fn hit(&self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool;
+
+    /// Returns a box which fully encloses the object
+    fn bounding_box(&self) -> Aabb;
+
+    /// Finds every span of the whole ray, including behind its origin, which is inside the object in ascending order.
+    /// Only closed objects can do this, so open objects such as quads return false
+    ///
+    /// ## Arguments
+    ///
+    /// - `ray` The ray which was cast
+    /// - `spans` Vector where the spans are written to
+    fn hit_spans(&self, _ray: &Ray, _spans: &mut Vec<HitSpan>) -> bool {
+        return false;
+    }
+
+    /// Numbers every `ObjectTag` inside the object in order, so objects which hold others must pass this on to them
+    ///
+    /// ## Arguments
+    ///
+    /// - `next_id` The id to give the next tagged object, which is advanced past every id handed out
+    fn assign_object_ids(&mut self, _next_id: &mut u32) {}
+
+    /// Estimates how many bytes the object takes up, so objects which own others or large buffers must add those in.
+    /// Used for the scene memory statistic, which would otherwise need every allocation in the program to be counted
+    fn memory_size(&self) -> usize {
+        return size_of_val(self);
+    }
 }
 
-trait HittableClone {
+pub trait HittableClone {
     fn clone_hittable(&self) -> Box<dyn Hittable>;
 }
 
@@ -56,3 +123,114 @@ impl Clone for Box<dyn Hittable> {
         self.clone_hittable()
     }
 }
+
+/// Lets one copy of an object be placed in several worlds, such as the static part of every frame of an animation.
+/// Its object ids have to be assigned before it is shared
+impl<T: Hittable + 'static> Hittable for Arc<T> {
+    fn hit(&self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        return (**self).hit(ray, ray_t, rec);
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        return (**self).bounding_box();
+    }
+
+    fn hit_spans(&self, ray: &Ray, spans: &mut Vec<HitSpan>) -> bool {
+        return (**self).hit_spans(ray, spans);
+    }
+
+    fn memory_size(&self) -> usize {
+        return (**self).memory_size();
+    }
+}
+
+/// Wraps an object to give every hit on it an object id, which is used for the object id render pass.
+/// Ids are 0 until the finished scene is numbered with `Hittable::assign_object_ids`, so they only depend on where
+/// an object is in the scene and not on what was built before it
+#[derive(Clone)]
+pub struct ObjectTag {
+    pub object: Box<dyn Hittable>,
+    pub id: u32,
+}
+
+impl ObjectTag {
+    /// Gives a record this object's id unless an object nested inside it already set one
+    fn tag(&self, record: &mut HitRecord) {
+        if record.object_id == 0 {
+            record.object_id = self.id;
+        }
+    }
+}
+
+impl Hittable for ObjectTag {
+    fn hit(&self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        // The record may already hold a closer hit from another object, so its id is put back on a miss
+        let previous_id = rec.object_id;
+        rec.object_id = 0;
+        stats::count_intersection_test();
+
+        if self.object.hit(ray, ray_t, rec) {
+            self.tag(rec);
+            return true;
+        }
+
+        rec.object_id = previous_id;
+        return false;
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        return self.object.bounding_box();
+    }
+
+    fn hit_spans(&self, ray: &Ray, spans: &mut Vec<HitSpan>) -> bool {
+        let mut object_spans = vec![];
+        if !self.object.hit_spans(ray, &mut object_spans) {
+            return false;
+        }
+
+        for span in &mut object_spans {
+            self.tag(&mut span.enter);
+            self.tag(&mut span.exit);
+        }
+
+        spans.append(&mut object_spans);
+        return true;
+    }
+
+    fn assign_object_ids(&mut self, next_id: &mut u32) {
+        self.id = *next_id;
+        *next_id += 1;
+        self.object.assign_object_ids(next_id);
+    }
+
+    fn memory_size(&self) -> usize {
+        return size_of_val(self) + self.object.memory_size();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vector3::Vector3;
+
+    fn spans_of(roots: &[f64]) -> Vec<(f64, f64)> {
+        let ray = Ray::from(Vector3::new(), Vector3::from(1.0, 0.0, 0.0));
+        let spans = spans_from_roots(&ray, roots, |r, t, rec| {
+            rec.t = t;
+            rec.point = r.at(t);
+        });
+        return spans.iter().map(|span| (span.enter.t, span.exit.t)).collect();
+    }
+
+    #[test]
+    fn roots_are_paired_in_order() {
+        assert_eq!(spans_of(&[1.0, 2.0, 3.0, 4.0]), vec![(1.0, 2.0), (3.0, 4.0)]);
+        assert!(spans_of(&[]).is_empty());
+    }
+
+    #[test]
+    fn trailing_root_is_kept_as_a_tangent() {
+        assert_eq!(spans_of(&[1.0, 2.0, 3.0]), vec![(1.0, 2.0), (3.0, 3.0)]);
+        assert_eq!(spans_of(&[5.0]), vec![(5.0, 5.0)]);
+    }
+}
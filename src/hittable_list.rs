@@ -1,5 +1,7 @@
 use crate::{
-    hittable::{HitRecord, Hittable},
+    aabb::Aabb,
+    csg::{combine_spans, CsgOperation},
+    hittable::{HitRecord, HitSpan, Hittable, ObjectTag},
     interval::Interval,
     ray::Ray,
 };
@@ -8,6 +10,7 @@ use crate::{
 #[derive(Clone)]
 pub struct HittableList {
     pub objects: Vec<Box<dyn Hittable>>,
+    bbox: Aabb,
 }
 
 impl HittableList {
@@ -15,41 +18,56 @@ impl HittableList {
     pub fn new() -> Self {
         Self {
             objects: Vec::new(),
+            bbox: Aabb::new(),
         }
     }
 
     /// Constructs a hittable list with one object added to it
+    #[allow(dead_code)]
     pub fn with_object(object: Box<dyn Hittable>) -> Self {
-        Self {
-            objects: vec![object],
-        }
+        let mut list = Self::new();
+        list.add(object);
+        return list;
     }
 
     /// Clears the list
+    #[allow(dead_code)]
     pub fn clear(&mut self) {
-        self.objects.clear()
+        self.objects.clear();
+        self.bbox = Aabb::new();
     }
 
-    /// Adds an item to the list
+    /// Adds an item to the list, it gets an object id when the scene is numbered with `assign_object_ids`
     ///
     /// ## Arguments
     ///
     /// - `object` Object to add
     pub fn add(&mut self, object: Box<dyn Hittable>) {
-        self.objects.push(object);
+        self.add_with_id(object, 0);
+    }
+
+    /// Adds an item to the list with an object id which was reserved for it
+    ///
+    /// ## Arguments
+    ///
+    /// - `object` Object to add
+    /// - `id` Object id the object is shown with in the object id pass
+    pub fn add_with_id(&mut self, object: Box<dyn Hittable>, id: u32) {
+        self.bbox = Aabb::from_boxes(self.bbox, object.bounding_box());
+        self.objects.push(Box::new(ObjectTag { object, id }));
     }
 }
 
 impl Hittable for HittableList {
     /// Checks if a ray intersects with a sphere using the formula *-b + √b * b - 4ac / 2a*
-    fn hit(&mut self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+    fn hit(&self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
         let mut temp_record = HitRecord::new();
         let mut hit_anything = false;
         let mut closest_so_far = ray_t.max;
 
         // Loop through every object and check if the ray hit it
         // If so store its information int the output record
-        for object in &mut self.objects {
+        for object in &self.objects {
             if object.hit(
                 ray,
                 Interval::from(ray_t.min, closest_so_far),
@@ -57,15 +75,102 @@ impl Hittable for HittableList {
             ) {
                 hit_anything = true;
                 closest_so_far = temp_record.t;
-                rec.point = temp_record.point;
-                rec.normal = temp_record.normal;
-                rec.t = temp_record.t;
-                rec.front_face = temp_record.front_face;
-                rec.material = temp_record.material.clone();
+                *rec = temp_record.clone();
             }
         }
 
         return hit_anything;
     }
+
+    fn bounding_box(&self) -> Aabb {
+        return self.bbox;
+    }
+
+    /// Treats the list as the union of its objects, so it only works if every object is closed
+    fn hit_spans(&self, ray: &Ray, spans: &mut Vec<HitSpan>) -> bool {
+        let mut combined = vec![];
+
+        for object in &self.objects {
+            let mut object_spans = vec![];
+            if !object.hit_spans(ray, &mut object_spans) {
+                return false;
+            }
+            combined = combine_spans(combined, object_spans, CsgOperation::Union);
+        }
+
+        spans.append(&mut combined);
+        return true;
+    }
+
+    fn assign_object_ids(&mut self, next_id: &mut u32) {
+        for object in &mut self.objects {
+            object.assign_object_ids(next_id);
+        }
+    }
+
+    fn memory_size(&self) -> usize {
+        let pointers = self.objects.capacity() * size_of::<Box<dyn Hittable>>();
+        return size_of_val(self) + pointers + self.objects.iter().map(|object| object.memory_size()).sum::<usize>();
+    }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{bvh::BvhNode, colour::Colour, material::Lambertian, sphere::Sphere, vector3::Vector3};
+
+    fn sphere(x: f64) -> Box<dyn Hittable> {
+        return Box::new(Sphere::new(Vector3::from(x, 0.0, 0.0), 0.5, Box::new(Lambertian::new(Colour::from(0.5, 0.5, 0.5)))));
+    }
+
+    /// Builds a list holding a sphere and a BVH of two more spheres, numbered from 1
+    fn scene() -> HittableList {
+        let mut inner = HittableList::new();
+        inner.add(sphere(2.0));
+        inner.add(sphere(4.0));
+
+        let mut world = HittableList::new();
+        world.add(sphere(0.0));
+        world.add(Box::new(BvhNode::from_list(inner)));
+        world.assign_object_ids(&mut 1);
+        return world;
+    }
+
+    fn id_at(world: &HittableList, x: f64) -> u32 {
+        let ray = Ray::from(Vector3::from(x, 0.0, -5.0), Vector3::from(0.0, 0.0, 1.0));
+        let mut record = HitRecord::new();
+        assert!(world.hit(&ray, Interval::from(0.001, f64::INFINITY), &mut record));
+        return record.object_id;
+    }
+
+    #[test]
+    fn nested_objects_get_distinct_ids() {
+        let world = scene();
+        let ids = [id_at(&world, 0.0), id_at(&world, 2.0), id_at(&world, 4.0)];
+
+        assert!(ids.iter().all(|&id| id != 0), "{:?}", ids);
+        assert!(ids[0] != ids[1] && ids[1] != ids[2] && ids[0] != ids[2], "{:?}", ids);
+    }
+
+    #[test]
+    fn memory_size_counts_nested_objects() {
+        let mut list = HittableList::new();
+        list.add(sphere(0.0));
+        let one = list.memory_size();
+
+        assert!(scene().memory_size() > one);
+        assert!(one >= size_of::<HittableList>() + size_of::<ObjectTag>() + size_of::<Sphere>());
+    }
+
+    #[test]
+    fn ids_do_not_depend_on_earlier_builds() {
+        let first = scene();
+        let _ = scene();
+        let second = scene();
+
+        for x in [0.0, 2.0, 4.0] {
+            assert_eq!(id_at(&first, x), id_at(&second, x));
+        }
+    }
+}
@@ -1,9 +1,16 @@
+#[derive(Copy, Clone, Debug)]
 pub struct Interval {
     pub min: f64,
     pub max: f64
 }
 
 impl Interval {
+    /// An interval containing nothing
+    pub const EMPTY: Interval = Interval { min: f64::INFINITY, max: f64::NEG_INFINITY };
+
+    /// An interval containing every value
+    pub const UNIVERSE: Interval = Interval { min: f64::NEG_INFINITY, max: f64::INFINITY };
+
     pub fn new() -> Self {
         Self {
             min: 0.0,
@@ -18,6 +25,18 @@ impl Interval {
         }
     }
 
+    /// Creates the smallest interval which encloses both intervals
+    ///
+    /// ## Arguments
+    /// - `a` The first interval
+    /// - `b` The second interval
+    pub fn from_intervals(a: Interval, b: Interval) -> Self {
+        Self {
+            min: a.min.min(b.min),
+            max: a.max.max(b.max),
+        }
+    }
+
     pub fn contains(&self, x: f64) -> bool {
         return self.min <= x && x <= self.max;
     }
@@ -31,4 +50,15 @@ impl Interval {
         if x > self.max { return self.max }
         return x;
     }
+
+    /// Returns the length of the interval
+    pub fn size(&self) -> f64 {
+        return self.max - self.min;
+    }
+
+    /// Returns a copy of the interval padded by `delta` in total
+    pub fn expand(&self, delta: f64) -> Self {
+        let padding = delta / 2.0;
+        return Interval::from(self.min - padding, self.max + padding);
+    }
 }
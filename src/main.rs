@@ -1,13 +1,30 @@
+
 use std::io::Write;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use camera::Camera;
+use aov::Aov;
+use animation::{parse_frame_range, render_animation, AnimatedScene, CameraAnimation, Interpolation, Track};
+use cli::Args;
+use film::{AlphaMode, Film};
+use filter::Filter;
+use sampler::SamplerKind;
+use scene::SceneKind;
+use progress::ConsoleProgress;
+use camera::{AdaptiveSampling, Camera};
+use cancel::CancelToken;
 use colour::Colour;
+use constant_medium::Atmosphere;
+use hittable::Hittable;
 use hittable_list::HittableList;
-use material::{Lambertian, Metal};
-use sphere::Sphere;
+use material::Metal;
+use save::{ExrPrecision, OutputSettings};
+use stats::{RenderStats, SceneStats};
+use tonemap::{DisplaySettings, ToneMapper};
+use torus::Torus;
+use transform::Transform;
 use vector3::Vector3;
-
-use crate::{material::{Dieletric, Material}, random::{random_f64, random_f64_in_range}};
+use voxel_grid::VoxelGrid;
 
 mod vector3;
 mod colour;
@@ -21,8 +38,357 @@ mod random;
 mod viewer;
 mod material;
 mod save;
+mod aabb;
+mod quad;
+mod aa_box;
+mod onb;
+mod solver;
+mod cylinder;
+mod cone;
+mod torus;
+mod capsule;
+mod csg;
+mod constant_medium;
+mod transform;
+mod voxel_grid;
+mod heterogeneous_medium;
+mod bvh;
+mod animation;
+mod cli;
+mod film;
+mod tonemap;
+mod aov;
+mod filter;
+mod sampler;
+mod cancel;
+mod checkpoint;
+mod distributed;
+mod progress;
+mod stats;
+mod scene;
+
+/// Printed for `--help`
+const USAGE: &str = "\
+Usage: raytracer [command] [--flag value]...
+
+Commands:
+  (none)       Ask for the width and samples on stdin, render the scene in a window and save it
+  explore      Render in a window where the camera can be moved, the last view is saved when it closes
+  animate      Render an orbit around the scene as numbered frames
+  merge        Add checkpoints of the same scene together into one image
+  distribute   Split a render across worker processes and save the merged image
+  worker       Wait for chunks of samples from `distribute` to render
+
+Image flags (no command, explore, distribute):
+  --scene NAME             spheres, shapes or volumes [spheres]
+  --volume PATH            Density grid for the volumes scene, a .vol file or raw floats [a random cloud]
+  --volume-resolution XxYxZ  Voxels along each axis of a raw --volume
+  --fog DENSITY            Fill the scene with fog up to --fog-height
+  --fog-height Y           Height of the top of the fog [2]
+  --filter NAME            box, tent, gaussian, mitchell or lanczos [box]
+  --filter-radius PIXELS   Reach of the filter, at least 0.5 [depends on the filter]
+  --sampler NAME           independent, stratified, halton, sobol or blue-noise [independent]
+  --transparent            Make the background transparent
+  --adaptive               Stop sampling pixels once they have converged
+  --min-samples N          Samples every pixel gets before it can stop [spp / 8, at least 16]
+  --threshold ERROR        Relative error a pixel converges at [0.01]
+  --seed N                 Seed for the samples, so the render can be repeated
+  --threads N              Threads to render with, the image doesn't depend on it [every core]
+  --scene-seed N           Seed for the layout of the scene [random, or the checkpoint's]
+
+Output flags (no command, explore, merge, distribute):
+  --output PATH            File to save, the extension picks the format [render.png]
+  --half                   Save OpenEXR files with half precision
+  --tonemap NAME           clamp, reinhard, extended-reinhard, aces, hable or agx [clamp]
+  --exposure STOPS         Exposure applied before tone mapping [0]
+  --aovs LIST              Render passes to save, separated by commas, or all
+  --alpha MODE             premultiplied or straight
+  --checkpoint PATH        Write the film here while rendering so it can be resumed
+  --checkpoint-interval S  Seconds between checkpoints [300]
+
+Budget flags (no command, explore, animate):
+  --time SECONDS           Keep sampling until this much time has passed, past --samples unless there is a sample budget too
+  --sample-budget N        Stop once this many samples have been taken across the image
+
+Command flags:
+  (none)       --resume PATH  --stats-json PATH
+  explore      --width N [400]  --samples N [100]  --stats-json PATH
+  animate      --width N [400]  --samples N [10]  --output DIR [frames]  --format EXT [png]  --frames A-B [1-48]
+               --interpolation NAME  bezier, linear or step, how the camera moves between keys [bezier]
+               --scene, --volume, --volume-resolution, --fog, --fog-height, --filter, --filter-radius,
+               --sampler, --adaptive, --min-samples, --threshold, --seed, --threads, --scene-seed,
+               --tonemap, --exposure  --stats-json PATH
+  merge        --inputs LIST  Checkpoints to merge, separated by commas
+  distribute   --workers LIST  --width N [400]  --samples N [100]  --chunk N [8]
+               --worker-timeout S [600]  Time before a chunk is also given to another worker
+               --io-timeout S [30]       Time before a worker which stops answering is dropped
+               --adaptive can't be used, workers only see their own chunk of each pixel
+               --volume has to exist at the same absolute path on every worker
+  worker       --listen ADDRESS [127.0.0.1:7878]  Listen on 0.0.0.0 to take jobs from other machines,
+               there is no authentication so only do that on a trusted network
+";
+
+/// Flags which change what a render looks like, sent to workers along with the scene's seed
+const SCENE_FLAGS: [&str; 12] = ["scene", "volume", "volume-resolution", "fog", "fog-height", "filter", "filter-radius", "sampler", "transparent", "adaptive", "min-samples", "threshold"];
+
+/// Flags read by `output_settings`
+const OUTPUT_FLAGS: [&str; 8] = ["output", "half", "tonemap", "exposure", "aovs", "alpha", "checkpoint", "checkpoint-interval"];
+
+/// Flags read by `render_budgets`
+const BUDGET_FLAGS: [&str; 2] = ["time", "sample-budget"];
 
 fn main() {
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("ERROR: {}", err);
+            std::process::exit(1);
+        }
+    };
+
+    if args.has("help") || args.command.as_deref() == Some("help") {
+        print!("{}", USAGE);
+        return;
+    }
+
+    let result = match args.command.as_deref() {
+        None => interactive(&args),
+        Some("animate") => animate(&args),
+        Some("explore") => explore(&args),
+        Some("merge") => merge(&args),
+        Some("worker") => worker(&args),
+        Some("distribute") => distribute(&args),
+        Some(other) => Err(format!("unknown command '{}'", other)),
+    };
+
+    if let Err(err) = result {
+        eprintln!("ERROR: {}", err);
+        std::process::exit(1);
+    }
+}
+
+/// Reads the exposure and tone mapper flags
+fn display_settings(args: &Args) -> Result<DisplaySettings, String> {
+    let tone_mapper_name = args.get("tonemap", String::from("clamp"))?;
+    let tone_mapper = match ToneMapper::from_name(&tone_mapper_name) {
+        Some(tone_mapper) => tone_mapper,
+        None => return Err(format!("unknown tone mapper '{}'", tone_mapper_name)),
+    };
+
+    return Ok(DisplaySettings {
+        exposure: args.get("exposure", 0.0)?,
+        tone_mapper,
+    });
+}
+
+/// Reads the reconstruction filter flags, the radius defaults to the usual one for the filter
+fn pixel_filter(args: &Args) -> Result<Filter, String> {
+    let filter_name = args.get("filter", String::from("box"))?;
+    let filter = match Filter::from_name(&filter_name) {
+        Some(filter) => filter,
+        None => return Err(format!("unknown filter '{}'", filter_name)),
+    };
+
+    return filter.with_radius(args.get("filter-radius", filter.radius)?);
+}
+
+/// Loads the voxel grid given by the volume flag. `.vol` files describe their own size,
+/// any other file is read as raw densities and needs `--volume-resolution`
+fn volume_grid(args: &Args) -> Result<Option<VoxelGrid>, String> {
+    let Some(path) = args.value("volume") else { return Ok(None) };
+    if scene_kind(args)? != SceneKind::Volumes {
+        return Err(String::from("--volume is only used by the volumes scene"));
+    }
+
+    let grid = if path.to_lowercase().ends_with(".vol") {
+        VoxelGrid::load_vol(path)
+    } else {
+        let resolution = args.get("volume-resolution", String::new())?;
+        let dimensions: Vec<usize> = resolution.split('x').filter_map(|n| n.parse().ok()).collect();
+        let [x, y, z] = dimensions[..] else {
+            return Err(format!("--volume-resolution should look like 64x64x64 for raw volumes, got '{}'", resolution));
+        };
+        VoxelGrid::load_raw(path, [x, y, z])
+    };
+
+    return grid.map(Some).map_err(|e| format!("could not load volume '{}': {}", path, e));
+}
+
+/// Reads the fog flags, which fill the scene with fog of that density up to a height
+fn atmosphere(args: &Args) -> Result<Option<Atmosphere>, String> {
+    if !args.has("fog") {
+        return Ok(None);
+    }
+
+    let density: f64 = args.get("fog", 0.0)?;
+    if !density.is_finite() || density <= 0.0 {
+        return Err(String::from("--fog must be a positive density"));
+    }
+
+    let height: f64 = args.get("fog-height", 2.0)?;
+    if !height.is_finite() {
+        return Err(String::from("--fog-height must be a number"));
+    }
+
+    return Ok(Some(Atmosphere::new(density, height, Colour::from(0.8, 0.8, 0.8), 0.0)));
+}
+
+/// Reads the sampler flag
+fn sampler_kind(args: &Args) -> Result<SamplerKind, String> {
+    let sampler_name = args.get("sampler", String::from("independent"))?;
+    return SamplerKind::from_name(&sampler_name).ok_or(format!("unknown sampler '{}'", sampler_name));
+}
+
+/// Reads the threads flag, leaving the camera to use one thread per core if it isn't given
+fn thread_count(args: &Args) -> Result<Option<usize>, String> {
+    if !args.has("threads") {
+        return Ok(None);
+    }
+
+    let threads: usize = args.get("threads", 0)?;
+    if threads == 0 {
+        return Err(String::from("--threads must be at least 1"));
+    }
+    return Ok(Some(threads));
+}
+
+/// Reads the scene flag
+fn scene_kind(args: &Args) -> Result<SceneKind, String> {
+    let scene_name = args.get("scene", String::from("spheres"))?;
+    return SceneKind::from_name(&scene_name).ok_or(format!("unknown scene '{}'", scene_name));
+}
+
+/// Reads the seed flag, which the samples are derived from so the render can be repeated
+fn seed(args: &Args) -> Result<Option<u64>, String> {
+    if !args.has("seed") {
+        return Ok(None);
+    }
+    return Ok(Some(args.get("seed", 0)?));
+}
+
+/// Reads the scene seed flag, which the layout of the scene is built from, picking a random one if it wasn't given.
+/// The seed is stored in every film so a checkpoint builds the same scene again when it's resumed
+///
+/// ## Arguments
+/// - `default` Seed to use if the flag wasn't given, such as the one of a checkpoint being resumed
+fn scene_seed(args: &Args, default: Option<u64>) -> Result<u64, String> {
+    if !args.has("scene-seed") {
+        return Ok(default.unwrap_or_else(rand::random));
+    }
+    return args.get("scene-seed", 0);
+}
+
+/// Loads the checkpoint given by the resume flag
+fn resume(args: &Args) -> Result<Option<Film>, String> {
+    let Some(path) = args.value("resume") else { return Ok(None) };
+
+    let film = checkpoint::load_checkpoint(path).map_err(|e| format!("could not load checkpoint '{}': {}", path, e))?;
+    println!("Resuming '{}' after {} samples per pixel", path, film.next_sample);
+
+    return Ok(Some(film));
+}
+
+/// Reads the adaptive sampling flags, the minimum defaults to an eighth of samples_per_pixel but at least 16
+fn adaptive_sampling(args: &Args, samples_per_pixel: i64) -> Result<Option<AdaptiveSampling>, String> {
+    if !args.has("adaptive") {
+        return Ok(None);
+    }
+
+    return Ok(Some(AdaptiveSampling {
+        min_samples: args.get("min-samples", (samples_per_pixel / 8).max(16))?,
+        threshold: args.get("threshold", 0.01)?,
+    }));
+}
+
+/// Reads the flags which control where and how the final image is saved
+fn output_settings(args: &Args) -> Result<OutputSettings, String> {
+    let path = args.get("output", String::from("render.png"))?;
+    if let Some(warning) = save::check_output_path(&path, args.has("transparent"))? {
+        eprintln!("WARNING: {}", warning);
+    }
+
+    return Ok(OutputSettings {
+        path,
+        precision: if args.has("half") { ExrPrecision::Half } else { ExrPrecision::Float },
+        display: display_settings(args)?,
+        aovs: Aov::parse_list(args.value("aovs").unwrap_or(""))?,
+        alpha_mode: match args.value("alpha") {
+            Some(name) => Some(AlphaMode::from_name(name).ok_or(format!("unknown alpha mode '{}'", name))?),
+            None => None,
+        },
+        checkpoint: args.value("checkpoint").map(String::from),
+        checkpoint_interval: Duration::from_secs_f64(args.get("checkpoint-interval", 300.0_f64)?.max(1.0)),
+    });
+}
+
+/// Reads the time and sample budget flags, the time budget is in seconds and the sample budget counts samples across the whole image
+fn render_budgets(args: &Args) -> Result<(Option<Duration>, Option<u64>), String> {
+    let time_budget = match args.value("time") {
+        Some(_) => {
+            let seconds: f64 = args.get("time", 0.0)?;
+            if !seconds.is_finite() || seconds <= 0.0 {
+                return Err(String::from("--time must be a positive number of seconds"));
+            }
+            Some(Duration::from_secs_f64(seconds))
+        }
+        None => None,
+    };
+    let sample_budget = match args.value("sample-budget") {
+        Some(_) => Some(args.get("sample-budget", 0)?),
+        None => None,
+    };
+
+    return Ok((time_budget, sample_budget));
+}
+
+/// Builds the scene, measuring how long it took and how much memory it holds
+///
+/// ## Arguments
+/// - `kind` Scene to build
+/// - `volume` Grid for the volumes scene, see `volume_grid`
+/// - `scene_seed` Seed the layout of the scene is built from
+fn build_world_measured(kind: SceneKind, volume: Option<VoxelGrid>, scene_seed: u64) -> (HittableList, SceneStats) {
+    let start_time = Instant::now();
+    let world = scene::build_world(kind, volume, scene_seed);
+
+    let scene_stats = SceneStats {
+        build_time: start_time.elapsed(),
+        memory_bytes: world.memory_size(),
+    };
+    return (world, scene_stats);
+}
+
+/// Prints the performance report, and writes it as JSON too if `--stats-json` was given
+fn report_stats(args: &Args, render_stats: &RenderStats, scene_stats: &SceneStats) {
+    println!();
+    stats::print_report(render_stats, scene_stats);
+
+    if let Some(path) = args.value("stats-json") {
+        match stats::write_json(path, render_stats, scene_stats) {
+            Ok(()) => println!("Statistics written to '{}'", path),
+            Err(err) => eprintln!("WARNING: could not write statistics '{}': {}", path, err),
+        }
+    }
+}
+
+/// Asks for the render settings on stdin then renders the scene and shows it in a window
+fn interactive(args: &Args) -> Result<(), String> {
+    args.expect_flags(&[&OUTPUT_FLAGS, &SCENE_FLAGS, &BUDGET_FLAGS, &["seed", "threads", "scene-seed", "resume", "stats-json"]])?;
+    let output = output_settings(args)?;
+    let filter = pixel_filter(args)?;
+    let sampler = sampler_kind(args)?;
+    let threads = thread_count(args)?;
+    let atmosphere = atmosphere(args)?;
+    let resume = resume(args)?;
+    let seed = match &resume {
+        Some(film) => Some(film.seed),
+        None => seed(args)?,
+    };
+    let scene_seed = scene_seed(args, resume.as_ref().map(|film| film.settings.scene_seed))?;
+    let scene = scene_kind(args)?;
+    let volume = volume_grid(args)?;
+    let (time_budget, sample_budget) = render_budgets(args)?;
+
     let mut input = String::new();
 
     print!("\x1B[2J\x1B[1;1H");
@@ -39,7 +405,7 @@ fn main() {
     match input.trim().parse::<i64>() {
         Ok(val) => width = val,
         Err(err) => {
-            eprintln!("ERROR: {}", err.to_string());
+            eprintln!("ERROR: {}", err);
             std::process::exit(1);
         }
     }
@@ -54,52 +420,216 @@ fn main() {
     match input.trim().parse::<i64>() {
         Ok(val) => samples = val,
         Err(err) => {
-            eprintln!("ERROR: {}", err.to_string());
+            eprintln!("ERROR: {}", err);
             std::process::exit(1);
         }
     }
 
-    let mut world = HittableList::new();
-    
-    // Generate some basic materials
-    let material_ground = Box::new(Lambertian::new(Colour::from(0.5, 0.5, 0.5)));
-    world.add(Box::new(Sphere { center: Vector3::from(0.0, -1000.0, 0.0), radius: 1000.0, material: material_ground}));
-
-    for a in -11..11 {
-        for b in -11..11 {
-            let choose_mat = random_f64();
-            let center = Vector3::from(a as f64 + 0.9 * random_f64(), 0.2, b as f64 + 0.9 * random_f64());
-
-            if (center - Vector3::from(4.0, 0.2, 0.0)).length() > 0.9 {
-                let sphere_material: Box<dyn Material>;
-
-                if choose_mat < 0.8 {
-                    let albedo = Colour::random() * Colour::random();
-                    sphere_material = Box::new(Lambertian::new(albedo));
-                    world.add(Box::new(Sphere {center, radius: 0.2, material: sphere_material}));
-                } else if choose_mat < 0.8 {
-                    let albedo = Colour::random_in_range(0.5..1.0);
-                    let fuzz = random_f64_in_range(0.0..0.5);
-                            
-                    sphere_material = Box::new(Metal::new(albedo, fuzz));
-                    world.add(Box::new(Sphere {center, radius: 0.2, material: sphere_material}));
-                } else {
-                    sphere_material = Box::new(Dieletric::new(1.5));
-                    world.add(Box::new(Sphere {center, radius: 0.2, material: sphere_material}));
-                }
-            }
-        }
+    let mut cam = build_camera(width, samples);
+    cam.seed = seed;
+    cam.scene = scene;
+    cam.scene_seed = scene_seed;
+    cam.volume = volume.as_ref().map(VoxelGrid::fingerprint);
+    cam.adaptive = adaptive_sampling(args, samples)?;
+    cam.transparent_background = args.has("transparent");
+    cam.filter = filter;
+    cam.sampler = sampler;
+    cam.threads = threads;
+    cam.atmosphere = atmosphere;
+    cam.time_budget = time_budget;
+    cam.sample_budget = sample_budget;
+
+    // Carrying on with different settings would mix samples of two different renders
+    if let Some(film) = &resume {
+        film.check_compatible(&cam.new_film()).map_err(|e| format!("the checkpoint doesn't match this render, {}", e))?;
+    }
+
+    let (world, scene_stats) = build_world_measured(scene, volume, scene_seed);
+
+    // Render the scene
+    let _interrupts = cancel::catch_interrupts();
+    print!("\x1B[38;2;255;255;255m");
+    let film = cam.render(world, &output, resume);
+    report_stats(args, &film.stats, &scene_stats);
+
+    return Ok(());
+}
+
+/// Renders the scene in a window where the camera can be moved around, the render starts again whenever the camera moves
+/// and the last view is saved when the window is closed
+fn explore(args: &Args) -> Result<(), String> {
+    args.expect_flags(&[&OUTPUT_FLAGS, &SCENE_FLAGS, &BUDGET_FLAGS, &["seed", "threads", "scene-seed", "width", "samples", "stats-json"]])?;
+    let output = output_settings(args)?;
+    let width = args.get("width", 400)?;
+    let samples = args.get("samples", 100)?;
+    let seed = seed(args)?;
+    let scene_seed = scene_seed(args, None)?;
+
+    let scene = scene_kind(args)?;
+    let volume = volume_grid(args)?;
+    let volume_fingerprint = volume.as_ref().map(VoxelGrid::fingerprint);
+
+    let (world, scene_stats) = build_world_measured(scene, volume, scene_seed);
+    let mut cam = build_camera(width, samples);
+    cam.seed = seed;
+    cam.scene = scene;
+    cam.scene_seed = scene_seed;
+    cam.volume = volume_fingerprint;
+    cam.adaptive = adaptive_sampling(args, samples)?;
+    cam.transparent_background = args.has("transparent");
+    cam.filter = pixel_filter(args)?;
+    cam.sampler = sampler_kind(args)?;
+    cam.threads = thread_count(args)?;
+    cam.atmosphere = atmosphere(args)?;
+    (cam.time_budget, cam.sample_budget) = render_budgets(args)?;
+
+    let _interrupts = cancel::catch_interrupts();
+    print!("\x1B[38;2;255;255;255m");
+    // Only the last view counts, renders thrown away when the camera moved aren't included
+    let film = cam.explore(world, &output);
+    report_stats(args, &film.stats, &scene_stats);
+
+    return Ok(());
+}
+
+/// Adds checkpoints of the same scene together into one image with more samples, and optionally a merged checkpoint
+fn merge(args: &Args) -> Result<(), String> {
+    args.expect_flags(&[&OUTPUT_FLAGS, &["inputs"]])?;
+    let output = output_settings(args)?;
+    let inputs = args.get("inputs", String::new())?;
+    let mut paths = inputs.split(',').map(|p| p.trim()).filter(|p| !p.is_empty());
+
+    let load = |path: &str| checkpoint::load_checkpoint(path).map_err(|e| format!("could not load checkpoint '{}': {}", path, e));
+    let mut film = match paths.next() {
+        Some(path) => load(path)?,
+        None => return Err(String::from("--inputs should list the checkpoints to merge, separated by commas")),
+    };
+
+    for path in paths {
+        film.merge(&load(path)?).map_err(|e| format!("could not merge '{}': {}", path, e))?;
+    }
+
+    println!("Merged film has {:.1} samples per pixel", film.average_samples());
+    save::save_film(&film, &output).map_err(|e| format!("could not save '{}': {}", output.path, e))?;
+    println!("Saved as '{}'", output.path);
+
+    if let Some(path) = &output.checkpoint {
+        checkpoint::save_checkpoint(path, &film).map_err(|e| format!("could not write checkpoint '{}': {}", path, e))?;
+        println!("Checkpoint written to '{}'", path);
+    }
+
+    return Ok(());
+}
+
+/// Splits a render across `raytracer worker` processes by handing each one chunks of samples, then saves the merged film
+fn distribute(args: &Args) -> Result<(), String> {
+    args.expect_flags(&[&OUTPUT_FLAGS, &SCENE_FLAGS, &["seed", "scene-seed", "width", "samples", "chunk", "workers", "worker-timeout", "io-timeout"]])?;
+    let output = output_settings(args)?;
+    let width: i64 = args.get("width", 400)?;
+    let samples: i64 = args.get("samples", 100)?;
+    let chunk_size: u64 = args.get("chunk", 8)?;
+    let timeouts = distributed::Timeouts {
+        io: Duration::from_secs_f64(args.get("io-timeout", 30.0_f64)?.max(1.0)),
+        chunk: Duration::from_secs_f64(args.get("worker-timeout", 600.0_f64)?.max(1.0)),
+    };
+
+    // Each chunk is a separate film, so no worker sees enough of a pixel's samples to tell when it has converged
+    if args.has("adaptive") {
+        return Err(String::from("--adaptive can't be used with distribute, pixels are only known to converge within one film"));
+    }
+
+    let workers: Vec<String> = args.get("workers", String::new())?.split(',').map(|w| w.trim().to_string()).filter(|w| !w.is_empty()).collect();
+    if workers.is_empty() {
+        return Err(String::from("--workers should list the workers' addresses, separated by commas"));
+    }
+
+    // Every worker has to build the same scene and derive the same samples from it, so the render always has both seeds
+    let seed = seed(args)?.unwrap_or_else(rand::random);
+    let scene_seed = scene_seed(args, None)?;
+    let mut job = vec![
+        String::from("--seed"), seed.to_string(),
+        String::from("--scene-seed"), scene_seed.to_string(),
+        String::from("--width"), width.to_string(),
+        String::from("--samples"), samples.to_string(),
+    ];
+    for name in SCENE_FLAGS {
+        let Some(value) = args.value(name) else { continue };
+
+        // Workers may run in another directory, so they're sent the whole path of the volume, which they must also have
+        let value = match name {
+            "volume" => std::path::absolute(value).map_err(|e| format!("could not find the path of '{}': {}", value, e))?.to_string_lossy().into_owned(),
+            _ => value.to_string(),
+        };
+        job.extend([format!("--{}", name), value]);
+    }
+
+    println!("Rendering {} samples per pixel with scene seed {} and sample seed {} on {} workers", samples, scene_seed, seed, workers.len());
+    let chunks = distributed::split_samples(samples.max(0) as u64, chunk_size);
+    let _interrupts = cancel::catch_interrupts();
+    let film = distributed::render_distributed(&workers, &job, chunks, timeouts, &mut ConsoleProgress::new("chunks"))?;
+
+    save::save_film(&film, &output).map_err(|e| format!("could not save '{}': {}", output.path, e))?;
+    println!("Saved as '{}'", output.path);
+
+    if let Some(path) = &output.checkpoint {
+        checkpoint::save_checkpoint(path, &film).map_err(|e| format!("could not write checkpoint '{}': {}", path, e))?;
+        println!("Checkpoint written to '{}'", path);
     }
 
-    let material1 = Box::new(Dieletric::new(1.5));
-    world.add(Box::new(Sphere {center: Vector3::from(0.0, 1.0, 0.0), radius: 1.0, material: material1}));
+    return Ok(());
+}
+
+/// Waits for `raytracer distribute` to send it chunks of samples to render
+fn worker(args: &Args) -> Result<(), String> {
+    args.expect_flags(&[&["listen"]])?;
+    let address = args.get("listen", String::from("127.0.0.1:7878"))?;
+    return distributed::serve(&address, render_chunk).map_err(|e| format!("could not listen on '{}': {}", address, e));
+}
+
+/// Renders the chunk of samples a job from a coordinator asks for
+///
+/// ## Arguments
+/// - `job` Flags describing the scene along with `--first-sample` and `--pass-samples`, which pick the chunk
+fn render_chunk(job: &Args) -> Result<Film, String> {
+    job.expect_flags(&[&SCENE_FLAGS, &["seed", "scene-seed", "width", "samples", "first-sample", "pass-samples"]])?;
+    if !job.has("seed") || !job.has("scene-seed") {
+        return Err(String::from("jobs need both seeds so every worker builds the same scene and takes the same samples"));
+    }
+
+    let samples = job.get("samples", 100)?;
+    let scene_seed = scene_seed(job, None)?;
+    let scene = scene_kind(job)?;
+    let volume = volume_grid(job)?;
+    let volume_fingerprint = volume.as_ref().map(VoxelGrid::fingerprint);
+    let world = Arc::new(scene::build_world(scene, volume, scene_seed));
+
+    let mut cam = build_camera(job.get("width", 400)?, samples);
+    cam.seed = seed(job)?;
+    cam.scene = scene;
+    cam.scene_seed = scene_seed;
+    cam.volume = volume_fingerprint;
+    cam.adaptive = adaptive_sampling(job, samples)?;
+    cam.transparent_background = job.has("transparent");
+    cam.filter = pixel_filter(job)?;
+    cam.sampler = sampler_kind(job)?;
+    cam.atmosphere = atmosphere(job)?;
 
-    let material2 = Box::new(Lambertian::new(Colour::from(0.4, 0.2, 0.1)));
-    world.add(Box::new(Sphere {center: Vector3::from(-4.0, 1.0, 0.0), radius: 1.0, material: material2}));
-    let material3 = Box::new(Metal::new(Colour::from(0.7, 0.6, 0.5), 0.0));
-    world.add(Box::new(Sphere {center: Vector3::from(4.0, 1.0, 0.0), radius: 1.0, material: material3}));
-     
+    let first_sample: u64 = job.get("first-sample", 0)?;
+    let mut film = cam.new_film();
+    film.first_sample = first_sample;
+    film.next_sample = first_sample;
 
+    // Only the one pass holding the chunk is rendered
+    let mut progress = ConsoleProgress::new("rows");
+    return Ok(cam.continue_passes(&world, film, job.get("pass-samples", samples)?, &CancelToken::new(), &mut progress, |_| false));
+}
+
+/// Creates the camera used to look at the scene
+///
+/// ## Arguments
+/// - `width` Width of the image in pixels
+/// - `samples` Samples per pixel
+fn build_camera(width: i64, samples: i64) -> Camera {
     let mut cam: Camera = Camera {..Default::default()};
     
     // Render settings
@@ -115,8 +645,79 @@ fn main() {
     
     cam.defocus_angle = 0.6;
     cam.focus_dist = 10.0;
-    
-    // Render the scene
+
+    return cam;
+}
+
+/// Renders an orbit around the scene with a spinning torus as a numbered sequence of frames
+fn animate(args: &Args) -> Result<(), String> {
+    args.expect_flags(&[
+        &BUDGET_FLAGS,
+        &["scene", "volume", "volume-resolution", "fog", "fog-height", "filter", "filter-radius", "sampler", "adaptive", "min-samples", "threshold", "seed", "threads", "scene-seed"],
+        &["width", "samples", "output", "format", "frames", "interpolation", "tonemap", "exposure", "stats-json"],
+    ])?;
+    let width = args.get("width", 400)?;
+    let samples = args.get("samples", 10)?;
+    let output_dir = args.get("output", String::from("frames"))?;
+    let format = args.get("format", String::from("png"))?;
+    save::check_output_path(&format!("frame.{}", format), false)?;
+
+    let frames = parse_frame_range(&args.get("frames", String::from("1-48"))?)?;
+    let interpolation_name = args.get("interpolation", String::from("bezier"))?;
+    let interpolation = Interpolation::from_name(&interpolation_name).ok_or(format!("unknown interpolation '{}'", interpolation_name))?;
+
+    // A single frame still gets keys a frame apart, so the tracks never divide by a zero length
+    let (first, last) = (*frames.start(), *frames.end());
+    let length = (last - first).max(1) as f64;
+
+    let seed = seed(args)?;
+    let scene_seed = scene_seed(args, None)?;
+    let scene_kind = scene_kind(args)?;
+    let volume = volume_grid(args)?;
+    let volume_fingerprint = volume.as_ref().map(VoxelGrid::fingerprint);
+    let (world, scene_stats) = build_world_measured(scene_kind, volume, scene_seed);
+    let mut scene = AnimatedScene::new(world);
+
+    let torus_material = Box::new(Metal::new(Colour::from(0.8, 0.6, 0.2), 0.1));
+    let torus = Box::new(Torus::new(Vector3::new(), Vector3::from(0.0, 1.0, 0.0), 0.6, 0.15, torus_material));
+    let spin = Track::new()
+        .key(first as f64, Transform::new(Vector3::from(2.0, 1.0, 2.0), Vector3::new(), Vector3::from(1.0, 1.0, 1.0)), Interpolation::Linear)
+        .key(first as f64 + length, Transform::new(Vector3::from(2.0, 1.0, 2.0), Vector3::from(360.0, 0.0, 0.0), Vector3::from(1.0, 1.0, 1.0)), Interpolation::Linear);
+    scene.add_animated(torus, spin);
+
+    // Orbit the camera a quarter turn around the scene
+    let quarter = length / 3.0;
+    let mut orbit = Track::new();
+    for step in 0..4 {
+        let angle = (step as f64 * 30.0).to_radians() + 13.0_f64.atan2(3.0);
+        let position = Vector3::from(13.4 * angle.sin(), 2.0, 13.4 * angle.cos());
+        orbit = orbit.key(first as f64 + step as f64 * quarter, position, interpolation);
+    }
+
+    let camera_animation = CameraAnimation {
+        look_from: Some(orbit),
+        ..Default::default()
+    };
+
+    let mut camera = build_camera(width, samples);
+    // Keep objects sharp enough to follow while they move
+    camera.shutter_close = 0.5;
+    camera.filter = pixel_filter(args)?;
+    camera.sampler = sampler_kind(args)?;
+    camera.threads = thread_count(args)?;
+    camera.atmosphere = atmosphere(args)?;
+    camera.seed = seed;
+    camera.scene = scene_kind;
+    camera.scene_seed = scene_seed;
+    camera.volume = volume_fingerprint;
+    camera.adaptive = adaptive_sampling(args, samples)?;
+    (camera.time_budget, camera.sample_budget) = render_budgets(args)?;
+
+    let _interrupts = cancel::catch_interrupts();
     print!("\x1B[38;2;255;255;255m");
-    cam.render(world);
+    let render_stats = render_animation(camera, &camera_animation, &scene, frames, &output_dir, &format, display_settings(args)?).map_err(|e| e.to_string())?;
+    report_stats(args, &render_stats, &scene_stats);
+
+    return Ok(());
 }
+
@@ -1,18 +1,52 @@
-use crate::{ray::Ray, hittable::HitRecord, colour::Colour, vector3::{random_unit_vector, reflect, dot_product, refract, Vector3}, random::random_f64};
+use std::f64::consts::PI;
+
+use crate::{ray::Ray, hittable::HitRecord, colour::Colour, vector3::{random_unit_vector, reflect, dot_product, refract, Vector3}, random::random_f64, onb::Onb};
 
 
 pub trait Material: MaterialClone + Send + Sync {
     fn scatter(&mut self, ray_in: &Ray, record: &HitRecord, attenuation: &mut Colour, scattered: &mut Ray) -> bool;
+
+    /// Returns the light given off by the material at a point, most materials don't emit any
+    fn emitted(&self, _u: f64, _v: f64, _point: Vector3) -> Colour {
+        return Colour::new();
+    }
+
+    /// Returns the base colour of the material at a point, used for the albedo render pass
+    fn albedo(&self, _u: f64, _v: f64, _point: Vector3) -> Colour {
+        return Colour::from(1.0, 1.0, 1.0);
+    }
+
+    /// Returns whether the material reflects or refracts light in a single direction rather than scattering it diffusely
+    fn is_specular(&self) -> bool {
+        return false;
+    }
+
+    /// Describes the material and its parameters, shown when a pixel is inspected in the viewer
+    fn describe(&self) -> String {
+        return String::from(self.type_name().rsplit("::").next().unwrap_or("Material"));
+    }
+}
+
+/// Formats a colour with a few decimal places for descriptions
+pub fn format_colour(colour: Colour) -> String {
+    return format!("({:.3}, {:.3}, {:.3})", colour.x, colour.y, colour.z);
 }
 
 pub trait MaterialClone {
     fn clone_box(&self) -> Box<dyn Material>;
+
+    /// Returns the name of the material's type, used to tell materials apart in the material id render pass
+    fn type_name(&self) -> &'static str;
 }
 
 impl<T> MaterialClone for T where T: 'static + Material + Clone {
     fn clone_box(&self) -> Box<dyn Material> {
         Box::new(self.clone())
     }
+
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<T>()
+    }
 }
 
 impl Clone for Box<dyn Material> {
@@ -42,10 +76,18 @@ impl Material for Lambertian {
             scatter_direction = record.normal;
         }
 
-        *scattered = Ray::from(record.point, scatter_direction);
+        *scattered = Ray::with_time(record.point, scatter_direction, ray_in.time);
         *attenuation = self.albedo; 
         return true;
     }
+
+    fn albedo(&self, _u: f64, _v: f64, _point: Vector3) -> Colour {
+        return self.albedo;
+    }
+
+    fn describe(&self) -> String {
+        return format!("Lambertian, albedo {}", format_colour(self.albedo));
+    }
 }
 
 #[derive(Clone)]
@@ -67,10 +109,22 @@ impl Material for Metal {
     fn scatter(&mut self, ray_in: &Ray, record: &HitRecord, attenuation: &mut Colour, scattered: &mut Ray) -> bool {
         let reflected = reflect(ray_in.direction.unit(), record.normal);
 
-        *scattered = Ray::from(record.point, reflected + self.fuzz * random_unit_vector());
+        *scattered = Ray::with_time(record.point, reflected + self.fuzz * random_unit_vector(), ray_in.time);
         *attenuation = self.albedo;
         return dot_product(scattered.direction, record.normal) > 0.0;
     }
+
+    fn albedo(&self, _u: f64, _v: f64, _point: Vector3) -> Colour {
+        return self.albedo;
+    }
+
+    fn is_specular(&self) -> bool {
+        return true;
+    }
+
+    fn describe(&self) -> String {
+        return format!("Metal, albedo {}, fuzz {:.3}", format_colour(self.albedo), self.fuzz);
+    }
 }
 
 #[derive(Clone)]
@@ -116,8 +170,99 @@ impl Material for Dieletric {
             direction = refract(unit_direction, record.normal, refraction_ratio);
         }
 
-        *scattered = Ray::from(record.point, direction);
+        *scattered = Ray::with_time(record.point, direction, ray_in.time);
 
         return true
     } 
+
+    fn is_specular(&self) -> bool {
+        return true;
+    }
+
+    fn describe(&self) -> String {
+        return format!("Dielectric, index of refraction {:.3}", self.index_of_refraction);
+    }
+}
+
+/// Scatters light equally in every direction, used as the phase function of participating media
+#[derive(Copy, Clone)]
+pub struct Isotropic {
+    pub albedo: Colour,
+}
+
+impl Isotropic {
+    pub fn new(albedo: Colour) -> Self {
+        Self {
+            albedo,
+        }
+    }
+}
+
+impl Material for Isotropic {
+    fn scatter(&mut self, ray_in: &Ray, record: &HitRecord, attenuation: &mut Colour, scattered: &mut Ray) -> bool {
+        *scattered = Ray::with_time(record.point, random_unit_vector(), ray_in.time);
+        *attenuation = self.albedo;
+        return true;
+    }
+
+    fn albedo(&self, _u: f64, _v: f64, _point: Vector3) -> Colour {
+        return self.albedo;
+    }
+
+    fn describe(&self) -> String {
+        return format!("Isotropic, albedo {}", format_colour(self.albedo));
+    }
+}
+
+/// Henyey-Greenstein phase function for participating media which scatter more forwards or backwards.
+/// `g` goes from -1 (everything scatters back) through 0 (isotropic) to 1 (everything scatters forward)
+#[derive(Copy, Clone)]
+pub struct HenyeyGreenstein {
+    pub albedo: Colour,
+    pub g: f64,
+}
+
+impl HenyeyGreenstein {
+    pub fn new(albedo: Colour, g: f64) -> Self {
+        Self {
+            albedo,
+            g: g.clamp(-0.999, 0.999),
+        }
+    }
+
+    /// Samples the cosine of the angle between the incoming and scattered directions
+    fn sample_cos_theta(&self) -> f64 {
+        let xi = random_f64();
+
+        if self.g.abs() < 1e-3 {
+            return 1.0 - 2.0 * xi;
+        }
+
+        let g = self.g;
+        let sqr_term = (1.0 - g * g) / (1.0 - g + 2.0 * g * xi);
+        return ((1.0 + g * g - sqr_term * sqr_term) / (2.0 * g)).clamp(-1.0, 1.0);
+    }
+}
+
+impl Material for HenyeyGreenstein {
+    fn scatter(&mut self, ray_in: &Ray, record: &HitRecord, attenuation: &mut Colour, scattered: &mut Ray) -> bool {
+        let cos_theta = self.sample_cos_theta();
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * random_f64();
+
+        let basis = Onb::from_w(ray_in.direction);
+        let direction = basis.local(Vector3::from(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta));
+
+        *scattered = Ray::with_time(record.point, direction, ray_in.time);
+        *attenuation = self.albedo;
+        return true;
+    }
+
+    fn albedo(&self, _u: f64, _v: f64, _point: Vector3) -> Colour {
+        return self.albedo;
+    }
+
+    fn describe(&self) -> String {
+        return format!("Henyey-Greenstein, albedo {}, g {:.3}", format_colour(self.albedo), self.g);
+    }
 }
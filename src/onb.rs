@@ -0,0 +1,34 @@
+use crate::vector3::{cross_product, dot_product, Vector3};
+
+/// Orthonormal basis, used to move points and directions into the local space of an object
+#[derive(Copy, Clone, Debug)]
+pub struct Onb {
+    pub u: Vector3,
+    pub v: Vector3,
+    pub w: Vector3,
+}
+
+impl Onb {
+    /// Builds a basis where `w` points along the given direction
+    ///
+    /// ## Arguments
+    /// - `n` Direction of the W axis, does not need to be a unit vector
+    pub fn from_w(n: Vector3) -> Self {
+        let w = n.unit();
+        let helper = if w.x.abs() > 0.9 { Vector3::from(0.0, 1.0, 0.0) } else { Vector3::from(1.0, 0.0, 0.0) };
+        let v = cross_product(w, helper).unit();
+        let u = cross_product(w, v);
+
+        Self { u, v, w }
+    }
+
+    /// Converts a vector in local coordinates into world coordinates
+    pub fn local(&self, a: Vector3) -> Vector3 {
+        return a.x * self.u + a.y * self.v + a.z * self.w;
+    }
+
+    /// Converts a vector in world coordinates into local coordinates
+    pub fn world_to_local(&self, a: Vector3) -> Vector3 {
+        return Vector3::from(dot_product(a, self.u), dot_product(a, self.v), dot_product(a, self.w));
+    }
+}
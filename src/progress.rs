@@ -0,0 +1,158 @@
+use std::{
+    io::{self, Write},
+    time::{Duration, Instant},
+};
+
+/// How far through a render is, counted in whole units of work such as rows of a pass or chunks of samples
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Progress {
+    pub completed: u64,
+    /// Units of work in the whole render, unused when it runs until a time budget is spent
+    pub total: u64,
+    /// Samples taken in all the completed units
+    pub samples: u64,
+    /// Time since the render started
+    pub elapsed: Duration,
+    /// Set when the render keeps going until this much time has passed, progress is then measured in time rather than units
+    pub time_budget: Option<Duration>,
+}
+
+impl Progress {
+    /// Returns the fraction of the work which is done, between 0 and 1
+    pub fn fraction(&self) -> f64 {
+        if let Some(budget) = self.time_budget {
+            return (self.elapsed.as_secs_f64() / budget.as_secs_f64()).min(1.0);
+        }
+        if self.total == 0 {
+            return 1.0;
+        }
+        return (self.completed as f64 / self.total as f64).min(1.0);
+    }
+
+    /// Returns how long the rest of the work should take at the rate so far, or None before any has been done
+    pub fn eta(&self) -> Option<Duration> {
+        if let Some(budget) = self.time_budget {
+            return Some(budget.saturating_sub(self.elapsed));
+        }
+        if self.completed == 0 {
+            return None;
+        }
+        let remaining = self.total.saturating_sub(self.completed) as f64 / self.completed as f64;
+        return Some(self.elapsed.mul_f64(remaining));
+    }
+
+    /// Returns how many samples have been taken per second so far
+    pub fn samples_per_second(&self) -> f64 {
+        let seconds = self.elapsed.as_secs_f64();
+        if seconds == 0.0 {
+            return 0.0;
+        }
+        return self.samples as f64 / seconds;
+    }
+}
+
+/// Receives progress as a render goes, so it can be shown in a terminal, a window or sent elsewhere
+pub trait ProgressReporter {
+    /// Called every time a unit of work completes
+    fn update(&mut self, progress: &Progress);
+
+    /// Called once when the render stops, whether or not every unit was completed
+    fn finish(&mut self, _progress: &Progress) {}
+}
+
+/// Any closure taking a `Progress` can be used as a reporter
+impl<F: FnMut(&Progress)> ProgressReporter for F {
+    fn update(&mut self, progress: &Progress) {
+        self(progress);
+    }
+}
+
+/// Shortest time between redraws of the progress line, so fast renders don't spend their time printing
+const CONSOLE_REFRESH: Duration = Duration::from_millis(100);
+
+/// Keeps a single line on stdout showing the percentage done, the sample rate and the time left
+pub struct ConsoleProgress {
+    /// What the units of work are called, such as "rows"
+    units: &'static str,
+    last_print: Option<Instant>,
+}
+
+impl ConsoleProgress {
+    pub fn new(units: &'static str) -> Self {
+        return Self { units, last_print: None };
+    }
+
+    fn print(&mut self, progress: &Progress) {
+        let eta = match progress.eta() {
+            Some(eta) => format_duration(eta),
+            None => String::from("--:--"),
+        };
+
+        let done = match progress.time_budget {
+            Some(_) => format!("{} {}", progress.completed, self.units),
+            None => format!("{}/{} {}", progress.completed, progress.total, self.units),
+        };
+
+        print!(
+            "\r\x1B[2K{:5.1}% ({}), {}, ETA {}",
+            100.0 * progress.fraction(),
+            done,
+            format_rate(progress.samples_per_second()),
+            eta
+        );
+        io::stdout().flush().unwrap();
+        self.last_print = Some(Instant::now());
+    }
+}
+
+impl ProgressReporter for ConsoleProgress {
+    fn update(&mut self, progress: &Progress) {
+        let due = self.last_print.is_none_or(|last| last.elapsed() >= CONSOLE_REFRESH);
+        if due || (progress.time_budget.is_none() && progress.completed >= progress.total) {
+            self.print(progress);
+        }
+    }
+
+    fn finish(&mut self, progress: &Progress) {
+        self.print(progress);
+        println!();
+    }
+}
+
+/// Formats a sample rate, switching to millions once it is that fast
+fn format_rate(samples_per_second: f64) -> String {
+    if samples_per_second >= 1e6 {
+        return format!("{:.2} Msamples/s", samples_per_second / 1e6);
+    }
+    return format!("{:.0} samples/s", samples_per_second);
+}
+
+/// Formats a duration as minutes and seconds, with hours in front once it is that long
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    if seconds >= 3600 {
+        return format!("{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60);
+    }
+    return format!("{}:{:02}", seconds / 60, seconds % 60);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn time_budget_measures_progress_in_time() {
+        let progress = Progress { completed: 50, total: 10, elapsed: Duration::from_secs(3), time_budget: Some(Duration::from_secs(4)), ..Default::default() };
+
+        assert_eq!(progress.fraction(), 0.75);
+        assert_eq!(progress.eta(), Some(Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn units_measure_progress_without_a_time_budget() {
+        let progress = Progress { completed: 1, total: 4, elapsed: Duration::from_secs(2), ..Default::default() };
+
+        assert_eq!(progress.fraction(), 0.25);
+        assert_eq!(progress.eta(), Some(Duration::from_secs(6)));
+    }
+}
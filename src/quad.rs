@@ -0,0 +1,326 @@
+use std::f64::consts::PI;
+
+use crate::{
+    aabb::Aabb,
+    hittable::{HitRecord, Hittable},
+    interval::Interval,
+    material::Material,
+    onb::Onb,
+    ray::Ray,
+    vector3::{cross_product, dot_product, Vector3},
+};
+
+/// Intersects a ray with the plane `dot(normal, p) = d`, returning the t value if it is within `ray_t`
+fn hit_plane(ray: &Ray, ray_t: Interval, normal: Vector3, d: f64) -> Option<f64> {
+    let denom = dot_product(normal, ray.direction);
+
+    // The ray is parallel to the plane
+    if denom.abs() < 1e-8 {
+        return None;
+    }
+
+    let t = (d - dot_product(normal, ray.origin)) / denom;
+    if !ray_t.surrounds(t) {
+        return None;
+    }
+
+    return Some(t);
+}
+
+/// A parallelogram defined by a corner `q` and the two edges `u` and `v`
+#[derive(Clone)]
+pub struct Quad {
+    pub q: Vector3,
+    pub u: Vector3,
+    pub v: Vector3,
+    pub material: Box<dyn Material>,
+    normal: Vector3,
+    d: f64,
+    w: Vector3,
+    bbox: Aabb,
+}
+
+impl Quad {
+    /// Creates a quad
+    ///
+    /// ## Arguments
+    /// - `q` Starting corner of the quad
+    /// - `u` First edge of the quad
+    /// - `v` Second edge of the quad
+    /// - `material` Material of the quad
+    pub fn new(q: Vector3, u: Vector3, v: Vector3, material: Box<dyn Material>) -> Self {
+        let n = cross_product(u, v);
+        let normal = n.unit();
+        let d = dot_product(normal, q);
+        let w = n / dot_product(n, n);
+
+        let bbox = Aabb::from_boxes(Aabb::from_points(q, q + u + v), Aabb::from_points(q + u, q + v)).pad();
+
+        Self {
+            q,
+            u,
+            v,
+            material,
+            normal,
+            d,
+            w,
+            bbox,
+        }
+    }
+}
+
+impl Hittable for Quad {
+    /// Intersects the plane of the quad then checks that the planar coordinates of the hit fall within the quad
+    fn hit(&self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        let t = match hit_plane(ray, ray_t, self.normal, self.d) {
+            Some(t) => t,
+            None => return false,
+        };
+
+        let intersection = ray.at(t);
+        let planar_hit = intersection - self.q;
+        let alpha = dot_product(self.w, cross_product(planar_hit, self.v));
+        let beta = dot_product(self.w, cross_product(self.u, planar_hit));
+
+        let unit_interval = Interval::from(0.0, 1.0);
+        if !unit_interval.contains(alpha) || !unit_interval.contains(beta) {
+            return false;
+        }
+
+        rec.t = t;
+        rec.point = intersection;
+        rec.u = alpha;
+        rec.v = beta;
+        rec.set_face_normal(ray, self.normal);
+        rec.material = self.material.clone();
+
+        return true;
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        return self.bbox;
+    }
+}
+
+/// An infinite plane passing through `point`. UVs are the planar coordinates of the hit in world units
+#[derive(Clone)]
+pub struct Plane {
+    pub point: Vector3,
+    pub normal: Vector3,
+    pub material: Box<dyn Material>,
+    basis: Onb,
+}
+
+impl Plane {
+    /// Creates a plane
+    ///
+    /// ## Arguments
+    /// - `point` Any point on the plane
+    /// - `normal` Direction the plane faces, does not need to be a unit vector
+    /// - `material` Material of the plane
+    pub fn new(point: Vector3, normal: Vector3, material: Box<dyn Material>) -> Self {
+        Self {
+            point,
+            normal: normal.unit(),
+            material,
+            basis: Onb::from_w(normal),
+        }
+    }
+}
+
+impl Hittable for Plane {
+    fn hit(&self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        let t = match hit_plane(ray, ray_t, self.normal, dot_product(self.normal, self.point)) {
+            Some(t) => t,
+            None => return false,
+        };
+
+        rec.t = t;
+        rec.point = ray.at(t);
+        rec.u = dot_product(rec.point - self.point, self.basis.u);
+        rec.v = dot_product(rec.point - self.point, self.basis.v);
+        rec.set_face_normal(ray, self.normal);
+        rec.material = self.material.clone();
+
+        return true;
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        return Aabb::UNIVERSE;
+    }
+}
+
+/// A flat circle. U is the angle around the center and V is the distance from the center, both from 0 to 1
+#[derive(Clone)]
+pub struct Disk {
+    pub center: Vector3,
+    pub normal: Vector3,
+    pub radius: f64,
+    pub material: Box<dyn Material>,
+    basis: Onb,
+}
+
+impl Disk {
+    /// Creates a disk
+    ///
+    /// ## Arguments
+    /// - `center` Center of the disk
+    /// - `normal` Direction the disk faces, does not need to be a unit vector
+    /// - `radius` Radius of the disk
+    /// - `material` Material of the disk
+    pub fn new(center: Vector3, normal: Vector3, radius: f64, material: Box<dyn Material>) -> Self {
+        Self {
+            center,
+            normal: normal.unit(),
+            radius,
+            material,
+            basis: Onb::from_w(normal),
+        }
+    }
+}
+
+impl Hittable for Disk {
+    fn hit(&self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        let t = match hit_plane(ray, ray_t, self.normal, dot_product(self.normal, self.center)) {
+            Some(t) => t,
+            None => return false,
+        };
+
+        let point = ray.at(t);
+        let offset = point - self.center;
+        if offset.length_squared() > self.radius * self.radius {
+            return false;
+        }
+
+        let phi = dot_product(offset, self.basis.v).atan2(dot_product(offset, self.basis.u)) + PI;
+
+        rec.t = t;
+        rec.point = point;
+        rec.u = phi / (2.0 * PI);
+        rec.v = offset.length() / self.radius;
+        rec.set_face_normal(ray, self.normal);
+        rec.material = self.material.clone();
+
+        return true;
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        // The extent of a disk along each axis is radius * sqrt(1 - n²) for that axis
+        let extent = |n: f64| self.radius * (1.0 - n * n).max(0.0).sqrt();
+        let e = Vector3::from(extent(self.normal.x), extent(self.normal.y), extent(self.normal.z));
+
+        return Aabb::from_points(self.center - e, self.center + e).pad();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colour::Colour;
+    use crate::material::Lambertian;
+
+    fn grey() -> Box<dyn Material> {
+        return Box::new(Lambertian::new(Colour::from(0.5, 0.5, 0.5)));
+    }
+
+    fn first_hit(object: &dyn Hittable, ray: &Ray) -> Option<HitRecord> {
+        let mut rec = HitRecord::new();
+        if !object.hit(ray, Interval::from(0.001, f64::INFINITY), &mut rec) {
+            return None;
+        }
+        return Some(rec);
+    }
+
+    fn assert_close(a: Vector3, b: Vector3) {
+        assert!((a - b).length() < 1e-9, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn quad_uvs_are_the_planar_coordinates_of_the_hit() {
+        let quad = Quad::new(Vector3::new(), Vector3::from(2.0, 0.0, 0.0), Vector3::from(0.0, 4.0, 0.0), grey());
+        let rec = first_hit(&quad, &Ray::from(Vector3::from(0.5, 3.0, 5.0), Vector3::from(0.0, 0.0, -1.0))).unwrap();
+
+        assert!((rec.t - 5.0).abs() < 1e-9);
+        assert!((rec.u - 0.25).abs() < 1e-9 && (rec.v - 0.75).abs() < 1e-9, "({}, {})", rec.u, rec.v);
+        assert!(first_hit(&quad, &Ray::from(Vector3::from(2.5, 3.0, 5.0), Vector3::from(0.0, 0.0, -1.0))).is_none());
+    }
+
+    #[test]
+    fn quad_normal_faces_the_ray() {
+        // u x v points along +z, so a ray travelling along +z hits the back face
+        let quad = Quad::new(Vector3::new(), Vector3::from(1.0, 0.0, 0.0), Vector3::from(0.0, 1.0, 0.0), grey());
+
+        let front = first_hit(&quad, &Ray::from(Vector3::from(0.5, 0.5, 1.0), Vector3::from(0.0, 0.0, -1.0))).unwrap();
+        assert!(front.front_face);
+        assert_close(front.normal, Vector3::from(0.0, 0.0, 1.0));
+
+        let back = first_hit(&quad, &Ray::from(Vector3::from(0.5, 0.5, -1.0), Vector3::from(0.0, 0.0, 1.0))).unwrap();
+        assert!(!back.front_face);
+        assert_close(back.normal, Vector3::from(0.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn quad_bounding_box_holds_every_corner_and_is_padded() {
+        let quad = Quad::new(Vector3::from(1.0, 0.0, 0.0), Vector3::from(-1.0, 0.0, 1.0), Vector3::from(0.0, 2.0, 0.0), grey());
+        let bbox = quad.bounding_box();
+
+        assert_eq!((bbox.x.min, bbox.x.max), (0.0, 1.0));
+        assert_eq!((bbox.y.min, bbox.y.max), (0.0, 2.0));
+        assert_eq!((bbox.z.min, bbox.z.max), (0.0, 1.0));
+
+        let flat = Quad::new(Vector3::new(), Vector3::from(1.0, 0.0, 0.0), Vector3::from(0.0, 0.0, 1.0), grey()).bounding_box();
+        assert!(flat.y.size() > 0.0);
+    }
+
+    #[test]
+    fn plane_uvs_are_distances_along_the_plane() {
+        let plane = Plane::new(Vector3::from(0.0, 1.0, 0.0), Vector3::from(0.0, 2.0, 0.0), grey());
+        let down = Vector3::from(0.0, -1.0, 0.0);
+
+        let origin = first_hit(&plane, &Ray::from(Vector3::from(0.0, 5.0, 0.0), down)).unwrap();
+        let other = first_hit(&plane, &Ray::from(Vector3::from(3.0, 5.0, -4.0), down)).unwrap();
+
+        assert!(origin.u.abs() < 1e-9 && origin.v.abs() < 1e-9);
+        let distance = ((other.u - origin.u).powi(2) + (other.v - origin.v).powi(2)).sqrt();
+        assert!((distance - 5.0).abs() < 1e-9, "{}", distance);
+        assert_close(other.normal, Vector3::from(0.0, 1.0, 0.0));
+        assert!(other.front_face);
+    }
+
+    #[test]
+    fn plane_is_unbounded_and_misses_parallel_rays() {
+        let plane = Plane::new(Vector3::new(), Vector3::from(0.0, 1.0, 0.0), grey());
+
+        assert_eq!(plane.bounding_box().x.min, f64::NEG_INFINITY);
+        assert!(first_hit(&plane, &Ray::from(Vector3::from(0.0, 1.0, 0.0), Vector3::from(1.0, 0.0, 0.0))).is_none());
+    }
+
+    #[test]
+    fn disk_uvs_are_the_angle_and_distance_from_the_center() {
+        let disk = Disk::new(Vector3::new(), Vector3::from(0.0, 1.0, 0.0), 2.0, grey());
+        let down = Vector3::from(0.0, -1.0, 0.0);
+
+        let near = first_hit(&disk, &Ray::from(Vector3::from(0.0, 1.0, 1.0), down)).unwrap();
+        let far = first_hit(&disk, &Ray::from(Vector3::from(0.0, 1.0, -1.5), down)).unwrap();
+
+        assert!((near.v - 0.5).abs() < 1e-9 && (far.v - 0.75).abs() < 1e-9);
+        assert!((0.0..=1.0).contains(&near.u) && (0.0..=1.0).contains(&far.u));
+        // Opposite sides of the center are half a turn apart
+        assert!(((near.u - far.u).abs() - 0.5).abs() < 1e-9, "{} {}", near.u, far.u);
+        assert_close(near.normal, Vector3::from(0.0, 1.0, 0.0));
+
+        assert!(first_hit(&disk, &Ray::from(Vector3::from(2.1, 1.0, 0.0), down)).is_none());
+    }
+
+    #[test]
+    fn disk_bounding_box_follows_its_tilt() {
+        let flat = Disk::new(Vector3::new(), Vector3::from(0.0, 1.0, 0.0), 2.0, grey()).bounding_box();
+        assert_eq!((flat.x.min, flat.x.max), (-2.0, 2.0));
+        assert!(flat.y.size() > 0.0 && flat.y.size() < 0.01);
+
+        let tilted = Disk::new(Vector3::new(), Vector3::from(1.0, 1.0, 0.0), 2.0, grey()).bounding_box();
+        let half_diagonal = 2.0 * 0.5_f64.sqrt();
+        assert!((tilted.x.max - half_diagonal).abs() < 1e-9 && (tilted.y.max - half_diagonal).abs() < 1e-9);
+        assert_eq!(tilted.z.max, 2.0);
+    }
+}
@@ -1,14 +1,67 @@
+use std::cell::RefCell;
 use std::ops::Range;
 
 /// Utiltiy functions related to random number generation
-use rand::{thread_rng, Rng};
+use rand::{rngs::StdRng, thread_rng, Rng, SeedableRng};
 
-const RAND_MAX: i64 = 32767;
+use crate::sampler::Sampler;
 
-/// Returns a random float in between 0 and 1
+thread_local! {
+    /// Sampler of the pixel sample being traced on this thread, if there is one
+    static SAMPLER: RefCell<Option<Sampler>> = const { RefCell::new(None) };
+    /// Generator used outside of pixel samples, such as while building a scene, if the thread has been seeded
+    static SEEDED_RNG: RefCell<Option<StdRng>> = const { RefCell::new(None) };
+}
+
+/// Seeds the random numbers drawn on this thread outside of pixel samples, so scenes built from random numbers can be repeated
+pub fn seed_thread(seed: u64) {
+    SEEDED_RNG.with(|r| *r.borrow_mut() = Some(StdRng::seed_from_u64(seed)));
+}
+
+/// Returns a random float from this thread's seeded generator, or from the operating system seeded one if it has none
+fn fallback_f64() -> f64 {
+    return SEEDED_RNG.with(|r| match r.borrow_mut().as_mut() {
+        Some(rng) => rng.gen::<f64>(),
+        None => thread_rng().gen::<f64>(),
+    });
+}
+
+/// Sets the sampler which every random number on this thread is drawn from, or goes back to independent random numbers
+pub fn set_sampler(sampler: Option<Sampler>) {
+    SAMPLER.with(|s| *s.borrow_mut() = sampler);
+}
+
+/// Starts a new pixel sample on this thread's sampler, if it has one
+///
+/// ## Arguments
+/// - `x` Column of the pixel
+/// - `y` Row of the pixel
+/// - `index` Which sample of the pixel this is
+pub fn start_pixel_sample(x: u32, y: u32, index: u32) {
+    SAMPLER.with(|s| {
+        if let Some(sampler) = s.borrow_mut().as_mut() {
+            sampler.start_pixel_sample(x, y, index);
+        }
+    });
+}
+
+/// Returns a random float in between 0 and 1, taken from the next dimension of this thread's sampler if it has one
 pub fn random_f64() -> f64 {
-    let mut rng = thread_rng();
-    return rng.gen_range(0..RAND_MAX) as f64 / (RAND_MAX + 1) as f64;
+    if let Some(value) = SAMPLER.with(|s| s.borrow_mut().as_mut().map(|sampler| sampler.get_1d())) {
+        return value;
+    }
+
+    return fallback_f64();
+}
+
+/// Returns a random point in the unit square, taken from the next two dimensions of this thread's sampler if it has one.
+/// Use this instead of two calls to `random_f64` when the numbers are used together, such as for a direction
+pub fn random_2d() -> (f64, f64) {
+    if let Some(value) = SAMPLER.with(|s| s.borrow_mut().as_mut().map(|sampler| sampler.get_2d())) {
+        return value;
+    }
+
+    return (fallback_f64(), fallback_f64());
 }
 
 /// Returns a random float within a range
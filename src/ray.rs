@@ -3,7 +3,8 @@ use crate::vector3::Vector3;
 #[derive(Debug)]
 pub struct Ray {
     pub origin: Vector3,
-    pub direction: Vector3
+    pub direction: Vector3,
+    pub time: f64,
 }
 
 impl Ray {
@@ -12,6 +13,7 @@ impl Ray {
         Self {
             origin: Vector3::new(),
             direction: Vector3::new(),
+            time: 0.0,
         }
     }
     
@@ -19,7 +21,17 @@ impl Ray {
     pub fn from(origin: Vector3, direction: Vector3) -> Self {
         Self {
             origin,
-            direction
+            direction,
+            time: 0.0,
+        }
+    }
+
+    /// Creates a ray from an origin and direction at a point in time during the camera's shutter interval
+    pub fn with_time(origin: Vector3, direction: Vector3, time: f64) -> Self {
+        Self {
+            origin,
+            direction,
+            time,
         }
     }
     /// Gets a point on the ray based on a given T value. Uses the formula *P(t) = A + tB*
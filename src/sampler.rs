@@ -0,0 +1,495 @@
+use std::sync::OnceLock;
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+/// Largest float below 1, samples are clamped to it so they stay in [0, 1)
+const ONE_MINUS_EPSILON: f64 = 1.0 - f64::EPSILON / 2.0;
+
+/// Primes used as the bases of each Halton dimension, dimensions past the end of the table are independent random numbers
+const PRIMES: [u32; 48] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89,
+    97, 101, 103, 107, 109, 113, 127, 131, 137, 139, 149, 151, 157, 163, 167, 173, 179, 181, 191, 193, 197, 199, 211, 223,
+];
+
+/// Width and height of the tiling blue noise texture
+const BLUE_NOISE_SIZE: usize = 64;
+
+/// How the samples of each pixel are spread out
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum SamplerKind {
+    /// Every number is independently random
+    #[default]
+    Independent,
+    /// Each dimension is split into one stratum per sample with a random point in each,
+    /// and the strata are shuffled differently for every dimension
+    Stratified,
+    /// Halton sequence with a random rotation per pixel
+    Halton,
+    /// Owen scrambled Sobol points, each pair of dimensions gets its own scramble and shuffle
+    Sobol,
+    /// Sobol points shared by every pixel and shifted by a blue noise texture,
+    /// which pushes the remaining error to high frequencies where it is less visible
+    BlueNoise,
+}
+
+impl SamplerKind {
+    pub const ALL: [SamplerKind; 5] = [SamplerKind::Independent, SamplerKind::Stratified, SamplerKind::Halton, SamplerKind::Sobol, SamplerKind::BlueNoise];
+
+    /// Finds a sampler from its name, as used on the command line
+    pub fn from_name(name: &str) -> Option<Self> {
+        return match name.to_lowercase().as_str() {
+            "independent" | "random" => Some(SamplerKind::Independent),
+            "stratified" | "jittered" => Some(SamplerKind::Stratified),
+            "halton" => Some(SamplerKind::Halton),
+            "sobol" => Some(SamplerKind::Sobol),
+            "blue-noise" | "bluenoise" => Some(SamplerKind::BlueNoise),
+            _ => None,
+        };
+    }
+}
+
+/// Hands out the numbers used by one pixel sample, one dimension at a time.
+/// The camera takes the first dimensions for the film position, lens position and time,
+/// every later dimension is used by materials and media while the path bounces around the scene.
+/// Every number only depends on the seed, the pixel and the sample index, so a render with the same seed is identical
+/// however many threads it uses and whatever order they run in
+#[derive(Clone, Debug)]
+pub struct Sampler {
+    pub kind: SamplerKind,
+    pub samples_per_pixel: u32,
+    /// The seed hashed down to 32 bits, mixed into every scramble
+    seed_hash: u32,
+    pixel: (u32, u32),
+    index: u32,
+    dimension: u32,
+    /// Random numbers for jittering and for dimensions the sequence doesn't cover, reseeded for every sample
+    rng: StdRng,
+}
+
+impl Sampler {
+    /// Creates a sampler
+    ///
+    /// ## Arguments
+    /// - `kind` How to spread out the samples
+    /// - `samples_per_pixel` Number of samples which will be taken in each pixel, stratification is planned around it
+    /// - `seed` Seed every random stream is derived from
+    pub fn new(kind: SamplerKind, samples_per_pixel: u32, seed: u64) -> Self {
+        Self {
+            kind,
+            samples_per_pixel: samples_per_pixel.max(1),
+            seed_hash: hash_u32(&[seed as u32, (seed >> 32) as u32]),
+            pixel: (0, 0),
+            index: 0,
+            dimension: 0,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Starts a new sample, dimensions are counted again from the first
+    ///
+    /// ## Arguments
+    /// - `x` Column of the pixel
+    /// - `y` Row of the pixel
+    /// - `index` Which sample of the pixel this is
+    pub fn start_pixel_sample(&mut self, x: u32, y: u32, index: u32) {
+        self.pixel = (x, y);
+        self.index = index;
+        self.dimension = 0;
+
+        let stream = (hash_u32(&[self.seed_hash, x, y, index]) as u64) << 32 | hash_u32(&[index, y, x, self.seed_hash]) as u64;
+        self.rng = StdRng::seed_from_u64(stream);
+    }
+
+    /// Returns the next dimension of the current sample
+    pub fn get_1d(&mut self) -> f64 {
+        let dimension = self.dimension;
+        self.dimension += 1;
+
+        let value = match self.kind {
+            SamplerKind::Independent => self.rng.gen::<f64>(),
+            SamplerKind::Stratified => {
+                let strata = self.samples_per_pixel;
+                let seed = self.hash(dimension, self.index / strata);
+                let stratum = permutation_element(self.index % strata, strata, seed);
+                (stratum as f64 + self.rng.gen::<f64>()) / strata as f64
+            }
+            SamplerKind::Halton => match PRIMES.get(dimension as usize) {
+                Some(base) => rotate(radical_inverse(*base, self.index), unit_float(self.hash(dimension, 0))),
+                None => self.rng.gen::<f64>(),
+            },
+            SamplerKind::Sobol => {
+                let seed = self.hash(dimension, 0);
+                let index = nested_uniform_scramble(self.index, hash_u32(&[seed, 0]));
+                unit_float(nested_uniform_scramble(index.reverse_bits(), hash_u32(&[seed, 1])))
+            }
+            SamplerKind::BlueNoise => {
+                let seed = hash_u32(&[self.seed_hash, dimension, 0]);
+                let index = nested_uniform_scramble(self.index, hash_u32(&[seed, 0]));
+                let value = unit_float(nested_uniform_scramble(index.reverse_bits(), hash_u32(&[seed, 1])));
+                rotate(value, self.blue_noise_shift(dimension))
+            }
+        };
+
+        return value.min(ONE_MINUS_EPSILON);
+    }
+
+    /// Returns the next two dimensions of the current sample, which are spread out well together as well as on their own
+    pub fn get_2d(&mut self) -> (f64, f64) {
+        let dimension = self.dimension;
+
+        let value = match self.kind {
+            SamplerKind::Stratified => {
+                self.dimension += 2;
+
+                // Split the square into as close to a square grid as the sample count allows
+                let columns = (self.samples_per_pixel as f64).sqrt().ceil() as u32;
+                let rows = self.samples_per_pixel.div_ceil(columns);
+                let strata = columns * rows;
+
+                let seed = self.hash(dimension, self.index / strata);
+                let stratum = permutation_element(self.index % strata, strata, seed);
+                let (jitter_x, jitter_y) = (self.rng.gen::<f64>(), self.rng.gen::<f64>());
+                (((stratum % columns) as f64 + jitter_x) / columns as f64, ((stratum / columns) as f64 + jitter_y) / rows as f64)
+            }
+            SamplerKind::Sobol | SamplerKind::BlueNoise => {
+                self.dimension += 2;
+
+                let seed = if self.kind == SamplerKind::Sobol { self.hash(dimension, 0) } else { hash_u32(&[self.seed_hash, dimension, 0]) };
+                let index = nested_uniform_scramble(self.index, hash_u32(&[seed, 0]));
+                let x = unit_float(nested_uniform_scramble(index.reverse_bits(), hash_u32(&[seed, 1])));
+                let y = unit_float(nested_uniform_scramble(sobol_second_dimension(index), hash_u32(&[seed, 2])));
+
+                if self.kind == SamplerKind::Sobol {
+                    (x, y)
+                } else {
+                    (rotate(x, self.blue_noise_shift(dimension)), rotate(y, self.blue_noise_shift(dimension + 1)))
+                }
+            }
+            // Halton and independent dimensions are already well spread out in pairs
+            _ => (self.get_1d(), self.get_1d()),
+        };
+
+        return (value.0.min(ONE_MINUS_EPSILON), value.1.min(ONE_MINUS_EPSILON));
+    }
+
+    /// Hashes the seed and current pixel with a dimension and an extra value, so every pixel gets its own scramble
+    fn hash(&self, dimension: u32, extra: u32) -> u32 {
+        return hash_u32(&[self.seed_hash, self.pixel.0, self.pixel.1, dimension, extra]);
+    }
+
+    /// Looks up the blue noise texture for the current pixel, each dimension reads it at a different offset
+    fn blue_noise_shift(&self, dimension: u32) -> f64 {
+        let offset = hash_u32(&[self.seed_hash, dimension, 1]);
+        let x = (self.pixel.0 as usize + (offset & 0xFFFF) as usize) % BLUE_NOISE_SIZE;
+        let y = (self.pixel.1 as usize + (offset >> 16) as usize) % BLUE_NOISE_SIZE;
+
+        return blue_noise_texture()[y * BLUE_NOISE_SIZE + x] as f64;
+    }
+}
+
+/// Adds a shift to a sample and wraps it back into [0, 1)
+fn rotate(value: f64, shift: f64) -> f64 {
+    let rotated = value + shift;
+    return rotated - rotated.floor();
+}
+
+/// Turns all 32 bits of an integer into a float in [0, 1)
+fn unit_float(bits: u32) -> f64 {
+    return bits as f64 / 4294967296.0;
+}
+
+/// Mirrors the digits of a number in a base around the decimal point, giving the Halton sequence for that base
+fn radical_inverse(base: u32, mut index: u32) -> f64 {
+    let inverse_base = 1.0 / base as f64;
+    let mut reversed: u64 = 0;
+    let mut scale = 1.0;
+
+    while index > 0 {
+        let next = index / base;
+        reversed = reversed * base as u64 + (index - next * base) as u64;
+        scale *= inverse_base;
+        index = next;
+    }
+
+    return (reversed as f64 * scale).min(ONE_MINUS_EPSILON);
+}
+
+/// Second dimension of the Sobol sequence, which together with the bit reversed index forms a (0, 2) sequence
+fn sobol_second_dimension(mut index: u32) -> u32 {
+    let mut result = 0;
+    let mut direction = 1 << 31;
+
+    while index != 0 {
+        if index & 1 != 0 {
+            result ^= direction;
+        }
+        index >>= 1;
+        direction ^= direction >> 1;
+    }
+
+    return result;
+}
+
+/// Hash based Owen scrambling, from Burley's "Practical Hash-based Owen Scrambling"
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    return laine_karras_permutation(x.reverse_bits(), seed).reverse_bits();
+}
+
+/// Scrambles the bits of a number so that each bit only depends on the bits below it
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    return x;
+}
+
+/// Returns where `i` ends up in a random permutation of `0..length` picked by `seed`, from Kensler's "Correlated Multi-Jittered Sampling"
+fn permutation_element(mut i: u32, length: u32, seed: u32) -> u32 {
+    let mut mask = length - 1;
+    mask |= mask >> 1;
+    mask |= mask >> 2;
+    mask |= mask >> 4;
+    mask |= mask >> 8;
+    mask |= mask >> 16;
+
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= seed >> 16;
+        i ^= (i & mask) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= seed >> 23;
+        i ^= (i & mask) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & mask) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & mask) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & mask) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= mask;
+        i ^= i >> 5;
+
+        if i < length {
+            break;
+        }
+    }
+
+    return (i.wrapping_add(seed)) % length;
+}
+
+/// Mixes the bits of a 64 bit number, the finaliser of MurmurHash3
+fn mix_bits(mut v: u64) -> u64 {
+    v ^= v >> 31;
+    v = v.wrapping_mul(0x7fb5d329728ea185);
+    v ^= v >> 27;
+    v = v.wrapping_mul(0x81dadef4bc2dd44d);
+    v ^= v >> 33;
+    return v;
+}
+
+/// Hashes a list of numbers into 32 bits
+pub fn hash_u32(values: &[u32]) -> u32 {
+    let mut hash: u64 = 0x9E3779B97F4A7C15;
+    for value in values {
+        hash = mix_bits(hash ^ (*value as u64).wrapping_add(0x9E3779B97F4A7C15));
+    }
+    return hash as u32;
+}
+
+/// Returns a tiling texture where every value from 0 to 1 appears once and neighbouring values are as different as possible,
+/// made once with Ulichney's void and cluster method
+pub fn blue_noise_texture() -> &'static [f32] {
+    static TEXTURE: OnceLock<Vec<f32>> = OnceLock::new();
+    return TEXTURE.get_or_init(build_blue_noise_texture);
+}
+
+fn build_blue_noise_texture() -> Vec<f32> {
+    const SIGMA: f64 = 1.5;
+    const KERNEL_RADIUS: i64 = 5;
+    let size = BLUE_NOISE_SIZE as i64;
+    let count = BLUE_NOISE_SIZE * BLUE_NOISE_SIZE;
+
+    let mut pattern = vec![false; count];
+    let mut energy = vec![0.0; count];
+    let mut ranks = vec![0u32; count];
+
+    // Adds or removes a point, updating the gaussian weighted energy of every cell around it on the torus
+    let splat = |pattern: &mut [bool], energy: &mut [f64], cell: usize, add: bool| {
+        pattern[cell] = add;
+        let sign = if add { 1.0 } else { -1.0 };
+        let (cx, cy) = ((cell % BLUE_NOISE_SIZE) as i64, (cell / BLUE_NOISE_SIZE) as i64);
+
+        for dy in -KERNEL_RADIUS..=KERNEL_RADIUS {
+            for dx in -KERNEL_RADIUS..=KERNEL_RADIUS {
+                let x = (cx + dx).rem_euclid(size) as usize;
+                let y = (cy + dy).rem_euclid(size) as usize;
+                energy[y * BLUE_NOISE_SIZE + x] += sign * (-((dx * dx + dy * dy) as f64) / (2.0 * SIGMA * SIGMA)).exp();
+            }
+        }
+    };
+    // The tightest cluster is the point with the most energy, the largest void is the empty cell with the least
+    let tightest_cluster = |pattern: &[bool], energy: &[f64]| (0..count).filter(|c| pattern[*c]).max_by(|a, b| energy[*a].total_cmp(&energy[*b])).unwrap();
+    let largest_void = |pattern: &[bool], energy: &[f64]| (0..count).filter(|c| !pattern[*c]).min_by(|a, b| energy[*a].total_cmp(&energy[*b])).unwrap();
+
+    // Start from a tenth of the cells picked at random, made deterministic so every run gets the same texture
+    let initial_count = count / 10;
+    let mut placed = 0;
+    let mut attempt = 0;
+    while placed < initial_count {
+        let cell = hash_u32(&[attempt, 0xB1E]) as usize % count;
+        attempt += 1;
+        if !pattern[cell] {
+            splat(&mut pattern, &mut energy, cell, true);
+            placed += 1;
+        }
+    }
+
+    // Spread the initial points out by moving the tightest cluster into the largest void until that changes nothing
+    for _ in 0..count {
+        let cluster = tightest_cluster(&pattern, &energy);
+        splat(&mut pattern, &mut energy, cluster, false);
+        let void = largest_void(&pattern, &energy);
+        splat(&mut pattern, &mut energy, void, true);
+
+        if void == cluster {
+            break;
+        }
+    }
+
+    // Rank the initial points by removing the tightest cluster each time
+    let (mut working_pattern, mut working_energy) = (pattern.clone(), energy.clone());
+    for rank in (0..initial_count).rev() {
+        let cluster = tightest_cluster(&working_pattern, &working_energy);
+        splat(&mut working_pattern, &mut working_energy, cluster, false);
+        ranks[cluster] = rank as u32;
+    }
+
+    // Rank the rest of the cells by filling the largest void each time
+    for rank in initial_count..count {
+        let void = largest_void(&pattern, &energy);
+        splat(&mut pattern, &mut energy, void, true);
+        ranks[void] = rank as u32;
+    }
+
+    return ranks.iter().map(|rank| (*rank as f32 + 0.5) / count as f32).collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KINDS: [SamplerKind; 5] = [SamplerKind::Independent, SamplerKind::Stratified, SamplerKind::Halton, SamplerKind::Sobol, SamplerKind::BlueNoise];
+
+    /// Takes every sample of a pixel, skipping `skip` dimensions first, and returns which of `strata` cells each 1D value fell in
+    fn strata_1d(kind: SamplerKind, samples: u32, skip: u32) -> Vec<u32> {
+        let mut sampler = Sampler::new(kind, samples, 42);
+        let mut cells = vec![];
+        for index in 0..samples {
+            sampler.start_pixel_sample(3, 7, index);
+            for _ in 0..skip {
+                sampler.get_1d();
+            }
+            cells.push((sampler.get_1d() * samples as f64) as u32);
+        }
+        cells.sort();
+        return cells;
+    }
+
+    /// Takes every sample of a pixel and returns which cell of a `columns` by `rows` grid each 2D value fell in
+    fn strata_2d(kind: SamplerKind, samples: u32, columns: u32, rows: u32) -> Vec<u32> {
+        let mut sampler = Sampler::new(kind, samples, 42);
+        let mut cells = vec![];
+        for index in 0..samples {
+            sampler.start_pixel_sample(3, 7, index);
+            sampler.get_1d();
+            let (x, y) = sampler.get_2d();
+            cells.push((y * rows as f64) as u32 * columns + (x * columns as f64) as u32);
+        }
+        cells.sort();
+        return cells;
+    }
+
+    #[test]
+    fn stratified_puts_one_sample_in_each_stratum() {
+        for skip in [0, 1, 5] {
+            assert_eq!(strata_1d(SamplerKind::Stratified, 16, skip), (0..16).collect::<Vec<_>>());
+            assert_eq!(strata_1d(SamplerKind::Stratified, 7, skip), (0..7).collect::<Vec<_>>());
+        }
+
+        assert_eq!(strata_2d(SamplerKind::Stratified, 16, 4, 4), (0..16).collect::<Vec<_>>());
+        // Six samples are split into three columns and two rows
+        assert_eq!(strata_2d(SamplerKind::Stratified, 6, 3, 2), (0..6).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn sobol_puts_one_sample_in_each_stratum() {
+        // Blue noise shifts these same points per pixel, which gives up exact stratification for a better spread across pixels
+        assert_eq!(strata_1d(SamplerKind::Sobol, 16, 2), (0..16).collect::<Vec<_>>());
+        assert_eq!(strata_2d(SamplerKind::Sobol, 16, 4, 4), (0..16).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn samples_stay_in_the_unit_interval() {
+        for kind in KINDS {
+            let mut sampler = Sampler::new(kind, 64, 7);
+            for index in 0..64 {
+                sampler.start_pixel_sample(index % 5, index / 5, index);
+                for _ in 0..60 {
+                    let value = sampler.get_1d();
+                    let (x, y) = sampler.get_2d();
+                    for v in [value, x, y] {
+                        assert!((0.0..1.0).contains(&v), "{:?} gave {}", kind, v);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn permutation_element_is_a_bijection() {
+        for length in [1, 2, 5, 16, 100, 257] {
+            for seed in [0, 1, 0xDEADBEEF, 123456789] {
+                let mut seen = vec![false; length as usize];
+                for i in 0..length {
+                    let j = permutation_element(i, length, seed);
+                    assert!(j < length && !seen[j as usize], "length {} seed {} sends {} to {}", length, seed, i, j);
+                    seen[j as usize] = true;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn blue_noise_ranks_are_a_permutation() {
+        let texture = blue_noise_texture();
+        let count = BLUE_NOISE_SIZE * BLUE_NOISE_SIZE;
+        assert_eq!(texture.len(), count);
+
+        let mut ranks: Vec<usize> = texture.iter().map(|value| (value * count as f32) as usize).collect();
+        ranks.sort();
+        assert_eq!(ranks, (0..count).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn samples_only_depend_on_the_seed_pixel_and_index() {
+        for kind in KINDS {
+            let mut first = Sampler::new(kind, 16, 99);
+            let mut second = Sampler::new(kind, 16, 99);
+
+            // The second sampler takes other samples first, which must not change what it gives for this one
+            second.start_pixel_sample(0, 0, 3);
+            second.get_2d();
+            first.start_pixel_sample(5, 6, 2);
+            second.start_pixel_sample(5, 6, 2);
+
+            for _ in 0..10 {
+                assert_eq!(first.get_1d(), second.get_1d(), "{:?}", kind);
+            }
+        }
+    }
+}
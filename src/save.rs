@@ -1,20 +1,33 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use exr::prelude::{AnyChannel, AnyChannels, Encoding, FlatSamples, Image, Layer, LayerAttributes, SmallVec, Vec2, WritableImage, f16};
 use image::ImageBuffer;
-use image::Rgb;
+use image::{Rgb, Rgba};
+use image::codecs::hdr::HdrEncoder;
+
+use crate::aov::{heat_colour, id_colour, Aov};
+use crate::colour::Colour;
+use crate::film::{AlphaMode, Film};
+use crate::tonemap::DisplaySettings;
 
-pub fn save_u32_vector_to_png(filename: &str, width: u32, height: u32, data: &Vec<u32>) -> Result<(), image::ImageError> {
-    // Create an ImageBuffer with RGBA format
+pub fn save_u32_vector_to_png(filename: &str, width: u32, height: u32, data: &[u32]) -> Result<(), image::ImageError> {
+    // Create an ImageBuffer with RGB format
     let mut img = ImageBuffer::new(width, height);
 
     // Iterate over the data and set the pixel values
     for (x, y, pixel) in img.enumerate_pixels_mut() {
-        // Convert the u32 value to RGBA format
-        let rgba = Rgb([
+        // Convert the u32 value to RGB format
+        let rgb = Rgb([
             ((data[(y * width + x) as usize] >> 16) & 0xFF) as u8,
             ((data[(y * width + x) as usize] >> 8) & 0xFF) as u8,
             (data[(y * width + x) as usize] & 0xFF) as u8,
         ]);
 
-        *pixel = rgba;
+        *pixel = rgb;
     }
 
     // Save the ImageBuffer to a PNG file
@@ -22,3 +35,356 @@ pub fn save_u32_vector_to_png(filename: &str, width: u32, height: u32, data: &Ve
 
     Ok(())
 } 
+
+/// Saves pixels packed as `ARGB` u32 values to an image format with an alpha channel such as PNG
+pub fn save_argb_image(filename: &str, width: u32, height: u32, data: &[u32]) -> Result<(), image::ImageError> {
+    let mut img = ImageBuffer::new(width, height);
+
+    for (x, y, pixel) in img.enumerate_pixels_mut() {
+        let value = data[(y * width + x) as usize];
+        *pixel = Rgba([
+            ((value >> 16) & 0xFF) as u8,
+            ((value >> 8) & 0xFF) as u8,
+            (value & 0xFF) as u8,
+            ((value >> 24) & 0xFF) as u8,
+        ]);
+    }
+
+    img.save(filename)?;
+
+    Ok(())
+}
+
+/// Precision of the samples written to an OpenEXR file
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ExrPrecision {
+    Half,
+    Float,
+}
+
+/// A named group of linear float channels, all of the same size, written as one layer of an OpenEXR file
+pub struct ExrLayer {
+    /// Name of the layer, an empty name writes the channels without a prefix
+    pub name: String,
+    /// Name and row by row samples of each channel
+    pub channels: Vec<(String, Vec<f32>)>,
+    /// Keeps full floats even when the file is saved at half precision, used for ids which must not be rounded
+    pub exact: bool,
+}
+
+impl ExrLayer {
+    /// Creates a layer with R, G and B channels from a film's average radiance
+    ///
+    /// ## Arguments
+    /// - `name` Name of the layer
+    /// - `film` Film to take the pixels from
+    /// - `alpha` Adds an A channel, with the colour stored as given, if set
+    pub fn from_film(name: &str, film: &Film, alpha: Option<AlphaMode>) -> Self {
+        let pixels: Vec<_> = film.pixels.iter().map(|p| p.colour_with_alpha(alpha.unwrap_or(AlphaMode::Premultiplied))).collect();
+
+        let mut channels = vec![
+            (String::from("R"), pixels.iter().map(|(c, _)| c.x as f32).collect()),
+            (String::from("G"), pixels.iter().map(|(c, _)| c.y as f32).collect()),
+            (String::from("B"), pixels.iter().map(|(c, _)| c.z as f32).collect()),
+        ];
+        if alpha.is_some() {
+            channels.push((String::from("A"), pixels.iter().map(|(_, a)| *a as f32).collect()));
+        }
+
+        Self {
+            name: name.to_string(),
+            channels,
+            exact: false,
+        }
+    }
+
+    /// Creates a layer named after a render pass holding its channels
+    ///
+    /// ## Arguments
+    /// - `film` Film to take the pass from
+    /// - `aov` Which pass to use
+    pub fn from_aov(film: &Film, aov: Aov) -> Self {
+        let values = film.aov_values(aov);
+
+        Self {
+            name: aov.name().to_string(),
+            channels: aov.channel_names().iter().enumerate().map(|(i, channel)| {
+                (channel.to_string(), values.iter().map(|v| v[i] as f32).collect())
+            }).collect(),
+            exact: matches!(aov, Aov::ObjectId | Aov::MaterialId | Aov::SampleCount),
+        }
+    }
+}
+
+/// Where and how a render is saved
+#[derive(Clone, Debug)]
+pub struct OutputSettings {
+    /// File to write, the format is chosen from the extension
+    pub path: String,
+    /// Sample precision for OpenEXR files
+    pub precision: ExrPrecision,
+    /// Exposure and tone mapping, only used for 8-bit formats since HDR formats keep the linear radiance
+    pub display: DisplaySettings,
+    /// Render passes to save with the image, as extra layers of an OpenEXR file or as separate files for other formats
+    pub aovs: Vec<Aov>,
+    /// How colour is stored next to alpha when the film has a transparent background,
+    /// by default premultiplied for OpenEXR and straight for 8-bit formats
+    pub alpha_mode: Option<AlphaMode>,
+    /// File the accumulated film is written to while rendering and when it finishes, so the render can be resumed
+    pub checkpoint: Option<String>,
+    /// How often the checkpoint is written
+    pub checkpoint_interval: Duration,
+}
+
+impl Default for OutputSettings {
+    fn default() -> Self {
+        Self {
+            path: String::from("render.png"),
+            precision: ExrPrecision::Float,
+            display: DisplaySettings::default(),
+            aovs: vec![],
+            alpha_mode: None,
+            checkpoint: None,
+            checkpoint_interval: Duration::from_secs(300),
+        }
+    }
+}
+
+impl OutputSettings {
+    /// Returns the directory the output file is written to, which is empty for a file in the current directory
+    pub fn directory(&self) -> &Path {
+        return Path::new(&self.path).parent().unwrap_or(Path::new(""));
+    }
+}
+
+/// Returns whether a path has a high dynamic range extension which keeps the linear film values
+pub fn is_hdr_path(path: &str) -> bool {
+    let extension = Path::new(path).extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
+    return matches!(extension.as_str(), "exr" | "hdr" | "pfm");
+}
+
+/// Extensions of the 8-bit formats a film can be saved as
+const LDR_EXTENSIONS: [&str; 9] = ["png", "jpg", "jpeg", "bmp", "tga", "tif", "tiff", "ppm", "pnm"];
+
+/// Checks that a film could be saved to a path, so a bad path is caught before rendering rather than after
+///
+/// ## Arguments
+/// - `path` File the film will be saved as
+/// - `transparent` Whether the film will have a transparent background
+///
+/// Returns a warning to show if the file would lose something the render has
+pub fn check_output_path(path: &str, transparent: bool) -> Result<Option<String>, String> {
+    let extension = Path::new(path).extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
+    if !is_hdr_path(path) && !LDR_EXTENSIONS.contains(&extension.as_str()) {
+        return Err(format!("cannot save '{}', the extension should be one of exr, hdr, pfm, {}", path, LDR_EXTENSIONS.join(", ")));
+    }
+
+    if let Some(dir) = Path::new(path).parent() {
+        if !dir.as_os_str().is_empty() && !dir.is_dir() {
+            return Err(format!("cannot save '{}', the directory '{}' does not exist", path, dir.display()));
+        }
+    }
+
+    if transparent && !matches!(extension.as_str(), "exr" | "png" | "tga" | "tif" | "tiff") {
+        return Ok(Some(format!("'{}' has no alpha channel, the transparent background will be saved as black", path)));
+    }
+
+    return Ok(None);
+}
+
+/// Saves a film, choosing the format from the file extension.
+/// `.exr`, `.hdr` and `.pfm` keep the linear radiance, every other format is tone mapped and quantised to 8 bits.
+/// Render passes are added as layers of an OpenEXR file, other formats write each pass next to the image, such as `render.albedo.png`.
+/// Films with a transparent background get an alpha channel in OpenEXR, PNG, TGA and TIFF files,
+/// other formats keep the colour premultiplied over black
+///
+/// ## Arguments
+/// - `film` Film to save
+/// - `settings` Where and how to save it
+pub fn save_film(film: &Film, settings: &OutputSettings) -> Result<(), Box<dyn Error>> {
+    let path = settings.path.as_str();
+    let extension = Path::new(path).extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
+
+    if extension == "exr" {
+        let alpha = film.transparent.then(|| settings.alpha_mode.unwrap_or(AlphaMode::Premultiplied));
+        let mut layers = vec![ExrLayer::from_film("", film, alpha)];
+        layers.extend(settings.aovs.iter().map(|aov| ExrLayer::from_aov(film, *aov)));
+
+        save_exr(path, film.width, film.height, &layers, settings.precision)?;
+        return Ok(());
+    }
+
+    if film.transparent && matches!(extension.as_str(), "png" | "tga" | "tif" | "tiff") {
+        let packed = film.to_packed_argb(&settings.display, settings.alpha_mode.unwrap_or(AlphaMode::Straight));
+        save_argb_image(path, film.width as u32, film.height as u32, &packed)?;
+    } else {
+        let colours: Vec<_> = film.pixels.iter().map(|p| p.colour()).collect();
+        save_colours(path, &extension, film.width, film.height, &colours, &film.to_packed_rgb(&settings.display))?;
+    }
+
+    for aov in &settings.aovs {
+        let aov_path = aov_path(path, *aov);
+        let values = film.aov_values(*aov);
+        save_colours(&aov_path, &extension, film.width, film.height, &values, &aov_display_pixels(&values, *aov, &settings.display))?;
+    }
+
+    return Ok(());
+}
+
+/// Saves linear colours as a Radiance or Portable Float Map file, or 8-bit pixels for every other format
+fn save_colours(path: &str, extension: &str, width: usize, height: usize, colours: &[Colour], packed: &[u32]) -> Result<(), Box<dyn Error>> {
+    match extension {
+        "hdr" => save_radiance_hdr(path, width, height, colours)?,
+        "pfm" => save_pfm(path, width, height, colours)?,
+        _ => save_u32_vector_to_png(path, width as u32, height as u32, packed)?,
+    }
+
+    return Ok(());
+}
+
+/// Returns the path a render pass is saved to, the pass name is put before the extension
+pub fn aov_path(path: &str, aov: Aov) -> String {
+    let path = Path::new(path);
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("render");
+
+    let file_name = match path.extension().and_then(|e| e.to_str()) {
+        Some(extension) => format!("{}.{}.{}", stem, aov.name(), extension),
+        None => format!("{}.{}", stem, aov.name()),
+    };
+
+    return path.with_file_name(file_name).to_string_lossy().into_owned();
+}
+
+/// Packs a colour in 0 to 1 into a `0RGB` u32 without any encoding
+fn pack_rgb(colour: Colour) -> u32 {
+    let channel = |c: f64| (256.0 * c.clamp(0.0, 0.999)) as u32;
+    return channel(colour.x) << 16 | channel(colour.y) << 8 | channel(colour.z);
+}
+
+/// Converts a render pass into 8-bit pixels which are easy to look at.
+/// Light is tone mapped like the beauty image, normals are mapped from -1..1 to 0..1,
+/// depth is shown brightest nearest the camera, ids get a random colour each and sample counts are shown as a heatmap
+///
+/// ## Arguments
+/// - `values` Value of the pass in every pixel
+/// - `aov` Which pass the values are from
+/// - `display` Exposure and tone mapping for passes holding light
+pub fn aov_display_pixels(values: &[Colour], aov: Aov, display: &DisplaySettings) -> Vec<u32> {
+    // Depth and sample counts are scaled by their largest value
+    let max_value = values.iter().map(|v| v.x).fold(0.0, f64::max);
+    let mut output = Vec::with_capacity(values.len());
+
+    for value in values {
+        match aov {
+            _ if aov.is_radiance() => display.map(*value).write_colour_pixels(&mut output, 1),
+            Aov::Albedo => value.write_colour_pixels(&mut output, 1),
+            Aov::Normal => output.push(pack_rgb(0.5 * (*value + Colour::from(1.0, 1.0, 1.0)))),
+            Aov::Depth => {
+                let shade = if value.x > 0.0 && max_value > 0.0 { 1.0 - value.x / max_value } else { 0.0 };
+                output.push(pack_rgb(Colour::from(shade, shade, shade)));
+            }
+            Aov::ObjectId | Aov::MaterialId => output.push(pack_rgb(id_colour(value.x as u32))),
+            Aov::SampleCount => output.push(pack_rgb(heat_colour(if max_value > 0.0 { value.x / max_value } else { 0.0 }))),
+            _ => output.push(pack_rgb(*value)),
+        }
+    }
+
+    return output;
+}
+
+/// Saves layers of linear channels as a single part OpenEXR file.
+/// Channels of named layers are prefixed with the layer name, for example `albedo.R`, which compositing software reads as layers
+///
+/// ## Arguments
+/// - `path` File to write
+/// - `width` Width of every layer
+/// - `height` Height of every layer
+/// - `layers` Layers to write
+/// - `precision` Whether to store half or full floats
+pub fn save_exr(path: &str, width: usize, height: usize, layers: &[ExrLayer], precision: ExrPrecision) -> Result<(), exr::error::Error> {
+    let mut channels = vec![];
+
+    for layer in layers {
+        for (channel_name, samples) in &layer.channels {
+            let name = if layer.name.is_empty() { channel_name.clone() } else { format!("{}.{}", layer.name, channel_name) };
+
+            let samples = match precision {
+                ExrPrecision::Half if !layer.exact => FlatSamples::F16(samples.iter().map(|s| f16::from_f32(*s)).collect()),
+                _ => FlatSamples::F32(samples.clone()),
+            };
+            channels.push(AnyChannel::new(name.as_str(), samples));
+        }
+    }
+
+    let layer = Layer::new(
+        Vec2(width, height),
+        LayerAttributes::default(),
+        Encoding::FAST_LOSSLESS,
+        AnyChannels::sort(SmallVec::from_vec(channels)),
+    );
+
+    return Image::from_layer(layer).write().to_file(path);
+}
+
+/// Saves linear colours as a Radiance RGBE `.hdr` file
+///
+/// ## Arguments
+/// - `path` File to write
+/// - `width` Width of the image
+/// - `height` Height of the image
+/// - `colours` Colour of every pixel row by row
+pub fn save_radiance_hdr(path: &str, width: usize, height: usize, colours: &[Colour]) -> Result<(), image::ImageError> {
+    let pixels: Vec<Rgb<f32>> = colours.iter().map(|c| Rgb([c.x as f32, c.y as f32, c.z as f32])).collect();
+
+    let writer = BufWriter::new(File::create(path)?);
+    return HdrEncoder::new(writer).encode(&pixels, width, height);
+}
+
+/// Saves linear colours as a little endian colour Portable Float Map, which stores rows from the bottom up
+///
+/// ## Arguments
+/// - `path` File to write
+/// - `width` Width of the image
+/// - `height` Height of the image
+/// - `colours` Colour of every pixel row by row, starting at the top
+pub fn save_pfm(path: &str, width: usize, height: usize, colours: &[Colour]) -> std::io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+
+    // A negative scale marks the file as little endian
+    write!(writer, "PF\n{} {}\n-1.0\n", width, height)?;
+
+    for row in colours.chunks(width).rev() {
+        for c in row {
+            for value in [c.x as f32, c.y as f32, c.z as f32] {
+                writer.write_all(&value.to_le_bytes())?;
+            }
+        }
+    }
+
+    return writer.flush();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_extensions_are_rejected_before_rendering() {
+        assert!(check_output_path("render.pngg", false).is_err());
+        assert!(check_output_path("render", false).is_err());
+        assert_eq!(check_output_path("render.PNG", false), Ok(None));
+        assert_eq!(check_output_path("render.exr", true), Ok(None));
+    }
+
+    #[test]
+    fn missing_directories_are_rejected() {
+        assert!(check_output_path("no-such-directory/render.png", false).is_err());
+    }
+
+    #[test]
+    fn transparency_in_formats_without_alpha_gives_a_warning() {
+        assert!(check_output_path("render.hdr", true).unwrap().is_some());
+        assert!(check_output_path("render.pfm", true).unwrap().is_some());
+        assert!(check_output_path("render.hdr", false).unwrap().is_none());
+    }
+}
@@ -0,0 +1,202 @@
+use crate::{
+    aa_box::AxisAlignedBox,
+    bvh::BvhNode,
+    capsule::Capsule,
+    colour::Colour,
+    cone::Cone,
+    constant_medium::ConstantMedium,
+    csg::Csg,
+    cylinder::Cylinder,
+    heterogeneous_medium::HeterogeneousMedium,
+    hittable::Hittable,
+    hittable_list::HittableList,
+    material::{Dieletric, Lambertian, Material, Metal},
+    quad::{Disk, Plane, Quad},
+    random::{self, random_f64, random_f64_in_range},
+    sphere::Sphere,
+    torus::Torus,
+    transform::{Instance, Transform},
+    vector3::Vector3,
+    voxel_grid::VoxelGrid,
+};
+
+/// Voxels along each side of the cloud the volumes scene uses when no grid is loaded
+const CLOUD_RESOLUTION: usize = 32;
+
+/// Which scene to render
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum SceneKind {
+    /// Hundreds of small random spheres around three large ones
+    #[default]
+    Spheres,
+    /// One of every kind of surface, including a CSG solid
+    Shapes,
+    /// Fog and smoke, with a constant density and from a voxel grid
+    Volumes,
+}
+
+impl SceneKind {
+    pub const ALL: [SceneKind; 3] = [SceneKind::Spheres, SceneKind::Shapes, SceneKind::Volumes];
+
+    /// Finds a scene from its name, as used on the command line
+    pub fn from_name(name: &str) -> Option<Self> {
+        return match name.to_lowercase().as_str() {
+            "spheres" => Some(SceneKind::Spheres),
+            "shapes" => Some(SceneKind::Shapes),
+            "volumes" => Some(SceneKind::Volumes),
+            _ => None,
+        };
+    }
+}
+
+/// Builds a scene on a grey ground plane, every scene is framed for the camera made by `build_camera`
+///
+/// ## Arguments
+/// - `kind` Scene to build
+/// - `volume` Grid the volumes scene puts in the middle, a random cloud is made if there is none
+/// - `scene_seed` Seed for everything random in the scene, the same seed always gives the same scene
+pub fn build_world(kind: SceneKind, volume: Option<VoxelGrid>, scene_seed: u64) -> HittableList {
+    random::seed_thread(scene_seed);
+    let mut world = HittableList::new();
+
+    // Generate some basic materials
+    let material_ground = Box::new(Lambertian::new(Colour::from(0.5, 0.5, 0.5)));
+    world.add(Box::new(Plane::new(Vector3::new(), Vector3::from(0.0, 1.0, 0.0), material_ground)));
+
+    // Bounded objects go in a BVH, the infinite ground plane stays outside it so it doesn't make every box infinite
+    let objects = match kind {
+        SceneKind::Spheres => random_spheres(),
+        SceneKind::Shapes => shapes(),
+        SceneKind::Volumes => volumes(volume),
+    };
+
+    world.add(Box::new(BvhNode::from_list(objects)));
+    world.assign_object_ids(&mut 1);
+
+    return world;
+}
+
+/// Small spheres scattered over the ground around three large ones
+fn random_spheres() -> HittableList {
+    let mut objects = HittableList::new();
+
+    for a in -11..11 {
+        for b in -11..11 {
+            let choose_mat = random_f64();
+            let center = Vector3::from(a as f64 + 0.9 * random_f64(), 0.2, b as f64 + 0.9 * random_f64());
+
+            if (center - Vector3::from(4.0, 0.2, 0.0)).length() > 0.9 {
+                let sphere_material: Box<dyn Material>;
+
+                if choose_mat < 0.8 {
+                    let albedo = Colour::random() * Colour::random();
+                    sphere_material = Box::new(Lambertian::new(albedo));
+
+                    // Diffuse spheres bounce upwards while the shutter is open
+                    let center1 = center + Vector3::from(0.0, random_f64_in_range(0.0..0.5), 0.0);
+                    objects.add(Box::new(Sphere::moving(center, center1, 0.2, sphere_material)));
+                } else {
+                    sphere_material = Box::new(Dieletric::new(1.5));
+                    objects.add(Box::new(Sphere::new(center, 0.2, sphere_material)));
+                }
+            }
+        }
+    }
+
+    let material1 = Box::new(Dieletric::new(1.5));
+    objects.add(Box::new(Sphere::new(Vector3::from(0.0, 1.0, 0.0), 1.0, material1)));
+
+    let material2 = Box::new(Lambertian::new(Colour::from(0.4, 0.2, 0.1)));
+    objects.add(Box::new(Sphere::new(Vector3::from(-4.0, 1.0, 0.0), 1.0, material2)));
+    let material3 = Box::new(Metal::new(Colour::from(0.7, 0.6, 0.5), 0.0));
+    objects.add(Box::new(Sphere::new(Vector3::from(4.0, 1.0, 0.0), 1.0, material3)));
+
+    return objects;
+}
+
+/// A row of every kind of surface in front of a wall
+fn shapes() -> HittableList {
+    let mut objects = HittableList::new();
+    let up = Vector3::from(0.0, 1.0, 0.0);
+
+    let wall = Box::new(Lambertian::new(Colour::from(0.6, 0.65, 0.7)));
+    objects.add(Box::new(Quad::new(Vector3::from(-5.0, 0.0, -6.0), Vector3::from(0.0, 0.0, 12.0), Vector3::from(0.0, 4.0, 0.0), wall)));
+
+    let mirror = Box::new(Metal::new(Colour::from(0.8, 0.8, 0.8), 0.02));
+    objects.add(Box::new(Disk::new(Vector3::from(1.5, 0.01, 0.0), up, 1.2, mirror)));
+
+    let red = Box::new(Lambertian::new(Colour::from(0.7, 0.15, 0.1)));
+    objects.add(Box::new(AxisAlignedBox::new(Vector3::from(-0.5, 0.0, -4.0), Vector3::from(0.5, 1.0, -3.0), red)));
+
+    let green = Box::new(Lambertian::new(Colour::from(0.2, 0.6, 0.25)));
+    objects.add(Box::new(Cylinder::new(Vector3::from(0.0, 0.0, -2.0), up, 0.45, 1.3, true, green)));
+
+    let blue = Box::new(Lambertian::new(Colour::from(0.15, 0.3, 0.7)));
+    objects.add(Box::new(Cone::new(Vector3::from(0.0, 0.0, -0.7), up, 0.5, 1.4, true, blue)));
+
+    let glass = Box::new(Dieletric::new(1.5));
+    objects.add(Box::new(Capsule::new(Vector3::from(0.0, 0.4, 0.6), Vector3::from(0.0, 1.2, 1.2), 0.35, glass)));
+
+    let gold = Box::new(Metal::new(Colour::from(0.8, 0.6, 0.2), 0.1));
+    objects.add(Box::new(Torus::new(Vector3::from(0.0, 0.6, 2.3), Vector3::from(1.0, 0.0, 0.0), 0.5, 0.15, gold)));
+
+    // A rounded cube with holes bored along every axis
+    let centre = Vector3::from(0.0, 0.75, 3.8);
+    let body = Box::new(Lambertian::new(Colour::from(0.8, 0.8, 0.75)));
+    let cube = Box::new(AxisAlignedBox::new(centre - Vector3::from(0.6, 0.6, 0.6), centre + Vector3::from(0.6, 0.6, 0.6), body.clone()));
+    let ball = Box::new(Sphere::new(centre, 0.8, body.clone()));
+    let mut holes: Box<dyn Hittable> = Box::new(Cylinder::new(centre - Vector3::from(0.8, 0.0, 0.0), Vector3::from(1.0, 0.0, 0.0), 0.3, 1.6, true, body.clone()));
+    for axis in [Vector3::from(0.0, 1.0, 0.0), Vector3::from(0.0, 0.0, 1.0)] {
+        let hole = Box::new(Cylinder::new(centre - 0.8 * axis, axis, 0.3, 1.6, true, body.clone()));
+        holes = Box::new(Csg::union(holes, hole));
+    }
+    objects.add(Box::new(Csg::difference(Box::new(Csg::intersection(cube, ball)), holes)));
+
+    return objects;
+}
+
+/// Media with a constant density on either side of a grid of smoke, next to a mirrored ball
+fn volumes(volume: Option<VoxelGrid>) -> HittableList {
+    let mut objects = HittableList::new();
+    let white = Colour::from(0.9, 0.9, 0.9);
+
+    let fog_boundary = Box::new(Sphere::new(Vector3::from(0.0, 1.0, -2.5), 1.0, Box::new(Dieletric::new(1.0))));
+    objects.add(Box::new(ConstantMedium::new(fog_boundary, 1.5, Colour::from(0.8, 0.3, 0.3))));
+
+    let haze_boundary = Box::new(AxisAlignedBox::new(Vector3::from(-0.8, 0.0, 1.4), Vector3::from(0.8, 1.6, 3.0), Box::new(Dieletric::new(1.0))));
+    objects.add(Box::new(ConstantMedium::with_henyey_greenstein(haze_boundary, 2.0, white, 0.6)));
+
+    // Whatever its bounds, the grid is scaled so its longest side is 2.4 units and stood on the ground in the middle of the scene
+    let grid = volume.unwrap_or_else(random_cloud);
+    let bounds = grid.bounds;
+    let scale = 2.4 / (0..3).map(|axis| bounds.axis(axis).size()).fold(0.0, f64::max);
+    let translation = -scale * Vector3::from((bounds.x.min + bounds.x.max) / 2.0, bounds.y.min, (bounds.z.min + bounds.z.max) / 2.0);
+    let medium = Box::new(HeterogeneousMedium::new(grid, 20.0, white, 0.0));
+    objects.add(Box::new(Instance::new(medium, Transform::new(translation, Vector3::new(), Vector3::from(scale, scale, scale)))));
+
+    let material = Box::new(Metal::new(Colour::from(0.7, 0.6, 0.5), 0.0));
+    objects.add(Box::new(Sphere::new(Vector3::from(-2.5, 0.6, 3.8), 0.6, material)));
+
+    return objects;
+}
+
+/// Makes a cloud filling the unit cube out of a few overlapping soft blobs
+fn random_cloud() -> VoxelGrid {
+    let blobs: Vec<(Vector3, f64)> = (0..6)
+        .map(|_| (Vector3::from(random_f64_in_range(0.3..0.7), random_f64_in_range(0.3..0.6), random_f64_in_range(0.3..0.7)), random_f64_in_range(0.15..0.3)))
+        .collect();
+
+    let n = CLOUD_RESOLUTION;
+    let mut density = Vec::with_capacity(n * n * n);
+    for z in 0..n {
+        for y in 0..n {
+            for x in 0..n {
+                let p = Vector3::from(x as f64 + 0.5, y as f64 + 0.5, z as f64 + 0.5) / n as f64;
+                let value = blobs.iter().map(|&(centre, radius)| (1.0 - (p - centre).length() / radius).max(0.0)).fold(0.0, f64::max);
+                density.push((value * value) as f32);
+            }
+        }
+    }
+
+    return VoxelGrid::from_density([n, n, n], density).expect("the cloud has one density per voxel");
+}
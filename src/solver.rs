@@ -0,0 +1,191 @@
+//! Utility functions for finding the real roots of polynomials, used by the analytic shapes
+
+use std::f64::consts::PI;
+
+const EPSILON: f64 = 1e-12;
+
+/// Returns the real roots of *ax² + bx + c = 0*
+pub fn solve_quadratic(a: f64, b: f64, c: f64) -> Vec<f64> {
+    if a.abs() < EPSILON {
+        if b.abs() < EPSILON {
+            return vec![];
+        }
+        return vec![-c / b];
+    }
+
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return vec![];
+    }
+
+    // Avoids the cancellation error of the textbook formula when b is close to √discriminant
+    let q = -0.5 * (b + b.signum() * discriminant.sqrt());
+    if q.abs() < EPSILON {
+        return vec![0.0];
+    }
+
+    let (r0, r1) = (q / a, c / q);
+    return if r0 < r1 { vec![r0, r1] } else { vec![r1, r0] };
+}
+
+/// Returns the real roots of *ax³ + bx² + cx + d = 0*
+pub fn solve_cubic(a: f64, b: f64, c: f64, d: f64) -> Vec<f64> {
+    if a.abs() < EPSILON {
+        return solve_quadratic(b, c, d);
+    }
+
+    // Normalize to x³ + Ax² + Bx + C and substitute x = y - A/3 to get y³ + py + q
+    let (a2, a1, a0) = (b / a, c / a, d / a);
+    let sq_a = a2 * a2;
+    let p = (1.0 / 3.0) * (-(1.0 / 3.0) * sq_a + a1);
+    let q = 0.5 * ((2.0 / 27.0) * a2 * sq_a - (1.0 / 3.0) * a2 * a1 + a0);
+
+    let cb_p = p * p * p;
+    let discriminant = q * q + cb_p;
+    let sub = a2 / 3.0;
+
+    let mut roots = if discriminant.abs() < EPSILON {
+        if q.abs() < EPSILON {
+            vec![0.0]
+        } else {
+            let u = (-q).cbrt();
+            vec![2.0 * u, -u]
+        }
+    } else if discriminant < 0.0 {
+        // Three real roots, solved with the trigonometric method
+        let phi = (1.0 / 3.0) * (-q / (-cb_p).sqrt()).clamp(-1.0, 1.0).acos();
+        let t = 2.0 * (-p).sqrt();
+        vec![t * phi.cos(), -t * (phi + PI / 3.0).cos(), -t * (phi - PI / 3.0).cos()]
+    } else {
+        let sqrt_d = discriminant.sqrt();
+        vec![(sqrt_d - q).cbrt() - (sqrt_d + q).cbrt()]
+    };
+
+    for root in roots.iter_mut() {
+        *root -= sub;
+    }
+
+    roots.sort_by(|a, b| a.total_cmp(b));
+    return roots;
+}
+
+/// Returns the real roots of *ax⁴ + bx³ + cx² + dx + e = 0* in ascending order using Ferrari's method.
+/// Each root is polished with a few Newton iterations since the closed form loses precision quickly
+pub fn solve_quartic(a: f64, b: f64, c: f64, d: f64, e: f64) -> Vec<f64> {
+    if a.abs() < EPSILON {
+        return solve_cubic(b, c, d, e);
+    }
+
+    // Normalize to x⁴ + Ax³ + Bx² + Cx + D and substitute x = y - A/4 to get y⁴ + py² + qy + r
+    let (a3, a2, a1, a0) = (b / a, c / a, d / a, e / a);
+    let sq_a = a3 * a3;
+    let p = -3.0 / 8.0 * sq_a + a2;
+    let q = 1.0 / 8.0 * sq_a * a3 - 1.0 / 2.0 * a3 * a2 + a1;
+    let r = -3.0 / 256.0 * sq_a * sq_a + 1.0 / 16.0 * sq_a * a2 - 1.0 / 4.0 * a3 * a1 + a0;
+
+    let mut roots = if r.abs() < EPSILON {
+        // No absolute term, y(y³ + py + q) = 0
+        let mut roots = solve_cubic(1.0, 0.0, p, q);
+        roots.push(0.0);
+        roots
+    } else {
+        // Solve the resolvent cubic and use one of its roots to split into two quadratics
+        let z = *solve_cubic(1.0, -0.5 * p, -r, 0.5 * r * p - 1.0 / 8.0 * q * q).last().unwrap();
+
+        let mut u = z * z - r;
+        let mut v = 2.0 * z - p;
+
+        if u.abs() < EPSILON { u = 0.0; } else if u > 0.0 { u = u.sqrt(); } else { return vec![]; }
+        if v.abs() < EPSILON { v = 0.0; } else if v > 0.0 { v = v.sqrt(); } else { return vec![]; }
+
+        let mut roots = solve_quadratic(1.0, if q < 0.0 { -v } else { v }, z - u);
+        roots.append(&mut solve_quadratic(1.0, if q < 0.0 { v } else { -v }, z + u));
+        roots
+    };
+
+    let sub = a3 / 4.0;
+    for root in roots.iter_mut() {
+        *root -= sub;
+
+        for _ in 0..4 {
+            let x = *root;
+            let f = (((a * x + b) * x + c) * x + d) * x + e;
+            let df = ((4.0 * a * x + 3.0 * b) * x + 2.0 * c) * x + d;
+            if df.abs() < EPSILON {
+                break;
+            }
+            *root = x - f / df;
+        }
+    }
+
+    roots.sort_by(|a, b| a.total_cmp(b));
+    return roots;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_roots(roots: Vec<f64>, expected: &[f64]) {
+        assert_eq!(roots.len(), expected.len(), "got {:?}, expected {:?}", roots, expected);
+        for (root, expected) in roots.iter().zip(expected) {
+            assert!((root - expected).abs() < 1e-9, "got {:?}, expected {:?}", roots, expected);
+        }
+    }
+
+    #[test]
+    fn quadratic_roots() {
+        assert_roots(solve_quadratic(1.0, -4.0, 3.0), &[1.0, 3.0]);
+        assert_roots(solve_quadratic(2.0, 0.0, -8.0), &[-2.0, 2.0]);
+        assert_roots(solve_quadratic(1.0, 0.0, 1.0), &[]);
+        // Degenerates to the linear 2x - 4 = 0
+        assert_roots(solve_quadratic(0.0, 2.0, -4.0), &[2.0]);
+    }
+
+    #[test]
+    fn cubic_with_three_real_roots() {
+        // (x - 1)(x - 2)(x - 3)
+        assert_roots(solve_cubic(1.0, -6.0, 11.0, -6.0), &[1.0, 2.0, 3.0]);
+        // 2(x + 2)(x - 0.5)(x - 4)
+        assert_roots(solve_cubic(2.0, -5.0, -14.0, 8.0), &[-2.0, 0.5, 4.0]);
+    }
+
+    #[test]
+    fn cubic_with_one_real_root() {
+        // (x + 1)(x² - x + 2)
+        assert_roots(solve_cubic(1.0, 0.0, 1.0, 2.0), &[-1.0]);
+    }
+
+    #[test]
+    fn cubic_with_a_repeated_root() {
+        // (x - 1)²(x + 2)
+        assert_roots(solve_cubic(1.0, 0.0, -3.0, 2.0), &[-2.0, 1.0]);
+    }
+
+    #[test]
+    fn quartic_with_four_real_roots() {
+        // (x - 1)(x - 2)(x - 3)(x - 4)
+        assert_roots(solve_quartic(1.0, -10.0, 35.0, -50.0, 24.0), &[1.0, 2.0, 3.0, 4.0]);
+        // 3(x² - 1)(x² - 4), symmetric like a ray through the middle of a torus
+        assert_roots(solve_quartic(3.0, 0.0, -15.0, 0.0, 12.0), &[-2.0, -1.0, 1.0, 2.0]);
+    }
+
+    #[test]
+    fn quartic_with_two_real_roots() {
+        // (x - 1)(x + 3)(x² + 1)
+        assert_roots(solve_quartic(1.0, 2.0, -2.0, 2.0, -3.0), &[-3.0, 1.0]);
+    }
+
+    #[test]
+    fn quartic_without_real_roots() {
+        assert_roots(solve_quartic(1.0, 0.0, 0.0, 0.0, 1.0), &[]);
+        // (x² + 1)(x² + 4)
+        assert_roots(solve_quartic(1.0, 0.0, 5.0, 0.0, 4.0), &[]);
+    }
+
+    #[test]
+    fn quartic_without_an_absolute_term() {
+        // x(x - 1)(x - 2)(x + 1)
+        assert_roots(solve_quartic(1.0, -2.0, -1.0, 2.0, 0.0), &[-1.0, 0.0, 1.0, 2.0]);
+    }
+}
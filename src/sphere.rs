@@ -1,8 +1,12 @@
+use std::f64::consts::PI;
+
 use crate::{
-    hittable::{HitRecord, Hittable},
+    aabb::Aabb,
+    hittable::{spans_from_roots, HitRecord, HitSpan, Hittable},
     interval::Interval,
     material::Material,
     ray::Ray,
+    solver::solve_quadratic,
     vector3::dot_product,
     Vector3,
 };
@@ -11,12 +15,74 @@ pub struct Sphere {
     pub center: Vector3,
     pub radius: f64,
     pub material: Box<dyn Material>,
+    /// How far the center moves per unit of time, zero for a stationary sphere
+    pub velocity: Vector3,
+}
+
+impl Sphere {
+    /// Creates a stationary sphere
+    ///
+    /// ## Arguments
+    /// - `center` Center of the sphere
+    /// - `radius` Radius of the sphere
+    /// - `material` Material of the sphere
+    pub fn new(center: Vector3, radius: f64, material: Box<dyn Material>) -> Self {
+        Self {
+            center,
+            radius,
+            material,
+            velocity: Vector3::new(),
+        }
+    }
+
+    /// Creates a sphere which moves in a straight line, from `center0` at time 0 to `center1` at time 1
+    ///
+    /// ## Arguments
+    /// - `center0` Center of the sphere at time 0
+    /// - `center1` Center of the sphere at time 1
+    /// - `radius` Radius of the sphere
+    /// - `material` Material of the sphere
+    pub fn moving(center0: Vector3, center1: Vector3, radius: f64, material: Box<dyn Material>) -> Self {
+        Self {
+            center: center0,
+            radius,
+            material,
+            velocity: center1 - center0,
+        }
+    }
+
+    /// Returns the center of the sphere at a point in time
+    pub fn center_at(&self, time: f64) -> Vector3 {
+        return self.center + time * self.velocity;
+    }
+
+    /// Finds the UV coordinates of a point on a unit sphere centered at the origin.
+    /// U goes around the Y axis starting from X = -1 and V goes from Y = -1 to Y = 1
+    ///
+    /// ## Arguments
+    /// - `p` Point on the unit sphere
+    pub fn get_sphere_uv(p: Vector3) -> (f64, f64) {
+        let theta = (-p.y).acos();
+        let phi = (-p.z).atan2(p.x) + PI;
+
+        return (phi / (2.0 * PI), theta / PI);
+    }
+
+    /// Fills in the hit record for a hit at `t`
+    pub fn fill_record(&self, ray: &Ray, t: f64, rec: &mut HitRecord) {
+        rec.t = t;
+        rec.point = ray.at(rec.t);
+        let outward_normal = (rec.point - self.center_at(ray.time)) / self.radius;
+        rec.set_face_normal(ray, outward_normal);
+        (rec.u, rec.v) = Sphere::get_sphere_uv(outward_normal);
+        rec.material = self.material.clone();
+    }
 }
 
 impl Hittable for Sphere {
     /// Checks if a ray intersects with a sphere using the formula *-b + √b * b - 4ac / 2a*
-    fn hit(&mut self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
-        let oc = ray.origin - self.center;
+    fn hit(&self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        let oc = ray.origin - self.center_at(ray.time);
 
         let a = ray.direction.length_squared();
         let half_b = dot_product(oc, ray.direction);
@@ -37,12 +103,27 @@ impl Hittable for Sphere {
             }
         }
 
-        rec.t = root;
-        rec.point = ray.at(rec.t);
-        let outward_normal = (rec.point - self.center) / self.radius;
-        rec.set_face_normal(ray, outward_normal);
-        rec.material = self.material.clone();
+        self.fill_record(ray, root, rec);
 
         return true;
     }
+
+    fn hit_spans(&self, ray: &Ray, spans: &mut Vec<HitSpan>) -> bool {
+        let oc = ray.origin - self.center_at(ray.time);
+        let roots = solve_quadratic(ray.direction.length_squared(), 2.0 * dot_product(oc, ray.direction), oc.length_squared() - self.radius * self.radius);
+
+        spans.append(&mut spans_from_roots(ray, &roots, |r, t, rec| self.fill_record(r, t, rec)));
+        return true;
+    }
+
+    /// Covers the whole path of the sphere from time 0 to time 1
+    fn bounding_box(&self) -> Aabb {
+        let radius_vec = Vector3::from(self.radius, self.radius, self.radius);
+        let center1 = self.center_at(1.0);
+
+        return Aabb::from_boxes(
+            Aabb::from_points(self.center - radius_vec, self.center + radius_vec),
+            Aabb::from_points(center1 - radius_vec, center1 + radius_vec),
+        );
+    }
 }
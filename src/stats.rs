@@ -0,0 +1,247 @@
+use std::{
+    cell::Cell,
+    fs,
+    io,
+    time::Duration,
+};
+
+/// How much work tracing rays took, counted separately on each thread so counting never needs a lock
+#[derive(Copy, Clone, Debug, Default)]
+pub struct RayCounters {
+    /// Rays leaving the camera
+    pub primary_rays: u64,
+    /// Rays scattered off surfaces and through volumes
+    pub secondary_rays: u64,
+    /// Tests of a ray against an object added to a `HittableList`
+    pub intersection_tests: u64,
+    /// BVH nodes a ray was tested against
+    pub bvh_node_visits: u64,
+}
+
+impl RayCounters {
+    /// Adds another set of counts to these
+    pub fn add(&mut self, other: &RayCounters) {
+        self.primary_rays += other.primary_rays;
+        self.secondary_rays += other.secondary_rays;
+        self.intersection_tests += other.intersection_tests;
+        self.bvh_node_visits += other.bvh_node_visits;
+    }
+
+    /// Returns how many rays of every kind were cast
+    pub fn total_rays(&self) -> u64 {
+        return self.primary_rays + self.secondary_rays;
+    }
+}
+
+thread_local! {
+    static COUNTERS: Cell<RayCounters> = Cell::new(RayCounters::default());
+}
+
+/// Changes this thread's counters
+fn count(update: impl FnOnce(&mut RayCounters)) {
+    COUNTERS.with(|counters| {
+        let mut value = counters.get();
+        update(&mut value);
+        counters.set(value);
+    });
+}
+
+pub fn count_primary_ray() {
+    count(|c| c.primary_rays += 1);
+}
+
+pub fn count_secondary_ray() {
+    count(|c| c.secondary_rays += 1);
+}
+
+pub fn count_intersection_test() {
+    count(|c| c.intersection_tests += 1);
+}
+
+pub fn count_bvh_node_visit() {
+    count(|c| c.bvh_node_visits += 1);
+}
+
+/// Returns this thread's counts and starts them again from zero
+pub fn take_counters() -> RayCounters {
+    return COUNTERS.with(|counters| counters.take());
+}
+
+/// What it cost to render a film
+#[derive(Clone, Debug, Default)]
+pub struct RenderStats {
+    pub counters: RayCounters,
+    /// Wall clock time spent rendering
+    pub render_time: Duration,
+    /// Time each render thread spent tracing rays rather than waiting for work
+    pub thread_busy: Vec<Duration>,
+}
+
+impl RenderStats {
+    /// Adds the stats of another render, such as another frame of an animation.
+    /// Threads are matched up by their position so utilisation stays a fraction of the total render time
+    pub fn merge(&mut self, other: &RenderStats) {
+        self.counters.add(&other.counters);
+        self.render_time += other.render_time;
+
+        if self.thread_busy.len() < other.thread_busy.len() {
+            self.thread_busy.resize(other.thread_busy.len(), Duration::ZERO);
+        }
+        for (busy, other) in self.thread_busy.iter_mut().zip(&other.thread_busy) {
+            *busy += *other;
+        }
+    }
+
+    /// Returns millions of rays cast per second of rendering
+    pub fn mrays_per_second(&self) -> f64 {
+        let seconds = self.render_time.as_secs_f64();
+        if seconds == 0.0 {
+            return 0.0;
+        }
+        return self.counters.total_rays() as f64 / seconds / 1e6;
+    }
+
+    /// Returns the fraction of the render each thread spent busy
+    pub fn thread_utilisation(&self) -> Vec<f64> {
+        let seconds = self.render_time.as_secs_f64();
+        return self.thread_busy.iter().map(|busy| if seconds > 0.0 { (busy.as_secs_f64() / seconds).min(1.0) } else { 0.0 }).collect();
+    }
+}
+
+/// What it cost to build the scene
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SceneStats {
+    pub build_time: Duration,
+    /// Estimated memory the scene's objects take up, which the render threads share
+    pub memory_bytes: usize,
+}
+
+/// Prints a performance report
+///
+/// ## Arguments
+/// - `render` Stats of the render
+/// - `scene` Stats of building the scene
+pub fn print_report(render: &RenderStats, scene: &SceneStats) {
+    let counters = &render.counters;
+    let utilisation: Vec<String> = render.thread_utilisation().iter().map(|u| format!("{:.0}%", 100.0 * u)).collect();
+
+    println!("Render statistics");
+    println!("  Scene build:        {:.3} s", scene.build_time.as_secs_f64());
+    println!("  Scene memory:       {:.2} MiB", scene.memory_bytes as f64 / (1024.0 * 1024.0));
+    println!("  Render time:        {:.3} s", render.render_time.as_secs_f64());
+    println!("  Primary rays:       {}", counters.primary_rays);
+    println!("  Secondary rays:     {}", counters.secondary_rays);
+    println!("  Intersection tests: {}", counters.intersection_tests);
+    println!("  BVH node visits:    {}", counters.bvh_node_visits);
+    println!("  Mrays/s:            {:.3}", render.mrays_per_second());
+    println!("  Thread utilisation: {}", utilisation.join(" "));
+}
+
+/// Writes the performance report as JSON, so it can be compared between runs
+///
+/// ## Arguments
+/// - `path` File to write
+/// - `render` Stats of the render
+/// - `scene` Stats of building the scene
+pub fn write_json(path: &str, render: &RenderStats, scene: &SceneStats) -> io::Result<()> {
+    let counters = &render.counters;
+    let utilisation: Vec<String> = render.thread_utilisation().iter().map(|u| format!("{:.4}", u)).collect();
+
+    let json = format!(
+        r#"{{
+  "scene": {{
+    "build_seconds": {:.6},
+    "memory_bytes": {}
+  }},
+  "render": {{
+    "seconds": {:.6},
+    "primary_rays": {},
+    "secondary_rays": {},
+    "intersection_tests": {},
+    "bvh_node_visits": {},
+    "mrays_per_second": {:.6},
+    "thread_utilisation": [{}]
+  }}
+}}
+"#,
+        scene.build_time.as_secs_f64(),
+        scene.memory_bytes,
+        render.render_time.as_secs_f64(),
+        counters.primary_rays,
+        counters.secondary_rays,
+        counters.intersection_tests,
+        counters.bvh_node_visits,
+        render.mrays_per_second(),
+        utilisation.join(", "),
+    );
+
+    return fs::write(path, json);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(rays: u64, seconds: u64, busy: &[u64]) -> RenderStats {
+        return RenderStats {
+            counters: RayCounters { primary_rays: rays, secondary_rays: rays, intersection_tests: 10 * rays, bvh_node_visits: 5 * rays },
+            render_time: Duration::from_secs(seconds),
+            thread_busy: busy.iter().map(|&b| Duration::from_secs(b)).collect(),
+        };
+    }
+
+    #[test]
+    fn merging_adds_counts_and_times() {
+        let mut total = stats(100, 2, &[1, 2]);
+        total.merge(&stats(50, 4, &[3, 4]));
+
+        assert_eq!(total.counters.primary_rays, 150);
+        assert_eq!(total.counters.total_rays(), 300);
+        assert_eq!(total.counters.intersection_tests, 1500);
+        assert_eq!(total.counters.bvh_node_visits, 750);
+        assert_eq!(total.render_time, Duration::from_secs(6));
+        assert_eq!(total.thread_busy, vec![Duration::from_secs(4), Duration::from_secs(6)]);
+    }
+
+    #[test]
+    fn merging_a_render_with_more_threads_keeps_the_extra_ones() {
+        let mut total = stats(0, 2, &[2]);
+        total.merge(&stats(0, 2, &[1, 2, 2]));
+
+        assert_eq!(total.thread_busy, vec![Duration::from_secs(3), Duration::from_secs(2), Duration::from_secs(2)]);
+        assert_eq!(total.thread_utilisation(), vec![0.75, 0.5, 0.5]);
+    }
+
+    #[test]
+    fn utilisation_is_a_fraction_of_the_render_time() {
+        assert_eq!(stats(0, 4, &[4, 2, 0]).thread_utilisation(), vec![1.0, 0.5, 0.0]);
+        // Rounding in the timers can leave a thread a little busier than the render was long
+        assert_eq!(stats(0, 4, &[5]).thread_utilisation(), vec![1.0]);
+        assert_eq!(stats(0, 0, &[1]).thread_utilisation(), vec![0.0]);
+    }
+
+    #[test]
+    fn rays_per_second() {
+        assert!((stats(1_000_000, 2, &[]).mrays_per_second() - 1.0).abs() < 1e-12);
+        assert_eq!(stats(1_000_000, 0, &[]).mrays_per_second(), 0.0);
+    }
+
+    #[test]
+    fn counters_are_taken_per_thread() {
+        take_counters();
+        count_primary_ray();
+        count_secondary_ray();
+        count_secondary_ray();
+        count_bvh_node_visit();
+
+        let other_thread = std::thread::spawn(|| {
+            count_primary_ray();
+            return take_counters();
+        });
+        assert_eq!(other_thread.join().unwrap().primary_rays, 1);
+
+        let counters = take_counters();
+        assert_eq!((counters.primary_rays, counters.secondary_rays, counters.bvh_node_visits), (1, 2, 1));
+        assert_eq!(take_counters().total_rays(), 0, "taking the counters starts them again");
+    }
+}
@@ -0,0 +1,222 @@
+use crate::colour::Colour;
+
+/// Curve which compresses high dynamic range radiance into the 0 to 1 range of a display
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ToneMapper {
+    /// Values above 1 are clipped
+    Clamp,
+    /// `c / (1 + c)`
+    Reinhard,
+    /// Reinhard which maps `white_point` to 1 so highlights can reach full brightness
+    ExtendedReinhard { white_point: f64 },
+    /// Krzysztof Narkowicz's fit of the ACES filmic curve
+    AcesFilmic,
+    /// John Hable's filmic curve from Uncharted 2
+    Hable,
+    /// Troy Sobotka's AgX, which desaturates bright colours instead of skewing their hue
+    AgX,
+}
+
+impl ToneMapper {
+    /// Every tone mapper in the order the viewer cycles through them
+    pub const ALL: [ToneMapper; 6] = [
+        ToneMapper::Clamp,
+        ToneMapper::Reinhard,
+        ToneMapper::ExtendedReinhard { white_point: 4.0 },
+        ToneMapper::AcesFilmic,
+        ToneMapper::Hable,
+        ToneMapper::AgX,
+    ];
+
+    /// Finds a tone mapper by the name used on the command line
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "clamp" | "none" => return Some(ToneMapper::Clamp),
+            "reinhard" => return Some(ToneMapper::Reinhard),
+            "extended-reinhard" => return Some(ToneMapper::ExtendedReinhard { white_point: 4.0 }),
+            "aces" => return Some(ToneMapper::AcesFilmic),
+            "hable" => return Some(ToneMapper::Hable),
+            "agx" => return Some(ToneMapper::AgX),
+            _ => return None,
+        }
+    }
+
+    /// Returns the name used on the command line
+    pub fn name(&self) -> &'static str {
+        match self {
+            ToneMapper::Clamp => "clamp",
+            ToneMapper::Reinhard => "reinhard",
+            ToneMapper::ExtendedReinhard { .. } => "extended-reinhard",
+            ToneMapper::AcesFilmic => "aces",
+            ToneMapper::Hable => "hable",
+            ToneMapper::AgX => "agx",
+        }
+    }
+
+    /// Returns the tone mapper after this one in `ToneMapper::ALL`
+    pub fn next(&self) -> Self {
+        let index = ToneMapper::ALL.iter().position(|t| t.name() == self.name()).unwrap_or(0);
+        return ToneMapper::ALL[(index + 1) % ToneMapper::ALL.len()];
+    }
+
+    /// Maps linear scene radiance to linear display values between 0 and 1
+    pub fn apply(&self, c: Colour) -> Colour {
+        let per_channel = |f: &dyn Fn(f64) -> f64| Colour::from(f(c.x), f(c.y), f(c.z));
+
+        let mapped = match *self {
+            ToneMapper::Clamp => c,
+            ToneMapper::Reinhard => per_channel(&|x| x / (1.0 + x)),
+            ToneMapper::ExtendedReinhard { white_point } => {
+                per_channel(&|x| x * (1.0 + x / (white_point * white_point)) / (1.0 + x))
+            }
+            ToneMapper::AcesFilmic => per_channel(&|x| (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)),
+            ToneMapper::Hable => {
+                let exposure_bias = 2.0;
+                let white_scale = 1.0 / hable_partial(11.2);
+                per_channel(&|x| hable_partial(x * exposure_bias) * white_scale)
+            }
+            ToneMapper::AgX => agx(c),
+        };
+
+        return Colour::from(mapped.x.clamp(0.0, 1.0), mapped.y.clamp(0.0, 1.0), mapped.z.clamp(0.0, 1.0));
+    }
+}
+
+fn hable_partial(x: f64) -> f64 {
+    let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+    return ((x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f)) - e / f;
+}
+
+/// Multiplies a colour by a row major 3x3 matrix
+fn apply_matrix(m: [[f64; 3]; 3], c: Colour) -> Colour {
+    Colour::from(
+        m[0][0] * c.x + m[0][1] * c.y + m[0][2] * c.z,
+        m[1][0] * c.x + m[1][1] * c.y + m[1][2] * c.z,
+        m[2][0] * c.x + m[2][1] * c.y + m[2][2] * c.z,
+    )
+}
+
+/// Minimal AgX based on Benjamin Wrensch's fit of the Blender AgX base look
+fn agx(c: Colour) -> Colour {
+    const INSET: [[f64; 3]; 3] = [
+        [0.842479062253094, 0.0784335999999992, 0.0792237451477643],
+        [0.0423282422610123, 0.878468636469772, 0.0791661274605434],
+        [0.0423756549057051, 0.0784336, 0.879142973793104],
+    ];
+    const OUTSET: [[f64; 3]; 3] = [
+        [1.19687900512017, -0.0980208811401368, -0.0990297440797205],
+        [-0.0528968517574562, 1.15190312990417, -0.0989611768448433],
+        [-0.0529716355144438, -0.0980434501171241, 1.15107367264116],
+    ];
+    const MIN_EV: f64 = -12.47393;
+    const MAX_EV: f64 = 4.026069;
+
+    let inset = apply_matrix(INSET, c);
+
+    let contrast = |x: f64| {
+        // Encode to a log curve then apply the sigmoid as a 6th order polynomial
+        let x = ((x.max(1e-10).log2() - MIN_EV) / (MAX_EV - MIN_EV)).clamp(0.0, 1.0);
+        let x2 = x * x;
+        let x4 = x2 * x2;
+        15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x - 0.00232
+    };
+    let curved = Colour::from(contrast(inset.x), contrast(inset.y), contrast(inset.z));
+
+    // The curve outputs display encoded values, so undo the 2.2 gamma to return linear values
+    let outset = apply_matrix(OUTSET, curved);
+    return Colour::from(outset.x.max(0.0).powf(2.2), outset.y.max(0.0).powf(2.2), outset.z.max(0.0).powf(2.2));
+}
+
+/// How the linear film is turned into an image for a display, applied only when the film is output
+#[derive(Copy, Clone, Debug)]
+pub struct DisplaySettings {
+    /// Exposure adjustment in stops, each stop doubles the brightness
+    pub exposure: f64,
+    pub tone_mapper: ToneMapper,
+}
+
+impl Default for DisplaySettings {
+    fn default() -> Self {
+        Self {
+            exposure: 0.0,
+            tone_mapper: ToneMapper::Clamp,
+        }
+    }
+}
+
+impl DisplaySettings {
+    /// Applies exposure and tone mapping to linear radiance, giving linear display values between 0 and 1
+    pub fn map(&self, linear: Colour) -> Colour {
+        return self.tone_mapper.apply(linear * 2.0_f64.powf(self.exposure));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grey(value: f64) -> Colour {
+        return Colour::from(value, value, value);
+    }
+
+    #[test]
+    fn black_stays_black() {
+        for tone_mapper in ToneMapper::ALL {
+            let mapped = tone_mapper.apply(grey(0.0));
+            assert!(mapped.x.abs() < 1e-3, "{} maps black to {:?}", tone_mapper.name(), mapped);
+        }
+    }
+
+    #[test]
+    fn output_is_in_range_and_increases_with_brightness() {
+        for tone_mapper in ToneMapper::ALL {
+            let mut previous = 0.0;
+            for step in 1..200 {
+                let mapped = tone_mapper.apply(grey(step as f64 * 0.1)).x;
+                assert!((0.0..=1.0).contains(&mapped), "{} gives {}", tone_mapper.name(), mapped);
+                assert!(mapped >= previous, "{} gets darker at {}", tone_mapper.name(), step as f64 * 0.1);
+                previous = mapped;
+            }
+        }
+    }
+
+    #[test]
+    fn reinhard_halves_one_and_approaches_white() {
+        assert!((ToneMapper::Reinhard.apply(grey(1.0)).x - 0.5).abs() < 1e-12);
+        assert!(ToneMapper::Reinhard.apply(grey(1000.0)).x > 0.99);
+    }
+
+    #[test]
+    fn extended_reinhard_reaches_white_at_the_white_point() {
+        let tone_mapper = ToneMapper::ExtendedReinhard { white_point: 4.0 };
+        assert!((tone_mapper.apply(grey(4.0)).x - 1.0).abs() < 1e-12);
+        assert!(tone_mapper.apply(grey(2.0)).x < 1.0);
+    }
+
+    #[test]
+    fn filmic_curves_saturate_to_white() {
+        // ACES passes 1 a little past 10 and Hable reaches it when the biased input hits its white point of 11.2
+        assert_eq!(ToneMapper::AcesFilmic.apply(grey(100.0)).x, 1.0);
+        assert!((ToneMapper::Hable.apply(grey(5.6)).x - 1.0).abs() < 1e-12);
+        assert!(ToneMapper::AgX.apply(grey(16.0)).x > 0.95);
+    }
+
+    #[test]
+    fn clamp_only_clips() {
+        let mapped = ToneMapper::Clamp.apply(Colour::from(0.25, 3.0, -1.0));
+        assert_eq!((mapped.x, mapped.y, mapped.z), (0.25, 1.0, 0.0));
+    }
+
+    #[test]
+    fn every_tone_mapper_is_found_by_its_name() {
+        for tone_mapper in ToneMapper::ALL {
+            assert_eq!(ToneMapper::from_name(tone_mapper.name()), Some(tone_mapper));
+        }
+    }
+
+    #[test]
+    fn each_stop_of_exposure_doubles_the_input() {
+        let settings = DisplaySettings { exposure: 1.0, tone_mapper: ToneMapper::Reinhard };
+        assert!((settings.map(grey(0.5)).x - ToneMapper::Reinhard.apply(grey(1.0)).x).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,178 @@
+use std::f64::consts::PI;
+
+use crate::{
+    aabb::Aabb,
+    hittable::{spans_from_roots, HitRecord, HitSpan, Hittable},
+    interval::Interval,
+    material::Material,
+    onb::Onb,
+    ray::Ray,
+    solver::solve_quartic,
+    vector3::{dot_product, Vector3},
+};
+
+/// A ring shaped torus around `axis`.
+/// U goes around the axis and V goes around the tube, starting from the outside edge
+#[derive(Clone)]
+pub struct Torus {
+    pub center: Vector3,
+    pub axis: Vector3,
+    pub major_radius: f64,
+    pub minor_radius: f64,
+    pub material: Box<dyn Material>,
+    basis: Onb,
+}
+
+impl Torus {
+    /// Creates a torus
+    ///
+    /// ## Arguments
+    /// - `center` Center of the ring
+    /// - `axis` Axis the ring goes around, does not need to be a unit vector
+    /// - `major_radius` Distance from the center to the middle of the tube
+    /// - `minor_radius` Radius of the tube
+    /// - `material` Material of the torus
+    pub fn new(center: Vector3, axis: Vector3, major_radius: f64, minor_radius: f64, material: Box<dyn Material>) -> Self {
+        Self {
+            center,
+            axis: axis.unit(),
+            major_radius,
+            minor_radius,
+            material,
+            basis: Onb::from_w(axis),
+        }
+    }
+
+    /// Returns every t value where the ray crosses the surface of the torus in ascending order.
+    /// Solves the quartic *(|p|² + R² - r²)² = 4R²(x² + y²)*
+    pub fn intersections(&self, ray: &Ray) -> Vec<f64> {
+        let mut o = self.basis.world_to_local(ray.origin - self.center);
+        let d = self.basis.world_to_local(ray.direction);
+        let dd = d.length_squared();
+
+        // Quartic roots lose precision far from the origin, so start the ray at the closest point to the torus center
+        let t_offset = -dot_product(o, d) / dd;
+        o += t_offset * d;
+
+        let r2 = self.major_radius * self.major_radius;
+        let g0 = o.length_squared() + r2 - self.minor_radius * self.minor_radius;
+        let g1 = 2.0 * dot_product(o, d);
+
+        let h2 = d.x * d.x + d.y * d.y;
+        let h1 = 2.0 * (o.x * d.x + o.y * d.y);
+        let h0 = o.x * o.x + o.y * o.y;
+
+        let mut roots = solve_quartic(
+            dd * dd,
+            2.0 * dd * g1,
+            g1 * g1 + 2.0 * dd * g0 - 4.0 * r2 * h2,
+            2.0 * g1 * g0 - 4.0 * r2 * h1,
+            g0 * g0 - 4.0 * r2 * h0,
+        );
+
+        for root in roots.iter_mut() {
+            *root += t_offset;
+        }
+
+        return roots;
+    }
+
+    /// Fills in the hit record for a hit at `t`
+    pub fn fill_record(&self, ray: &Ray, t: f64, rec: &mut HitRecord) {
+        rec.t = t;
+        rec.point = ray.at(t);
+
+        let p = self.basis.world_to_local(rec.point - self.center);
+        let rho = (p.x * p.x + p.y * p.y).sqrt();
+
+        // The normal points from the closest point on the center ring of the tube to the hit point
+        let ring_point = if rho > 1e-12 { Vector3::from(p.x / rho, p.y / rho, 0.0) * self.major_radius } else { Vector3::new() };
+        let outward_normal = self.basis.local(p - ring_point).unit();
+
+        rec.u = (p.y.atan2(p.x) + PI) / (2.0 * PI);
+        rec.v = (p.z.atan2(rho - self.major_radius) + PI) / (2.0 * PI);
+
+        rec.set_face_normal(ray, outward_normal);
+        rec.material = self.material.clone();
+    }
+}
+
+impl Hittable for Torus {
+    fn hit(&self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        match self.intersections(ray).into_iter().find(|t| ray_t.surrounds(*t)) {
+            Some(t) => {
+                self.fill_record(ray, t, rec);
+                return true;
+            }
+            None => return false,
+        }
+    }
+
+    fn hit_spans(&self, ray: &Ray, spans: &mut Vec<HitSpan>) -> bool {
+        spans.append(&mut spans_from_roots(ray, &self.intersections(ray), |r, t, rec| self.fill_record(r, t, rec)));
+        return true;
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        // The ring is a circle of radius R around the axis, thickened by r in every direction
+        let extent = |n: f64| self.major_radius * (1.0 - n * n).max(0.0).sqrt() + self.minor_radius;
+        let e = Vector3::from(extent(self.axis.x), extent(self.axis.y), extent(self.axis.z));
+
+        return Aabb::from_points(self.center - e, self.center + e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colour::Colour;
+    use crate::material::Lambertian;
+
+    fn ring() -> Torus {
+        return Torus::new(Vector3::new(), Vector3::from(0.0, 1.0, 0.0), 2.0, 0.5, Box::new(Lambertian::new(Colour::from(0.5, 0.5, 0.5))));
+    }
+
+    fn normal_at(torus: &Torus, ray: &Ray, t: f64) -> Vector3 {
+        let mut rec = HitRecord::new();
+        torus.fill_record(ray, t, &mut rec);
+        return rec.normal;
+    }
+
+    #[test]
+    fn ray_through_the_middle_crosses_the_tube_four_times() {
+        let torus = ring();
+        let ray = Ray::from(Vector3::from(-5.0, 0.0, 0.0), Vector3::from(1.0, 0.0, 0.0));
+        let roots = torus.intersections(&ray);
+
+        assert_eq!(roots.len(), 4, "{:?}", roots);
+        for (root, expected) in roots.iter().zip([2.5, 3.5, 6.5, 7.5]) {
+            assert!((root - expected).abs() < 1e-6, "{:?}", roots);
+        }
+
+        assert!((normal_at(&torus, &ray, roots[0]) - Vector3::from(-1.0, 0.0, 0.0)).length() < 1e-6);
+
+        // Leaving the tube on the side facing the hole
+        let mut rec = HitRecord::new();
+        torus.fill_record(&ray, roots[1], &mut rec);
+        assert!(!rec.front_face);
+        assert!((rec.normal - Vector3::from(-1.0, 0.0, 0.0)).length() < 1e-6);
+    }
+
+    #[test]
+    fn ray_along_the_axis_passes_through_the_hole() {
+        let ray = Ray::from(Vector3::from(0.0, -5.0, 0.0), Vector3::from(0.0, 1.0, 0.0));
+        assert!(ring().intersections(&ray).is_empty());
+    }
+
+    #[test]
+    fn ray_down_onto_the_tube_hits_its_top() {
+        let torus = ring();
+        let ray = Ray::from(Vector3::from(2.0, 5.0, 0.0), Vector3::from(0.0, -1.0, 0.0));
+        let roots = torus.intersections(&ray);
+
+        assert_eq!(roots.len(), 2, "{:?}", roots);
+        assert!((roots[0] - 4.5).abs() < 1e-6);
+        assert!((roots[1] - 5.5).abs() < 1e-6);
+        assert!((normal_at(&torus, &ray, roots[0]) - Vector3::from(0.0, 1.0, 0.0)).length() < 1e-6);
+    }
+}
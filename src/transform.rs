@@ -0,0 +1,325 @@
+use crate::{
+    aabb::Aabb,
+    hittable::{HitRecord, HitSpan, Hittable},
+    interval::Interval,
+    ray::Ray,
+    vector3::{dot_product, Vector3},
+};
+
+/// Row major 3x3 matrix
+#[derive(Copy, Clone, Debug)]
+pub struct Matrix3 {
+    pub rows: [Vector3; 3],
+}
+
+impl Matrix3 {
+    /// Creates a rotation matrix from euler angles in degrees, applied around X then Y then Z
+    pub fn from_euler_degrees(angles: Vector3) -> Self {
+        let (sx, cx) = angles.x.to_radians().sin_cos();
+        let (sy, cy) = angles.y.to_radians().sin_cos();
+        let (sz, cz) = angles.z.to_radians().sin_cos();
+
+        let rx = Matrix3 { rows: [Vector3::from(1.0, 0.0, 0.0), Vector3::from(0.0, cx, -sx), Vector3::from(0.0, sx, cx)] };
+        let ry = Matrix3 { rows: [Vector3::from(cy, 0.0, sy), Vector3::from(0.0, 1.0, 0.0), Vector3::from(-sy, 0.0, cy)] };
+        let rz = Matrix3 { rows: [Vector3::from(cz, -sz, 0.0), Vector3::from(sz, cz, 0.0), Vector3::from(0.0, 0.0, 1.0)] };
+
+        return rz.multiply(&ry).multiply(&rx);
+    }
+
+    /// Creates a matrix which scales each axis
+    pub fn from_scale(scale: Vector3) -> Self {
+        Self {
+            rows: [Vector3::from(scale.x, 0.0, 0.0), Vector3::from(0.0, scale.y, 0.0), Vector3::from(0.0, 0.0, scale.z)],
+        }
+    }
+
+    /// Returns the matrix with its rows and columns swapped
+    pub fn transpose(&self) -> Self {
+        let r = &self.rows;
+        Self {
+            rows: [
+                Vector3::from(r[0].x, r[1].x, r[2].x),
+                Vector3::from(r[0].y, r[1].y, r[2].y),
+                Vector3::from(r[0].z, r[1].z, r[2].z),
+            ],
+        }
+    }
+
+    /// Multiplies two matrices, `self * other`
+    pub fn multiply(&self, other: &Matrix3) -> Self {
+        let columns = other.transpose();
+        let row = |r: Vector3| Vector3::from(dot_product(r, columns.rows[0]), dot_product(r, columns.rows[1]), dot_product(r, columns.rows[2]));
+
+        Self {
+            rows: [row(self.rows[0]), row(self.rows[1]), row(self.rows[2])],
+        }
+    }
+
+    /// Multiplies a column vector by the matrix
+    pub fn apply(&self, v: Vector3) -> Vector3 {
+        return Vector3::from(dot_product(self.rows[0], v), dot_product(self.rows[1], v), dot_product(self.rows[2], v));
+    }
+}
+
+/// Places an object in the world by scaling, then rotating (euler angles in degrees) and then translating it
+#[derive(Copy, Clone, Debug)]
+pub struct Transform {
+    pub translation: Vector3,
+    pub rotation: Vector3,
+    pub scale: Vector3,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            translation: Vector3::new(),
+            rotation: Vector3::new(),
+            scale: Vector3::from(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+impl Transform {
+    /// Creates a transform
+    ///
+    /// ## Arguments
+    /// - `translation` Offset of the object
+    /// - `rotation` Euler angles in degrees, applied around X then Y then Z
+    /// - `scale` Scale along each axis
+    pub fn new(translation: Vector3, rotation: Vector3, scale: Vector3) -> Self {
+        Self {
+            translation,
+            rotation,
+            scale,
+        }
+    }
+
+    /// Linearly interpolates each part of two transforms
+    ///
+    /// ## Arguments
+    /// - `a` Transform when `t` is 0
+    /// - `b` Transform when `t` is 1
+    /// - `t` How far to go from `a` to `b`
+    pub fn lerp(a: &Transform, b: &Transform, t: f64) -> Self {
+        Self {
+            translation: (1.0 - t) * a.translation + t * b.translation,
+            rotation: (1.0 - t) * a.rotation + t * b.rotation,
+            scale: (1.0 - t) * a.scale + t * b.scale,
+        }
+    }
+
+    /// Matrix which takes directions from local space into world space
+    pub fn linear(&self) -> Matrix3 {
+        return Matrix3::from_euler_degrees(self.rotation).multiply(&Matrix3::from_scale(self.scale));
+    }
+
+    /// Matrix which takes directions from world space into local space
+    pub fn inverse_linear(&self) -> Matrix3 {
+        let inverse_scale = Vector3::from(1.0 / self.scale.x, 1.0 / self.scale.y, 1.0 / self.scale.z);
+        return Matrix3::from_scale(inverse_scale).multiply(&Matrix3::from_euler_degrees(self.rotation).transpose());
+    }
+
+    /// Converts a ray in world space into local space. The t values of hits are the same in both spaces
+    pub fn ray_to_local(&self, ray: &Ray) -> Ray {
+        let inverse = self.inverse_linear();
+        return Ray::with_time(inverse.apply(ray.origin - self.translation), inverse.apply(ray.direction), ray.time);
+    }
+
+    /// Converts a hit record in local space into world space
+    pub fn record_to_world(&self, rec: &mut HitRecord) {
+        rec.point = self.linear().apply(rec.point) + self.translation;
+
+        // Normals use the inverse transpose so they stay perpendicular to a non uniformly scaled surface
+        rec.normal = self.inverse_linear().transpose().apply(rec.normal).unit();
+    }
+
+    /// Converts a bounding box in local space into world space by transforming all of its corners
+    pub fn box_to_world(&self, bbox: Aabb) -> Aabb {
+        let is_infinite = |i: Interval| i.min.is_infinite() || i.max.is_infinite();
+        if is_infinite(bbox.x) || is_infinite(bbox.y) || is_infinite(bbox.z) {
+            return Aabb::UNIVERSE;
+        }
+
+        let linear = self.linear();
+        let mut result = Aabb::new();
+
+        for corner in 0..8 {
+            let local = Vector3::from(
+                if corner & 1 == 0 { bbox.x.min } else { bbox.x.max },
+                if corner & 2 == 0 { bbox.y.min } else { bbox.y.max },
+                if corner & 4 == 0 { bbox.z.min } else { bbox.z.max },
+            );
+            let world = linear.apply(local) + self.translation;
+            result = Aabb::from_boxes(result, Aabb::from_points(world, world));
+        }
+
+        return result;
+    }
+}
+
+/// Number of points in time sampled to bound the path of a moving instance
+const MOTION_BOUND_STEPS: usize = 16;
+
+/// An object which has been moved, rotated and scaled.
+/// If it has an end transform it moves from `transform` at time 0 to `end_transform` at time 1
+#[derive(Clone)]
+pub struct Instance {
+    pub object: Box<dyn Hittable>,
+    pub transform: Transform,
+    pub end_transform: Option<Transform>,
+}
+
+impl Instance {
+    /// Creates an instance
+    ///
+    /// ## Arguments
+    /// - `object` Object to place in the world
+    /// - `transform` Where to place the object
+    pub fn new(object: Box<dyn Hittable>, transform: Transform) -> Self {
+        Self {
+            object,
+            transform,
+            end_transform: None,
+        }
+    }
+
+    /// Creates an instance which moves between two transforms during the shutter interval
+    ///
+    /// ## Arguments
+    /// - `object` Object to place in the world
+    /// - `start` Where the object is at time 0
+    /// - `end` Where the object is at time 1
+    pub fn moving(object: Box<dyn Hittable>, start: Transform, end: Transform) -> Self {
+        Self {
+            object,
+            transform: start,
+            end_transform: Some(end),
+        }
+    }
+
+    /// Returns the transform of the instance at a point in time
+    pub fn transform_at(&self, time: f64) -> Transform {
+        match &self.end_transform {
+            Some(end) => return Transform::lerp(&self.transform, end, time),
+            None => return self.transform,
+        }
+    }
+}
+
+impl Hittable for Instance {
+    fn hit(&self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        let transform = self.transform_at(ray.time);
+        let local_ray = transform.ray_to_local(ray);
+
+        if !self.object.hit(&local_ray, ray_t, rec) {
+            return false;
+        }
+
+        transform.record_to_world(rec);
+        return true;
+    }
+
+    /// Covers the object at several points along its path, since rotation can swing it outside the boxes at either end
+    fn bounding_box(&self) -> Aabb {
+        let local_box = self.object.bounding_box();
+
+        if self.end_transform.is_none() {
+            return self.transform.box_to_world(local_box);
+        }
+
+        let mut bbox = Aabb::new();
+        for step in 0..=MOTION_BOUND_STEPS {
+            let time = step as f64 / MOTION_BOUND_STEPS as f64;
+            bbox = Aabb::from_boxes(bbox, self.transform_at(time).box_to_world(local_box));
+        }
+
+        return bbox;
+    }
+
+    fn hit_spans(&self, ray: &Ray, spans: &mut Vec<HitSpan>) -> bool {
+        let transform = self.transform_at(ray.time);
+        let local_ray = transform.ray_to_local(ray);
+        let mut local_spans = vec![];
+
+        if !self.object.hit_spans(&local_ray, &mut local_spans) {
+            return false;
+        }
+
+        for mut span in local_spans {
+            transform.record_to_world(&mut span.enter);
+            transform.record_to_world(&mut span.exit);
+            spans.push(span);
+        }
+
+        return true;
+    }
+
+    fn assign_object_ids(&mut self, next_id: &mut u32) {
+        self.object.assign_object_ids(next_id);
+    }
+
+    fn memory_size(&self) -> usize {
+        return size_of_val(self) + self.object.memory_size();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{aa_box::AxisAlignedBox, colour::Colour, material::Lambertian};
+
+    fn assert_close(a: Vector3, b: Vector3) {
+        assert!((a - b).length() < 1e-9, "{:?} != {:?}", a, b);
+    }
+
+    fn normal_to_world(transform: &Transform, normal: Vector3) -> Vector3 {
+        let mut rec = HitRecord::new();
+        rec.normal = normal;
+        transform.record_to_world(&mut rec);
+        return rec.normal;
+    }
+
+    #[test]
+    fn normals_stay_perpendicular_under_non_uniform_scale() {
+        // The local plane x + y = 1 becomes x / 2 + y = 1 when stretched along X
+        let transform = Transform::new(Vector3::from(3.0, -1.0, 2.0), Vector3::new(), Vector3::from(2.0, 1.0, 1.0));
+        let normal = normal_to_world(&transform, Vector3::from(1.0, 1.0, 0.0).unit());
+        assert_close(normal, Vector3::from(0.5, 1.0, 0.0).unit());
+
+        // Any direction along the surface is still perpendicular to the normal once transformed
+        let along_surface = transform.linear().apply(Vector3::from(1.0, -1.0, 0.0));
+        assert!(dot_product(normal, along_surface).abs() < 1e-9);
+    }
+
+    #[test]
+    fn normals_rotate_with_the_object() {
+        let transform = Transform::new(Vector3::new(), Vector3::from(0.0, 0.0, 90.0), Vector3::from(3.0, 3.0, 3.0));
+        assert_close(normal_to_world(&transform, Vector3::from(1.0, 0.0, 0.0)), Vector3::from(0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn inverse_linear_undoes_linear() {
+        let transform = Transform::new(Vector3::new(), Vector3::from(30.0, -45.0, 60.0), Vector3::from(2.0, 0.5, 3.0));
+        let v = Vector3::from(0.3, -1.2, 2.5);
+        assert_close(transform.inverse_linear().apply(transform.linear().apply(v)), v);
+    }
+
+    #[test]
+    fn moving_instance_box_covers_the_whole_path() {
+        // A block off to the side swings half a turn around the Y axis, passing through places neither end is near
+        let material = Box::new(Lambertian::new(Colour::from(0.5, 0.5, 0.5)));
+        let block = AxisAlignedBox::new(Vector3::from(2.0, 0.0, -0.5), Vector3::from(3.0, 1.0, 0.5), material);
+        let local_box = block.bounding_box();
+        let instance = Instance::moving(Box::new(block), Transform::default(), Transform::new(Vector3::new(), Vector3::from(0.0, 180.0, 0.0), Vector3::from(1.0, 1.0, 1.0)));
+
+        let bbox = instance.bounding_box();
+        for step in 0..=50 {
+            let moved = instance.transform_at(step as f64 / 50.0).box_to_world(local_box);
+            for axis in 0..3 {
+                // The box is built from sampled times, the arc between two samples bulges out by less than 0.02
+                assert!(moved.axis(axis).min >= bbox.axis(axis).min - 0.05, "{:?} at step {}", axis, step);
+                assert!(moved.axis(axis).max <= bbox.axis(axis).max + 0.05, "{:?} at step {}", axis, step);
+            }
+        }
+    }
+}
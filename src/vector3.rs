@@ -1,6 +1,6 @@
-use std::{ops::{self, Range}, fmt::Display};
+use std::{ops::{self, Range}, fmt::Display, f64::consts::PI};
 
-use crate::random::{random_f64, random_f64_in_range};
+use crate::random::{random_2d, random_f64, random_f64_in_range};
 
 #[derive(Copy, Clone, Debug)]
 pub struct Vector3 {
@@ -126,6 +126,18 @@ impl ops::Mul<Vector3> for i64 {
     }
 }
 
+impl ops::Index<usize> for Vector3 {
+    type Output = f64;
+    fn index(&self, index: usize) -> &Self::Output {
+        match index {
+            0 => &self.x,
+            1 => &self.y,
+            2 => &self.z,
+            _ => panic!("Vector3 index out of range: {}", index),
+        }
+    }
+}
+
 impl ops::Div<f64> for Vector3 {
     type Output = Self;
     fn div(self, rhs: f64) -> Self::Output {
@@ -219,6 +231,7 @@ pub fn cross_product(a: Vector3, b: Vector3) -> Vector3 {
 }
 
 /// Returns a random vector within a unit sphere
+#[allow(dead_code)]
 pub fn random_in_unit_sphere() -> Vector3 {
         loop {
             let p = Vector3::random_in_range(-1.0..1.0);
@@ -229,9 +242,14 @@ pub fn random_in_unit_sphere() -> Vector3 {
         }
 }
 
-/// Returns a random unit sphere's unit
+/// Returns a random direction, spread evenly over the unit sphere
 pub fn random_unit_vector() -> Vector3 {
-    return random_in_unit_sphere().unit();
+    let (u1, u2) = random_2d();
+    let z = 1.0 - 2.0 * u1;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * u2;
+
+    return Vector3::from(r * phi.cos(), r * phi.sin(), z);
 }
 
 /// Finds a random point in a hemisphere
@@ -239,6 +257,7 @@ pub fn random_unit_vector() -> Vector3 {
 /// ## Arguments
 ///
 /// - `normal` The normal of the hemisphere
+#[allow(dead_code)]
 pub fn random_on_hemisphere(normal: Vector3) -> Vector3 {
     let on_unit_sphere = random_unit_vector();
     if dot_product(on_unit_sphere, normal) > 0.0 {
@@ -263,11 +282,20 @@ pub fn refract(uv: Vector3, n: Vector3, etai_over_etat: f64) -> Vector3 {
     return r_out_perp + r_out_parallel;
 }
 
+/// Returns a random point in the unit disk on the xy plane, using Shirley's concentric mapping so a well spread out square stays well spread out
 pub fn random_in_unit_disk() -> Vector3 {
-    loop {
-        let p = Vector3::from(random_f64_in_range(-1.0..1.0), random_f64_in_range(-1.0..1.0), 0.0);
-        if p.length_squared() < 1.0 {
-            return p;
-        }
+    let (u1, u2) = random_2d();
+    let (a, b) = (2.0 * u1 - 1.0, 2.0 * u2 - 1.0);
+
+    if a == 0.0 && b == 0.0 {
+        return Vector3::new();
     }
+
+    let (r, theta) = if a.abs() > b.abs() {
+        (a, PI / 4.0 * (b / a))
+    } else {
+        (b, PI / 2.0 - PI / 4.0 * (a / b))
+    };
+
+    return Vector3::from(r * theta.cos(), r * theta.sin(), 0.0);
 }
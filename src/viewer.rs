@@ -1,8 +1,23 @@
-use minifb::{Key, Window, WindowOptions};
+use minifb::{Key, KeyRepeat, MouseButton, MouseMode, Window, WindowOptions};
+use std::path::Path;
+use std::sync::{mpsc::{Receiver, Sender}, Arc};
 
-pub fn show_image(buffer: &mut Vec<u32>, width: usize, height: usize) {
+use crate::camera::Camera;
+use crate::hittable_list::HittableList;
+use crate::material::format_colour;
+
+use crate::aov::Aov;
+use crate::cancel;
+use crate::film::Film;
+use crate::save;
+use crate::tonemap::DisplaySettings;
+
+const TITLE: &str = "Rust Raytracer by grqphical07";
+
+#[allow(dead_code)]
+pub fn show_image(buffer: &[u32], width: usize, height: usize) {
     let mut window = Window::new(
-        "Rust Raytracer by grqphical07",
+        TITLE,
         width,
         height,
         WindowOptions::default(),
@@ -16,8 +31,371 @@ pub fn show_image(buffer: &mut Vec<u32>, width: usize, height: usize) {
 
     while window.is_open() && !window.is_key_down(Key::Escape) {
         // We unwrap here as we want this code to exit if it fails. Real applications may want to handle this in a different way
+        window
+            .update_with_buffer(buffer, width, height)
+            .unwrap();
+    }
+}
+
+/// Shows a film which is still being rendered, redrawing the window whenever a newer version of it arrives.
+/// Returns once the window is closed. If no window can be opened the films are read until the sender is dropped instead,
+/// so a render without a display still runs to completion
+///
+/// ## Arguments
+/// - `width` Width of the film
+/// - `height` Height of the film
+/// - `updates` Newer versions of the film
+/// - `display` Starting exposure and tone mapper
+/// - `view_dir` Directory views saved with P are written to
+pub fn show_film_live(width: usize, height: usize, updates: Receiver<Film>, display: DisplaySettings, view_dir: &Path) {
+    show_film_navigable(width, height, updates, display, view_dir, None);
+}
+
+/// Lets the viewer move the camera, every change is sent back so the render can start again from the new view.
+/// The viewer shares the world with the render so clicking a pixel can show what is there
+pub struct Navigation {
+    pub camera: Camera,
+    pub world: Arc<HittableList>,
+    pub changes: Sender<Camera>,
+}
+
+/// How far, in pixels, the mouse has to move with a button held before it counts as dragging rather than clicking
+const CLICK_DISTANCE: f32 = 3.0;
+
+/// Passes the viewer can show, Tab moves to the next one and None is the beauty image
+const VIEW_PASSES: [Option<Aov>; 5] = [None, Some(Aov::Albedo), Some(Aov::Normal), Some(Aov::Depth), Some(Aov::SampleCount)];
+
+/// Which pass is shown and which part of the film fills the window
+struct View {
+    /// Index into `VIEW_PASSES`
+    pass: usize,
+    /// How many window pixels each film pixel covers, always a power of two
+    zoom: usize,
+    /// Film pixel shown in the top left corner of the window
+    left: usize,
+    top: usize,
+}
+
+impl View {
+    fn new() -> Self {
+        Self { pass: 0, zoom: 1, left: 0, top: 0 }
+    }
+
+    /// Name of the pass being shown
+    fn pass_name(&self) -> &'static str {
+        return match VIEW_PASSES[self.pass] {
+            Some(aov) => aov.name(),
+            None => "beauty",
+        };
+    }
+
+    /// Converts a position in the window to a position on the film
+    fn to_film(&self, x: f32, y: f32) -> (f32, f32) {
+        return (self.left as f32 + x / self.zoom as f32, self.top as f32 + y / self.zoom as f32);
+    }
+
+    /// Zooms in or out while keeping the film pixel under a point of the window in place
+    ///
+    /// ## Arguments
+    /// - `zoom` New magnification, clamped between 1 and 32
+    /// - `anchor` Point in the window which stays over the same part of the film
+    /// - `width` Width of the film
+    /// - `height` Height of the film
+    fn zoom_to(&mut self, zoom: usize, anchor: (f32, f32), width: usize, height: usize) {
+        let (film_x, film_y) = self.to_film(anchor.0, anchor.1);
+        self.zoom = zoom.clamp(1, 32);
+
+        let left = film_x - anchor.0 / self.zoom as f32;
+        let top = film_y - anchor.1 / self.zoom as f32;
+        self.pan_to(left.round() as i64, top.round() as i64, width, height);
+    }
+
+    /// Moves the top left corner of the view, keeping the whole window over the film
+    fn pan_to(&mut self, left: i64, top: i64, width: usize, height: usize) {
+        self.left = left.clamp(0, (width - width.div_ceil(self.zoom)) as i64) as usize;
+        self.top = top.clamp(0, (height - height.div_ceil(self.zoom)) as i64) as usize;
+    }
+
+    /// Turns the film into window pixels, tone mapping light and colouring the other passes,
+    /// then magnifies the visible part with nearest neighbour filtering so single pixels are easy to see
+    fn pixels(&self, film: &Film, display: &DisplaySettings) -> Vec<u32> {
+        let pixels = match VIEW_PASSES[self.pass] {
+            Some(aov) => save::aov_display_pixels(&film.aov_values(aov), aov, display),
+            None => film.to_packed_rgb(display),
+        };
+        if self.zoom == 1 {
+            return pixels;
+        }
+
+        let mut output = Vec::with_capacity(pixels.len());
+        for y in 0..film.height {
+            let row = (self.top + y / self.zoom) * film.width;
+            for x in 0..film.width {
+                output.push(pixels[row + self.left + x / self.zoom]);
+            }
+        }
+
+        return output;
+    }
+}
+
+/// Saves what the window shows as a PNG in `dir`, numbered so earlier views aren't overwritten
+fn save_view(buffer: &[u32], width: usize, height: usize, dir: &Path) {
+    let path = (1..).map(|n| dir.join(format!("view-{}.png", n))).find(|path| !path.exists()).unwrap();
+    let path = path.to_string_lossy();
+
+    match save::save_u32_vector_to_png(&path, width as u32, height as u32, buffer) {
+        Ok(()) => println!("\nSaved view as '{}'", path),
+        Err(err) => eprintln!("\nERROR: could not save '{}': {}", path, err),
+    }
+}
+
+/// Mouse position and buttons from the last frame, used to turn dragging into camera movement
+#[derive(Default)]
+struct MouseState {
+    position: Option<(f32, f32)>,
+    left: bool,
+    right: bool,
+    /// Where the left button went down, until the mouse moves far enough to start dragging
+    pressed_at: Option<(f32, f32)>,
+}
+
+/// Prints what is under a pixel, and focuses on it if a modifier is held. Returns whether the camera changed
+///
+/// ## Arguments
+/// - `navigation` Camera and world to cast the ray into
+/// - `film` Newest film, which holds the pixel's radiance
+/// - `x` Horizontal position of the click on the film in pixels
+/// - `y` Vertical position of the click on the film in pixels
+/// - `focus` Whether to set the focus distance to whatever was hit
+fn inspect_pixel(navigation: &mut Navigation, film: Option<&Film>, x: f32, y: f32, focus: bool) -> bool {
+    let (px, py) = (x.floor().max(0.0) as usize, y.floor().max(0.0) as usize);
+
+    // The camera puts pixel centres on whole numbers
+    let inspection = navigation.camera.inspect(&navigation.world, px as f64, py as f64);
+
+    println!("\nPixel ({}, {})", px, py);
+    if let Some(film) = film.filter(|film| px < film.width && py < film.height) {
+        let pixel = film.pixel(px, py);
+        println!("  Radiance: {} from {} samples", format_colour(pixel.colour()), pixel.samples);
+    }
+
+    let Some(inspection) = inspection else {
+        println!("  Nothing hit");
+        return false;
+    };
+
+    println!("  Object: {}", inspection.object_id);
+    println!("  Material: {}", inspection.material);
+    println!("  Distance: {:.4}", inspection.distance);
+    println!("  Point: {}", format_colour(inspection.point));
+    println!("  Normal: {}", format_colour(inspection.normal));
+
+    if focus {
+        navigation.camera.focus_dist = inspection.focus_dist;
+        println!("  Focus distance set to {:.4}", inspection.focus_dist);
+        return true;
+    }
+
+    return false;
+}
+
+/// Reads the mouse and keyboard and moves the camera, returns whether it moved.
+///
+/// - Left drag orbits around the point the camera looks at, right or middle drag pans and the scroll wheel dollies
+/// - Left click prints what is under the pixel, Shift or Ctrl click also focuses on it
+/// - W/A/S/D move forwards, left, backwards and right, Q/E move down and up
+/// - [ and ] narrow and widen the field of view, , and . pull the focus distance in and push it out
+fn navigate(window: &Window, navigation: &mut Navigation, film: Option<&Film>, view: &View, mouse: &mut MouseState) -> bool {
+    let mut moved = false;
+    let camera = &mut navigation.camera;
+
+    let position = window.get_mouse_pos(MouseMode::Pass);
+    let left = window.get_mouse_down(MouseButton::Left);
+    let right = window.get_mouse_down(MouseButton::Right) || window.get_mouse_down(MouseButton::Middle);
+
+    if left && !mouse.left {
+        mouse.pressed_at = position;
+    }
+    if let (Some((x, y)), Some((start_x, start_y))) = (position, mouse.pressed_at) {
+        if (x - start_x).hypot(y - start_y) > CLICK_DISTANCE {
+            mouse.pressed_at = None;
+        }
+    }
+
+    if let (Some((x, y)), Some((last_x, last_y))) = (position, mouse.position) {
+        let (dx, dy) = ((x - last_x) as f64, (y - last_y) as f64);
+
+        if dx != 0.0 || dy != 0.0 {
+            if left && mouse.left && mouse.pressed_at.is_none() {
+                camera.orbit(-0.3 * dx, 0.3 * dy);
+                moved = true;
+            } else if right && mouse.right {
+                camera.pan(-0.002 * dx, 0.002 * dy);
+                moved = true;
+            }
+        }
+    }
+
+    // Releasing the button without dragging is a click
+    let clicked = if !left && mouse.left { mouse.pressed_at.take() } else { None };
+    *mouse = MouseState { position, left, right, pressed_at: mouse.pressed_at };
+
+    if let Some((_, scroll)) = window.get_scroll_wheel() {
+        if scroll != 0.0 {
+            camera.dolly(0.1 * scroll.signum() as f64);
+            moved = true;
+        }
+    }
+
+    // Walking speed scales with how far away the camera is looking so both small and large scenes are easy to move around
+    let step = 0.02 * (camera.look_at - camera.look_from).length();
+    let keys = [(Key::W, (step, 0.0, 0.0)), (Key::S, (-step, 0.0, 0.0)), (Key::D, (0.0, step, 0.0)), (Key::A, (0.0, -step, 0.0)), (Key::E, (0.0, 0.0, step)), (Key::Q, (0.0, 0.0, -step))];
+    for (key, (forward, right, up)) in keys {
+        if window.is_key_down(key) {
+            camera.fly(forward, right, up);
+            moved = true;
+        }
+    }
+
+    if window.is_key_pressed(Key::LeftBracket, KeyRepeat::Yes) {
+        camera.vfov = (camera.vfov - 2.0).max(1.0);
+        moved = true;
+    }
+    if window.is_key_pressed(Key::RightBracket, KeyRepeat::Yes) {
+        camera.vfov = (camera.vfov + 2.0).min(170.0);
+        moved = true;
+    }
+    if window.is_key_pressed(Key::Comma, KeyRepeat::Yes) {
+        camera.focus_dist = (camera.focus_dist / 1.1).max(0.01);
+        moved = true;
+    }
+    if window.is_key_pressed(Key::Period, KeyRepeat::Yes) {
+        camera.focus_dist *= 1.1;
+        moved = true;
+    }
+
+    if let Some((x, y)) = clicked.map(|(x, y)| view.to_film(x, y)) {
+        let focus = [Key::LeftShift, Key::RightShift, Key::LeftCtrl, Key::RightCtrl].iter().any(|key| window.is_key_down(*key));
+        moved |= inspect_pixel(navigation, film, x, y, focus);
+    }
+
+    return moved;
+}
+
+/// Shows a film which is still being rendered, like `show_film_live`, and optionally lets the camera be moved around.
+///
+/// - T cycles the tone mapper and +/- change the exposure by half a stop, neither needs a new render
+/// - Tab and Shift+Tab cycle through the beauty image, albedo, normals, depth and the sample count heatmap
+/// - Z and X zoom in and out, arrow keys pan the zoomed view and without navigation the scroll wheel zooms too
+/// - P saves what the window shows as view-N.png in `view_dir`
+///
+/// ## Arguments
+/// - `width` Width of the film
+/// - `height` Height of the film
+/// - `updates` Newer versions of the film
+/// - `display` Starting exposure and tone mapper
+/// - `view_dir` Directory views saved with P are written to, normally the one the render is saved in
+/// - `navigation` Camera to move and where to send its changes, see `navigate` for the controls
+pub fn show_film_navigable(width: usize, height: usize, updates: Receiver<Film>, display: DisplaySettings, view_dir: &Path, navigation: Option<Navigation>) {
+    let mut display = display;
+    let mut navigation = navigation;
+    let mut mouse = MouseState::default();
+    let mut view = View::new();
+    let mut film: Option<Film> = None;
+    let mut buffer = vec![0; width * height];
+
+    let mut window = match Window::new(TITLE, width, height, WindowOptions::default()) {
+        Ok(window) => window,
+        Err(err) => {
+            eprintln!("WARNING: could not open a window: {}", err);
+            // Nothing can move the camera any more, so let the render finish
+            drop(navigation);
+            for _ in updates {}
+            return;
+        }
+    };
+
+    // Limit to max ~60 fps update rate
+    window.limit_update_rate(Some(std::time::Duration::from_micros(16600)));
+
+    // Ctrl+C in the terminal closes the window too, so the render can be saved
+    while window.is_open() && !window.is_key_down(Key::Escape) && !cancel::interrupted() {
+        let mut changed = false;
+
+        if let Some(newer) = updates.try_iter().last() {
+            film = Some(newer);
+            changed = true;
+        }
+
+        if let Some(navigation) = navigation.as_mut() {
+            if navigate(&window, navigation, film.as_ref(), &view, &mut mouse) {
+                // The render thread is gone once it has returned its film, there is nothing left to update
+                let _ = navigation.changes.send(navigation.camera);
+            }
+        }
+
+        if window.is_key_pressed(Key::T, KeyRepeat::No) {
+            display.tone_mapper = display.tone_mapper.next();
+            changed = true;
+        }
+        if window.is_key_pressed(Key::Equal, KeyRepeat::Yes) || window.is_key_pressed(Key::NumPadPlus, KeyRepeat::Yes) {
+            display.exposure += 0.5;
+            changed = true;
+        }
+        if window.is_key_pressed(Key::Minus, KeyRepeat::Yes) || window.is_key_pressed(Key::NumPadMinus, KeyRepeat::Yes) {
+            display.exposure -= 0.5;
+            changed = true;
+        }
+
+        if window.is_key_pressed(Key::Tab, KeyRepeat::No) {
+            let backwards = window.is_key_down(Key::LeftShift) || window.is_key_down(Key::RightShift);
+            view.pass = (view.pass + if backwards { VIEW_PASSES.len() - 1 } else { 1 }) % VIEW_PASSES.len();
+            changed = true;
+        }
+
+        // Zoom around the mouse, or the middle of the window when the mouse is outside it
+        let anchor = window.get_mouse_pos(MouseMode::Discard).unwrap_or((width as f32 / 2.0, height as f32 / 2.0));
+        let mut zoom = view.zoom;
+        if window.is_key_pressed(Key::Z, KeyRepeat::No) {
+            zoom *= 2;
+        }
+        if window.is_key_pressed(Key::X, KeyRepeat::No) {
+            zoom /= 2;
+        }
+        // The scroll wheel dollies the camera when navigating, otherwise it zooms
+        if navigation.is_none() {
+            if let Some((_, scroll)) = window.get_scroll_wheel() {
+                if scroll > 0.0 { zoom *= 2 } else if scroll < 0.0 { zoom /= 2 }
+            }
+        }
+        if zoom != view.zoom {
+            view.zoom_to(zoom, anchor, width, height);
+            changed = true;
+        }
+
+        let step = (width.max(height) / view.zoom / 8).max(1) as i64;
+        let pans = [(Key::Left, (-step, 0)), (Key::Right, (step, 0)), (Key::Up, (0, -step)), (Key::Down, (0, step))];
+        for (key, (dx, dy)) in pans {
+            if window.is_key_pressed(key, KeyRepeat::Yes) {
+                view.pan_to(view.left as i64 + dx, view.top as i64 + dy, width, height);
+                changed = true;
+            }
+        }
+
+        if changed {
+            if let Some(film) = &film {
+                buffer = view.pixels(film, &display);
+            }
+            window.set_title(&format!("{} - {} - {} {:+.1} EV - {}x", TITLE, view.pass_name(), display.tone_mapper.name(), display.exposure, view.zoom));
+        }
+
+        if window.is_key_pressed(Key::P, KeyRepeat::No) {
+            save_view(&buffer, width, height, view_dir);
+        }
+
         window
             .update_with_buffer(&buffer, width, height)
             .unwrap();
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,401 @@
+use std::{
+    fs,
+    io::{self, Error, ErrorKind},
+};
+
+use crate::{aabb::Aabb, interval::Interval, vector3::Vector3};
+
+/// Side length in voxels of each cell of the majorant grid
+const MAJORANT_BLOCK_SIZE: usize = 8;
+
+/// Most values a grid may hold, so a damaged header can't make us allocate an absurd grid
+const MAX_VALUES: usize = 1 << 28;
+
+/// A 3D grid of voxels storing density and optionally emission and temperature.
+///
+/// Voxels are stored X fastest, then Y, then Z, with every channel of a voxel next to each other.
+/// Channel 0 is always density. `.vol` files with 2 channels store temperature in channel 1,
+/// 4 channels store RGB emission in channels 1 to 3 and 5 channels store emission then temperature
+#[derive(Clone, Debug)]
+pub struct VoxelGrid {
+    pub resolution: [usize; 3],
+    pub channels: usize,
+    pub data: Vec<f32>,
+    pub bounds: Aabb,
+    pub emission_channel: Option<usize>,
+    pub temperature_channel: Option<usize>,
+}
+
+/// Reads a little endian i32 from the start of a byte slice
+fn read_i32(bytes: &[u8], offset: usize) -> io::Result<i32> {
+    match bytes.get(offset..offset + 4) {
+        Some(b) => return Ok(i32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+        None => return Err(Error::new(ErrorKind::UnexpectedEof, "voxel file ended early")),
+    }
+}
+
+/// Reads a little endian f32 from the start of a byte slice
+fn read_f32(bytes: &[u8], offset: usize) -> io::Result<f32> {
+    return Ok(f32::from_bits(read_i32(bytes, offset)? as u32));
+}
+
+/// Returns how many values a grid of this size holds, checking every dimension is at least 1 and the total isn't absurd
+fn value_count(resolution: [usize; 3], channels: usize) -> io::Result<usize> {
+    let count = resolution.iter().chain([&channels]).try_fold(1usize, |count, &n| match n {
+        0 => None,
+        n => count.checked_mul(n),
+    });
+
+    match count {
+        Some(count) if count <= MAX_VALUES => return Ok(count),
+        _ => return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("invalid grid of {}x{}x{} voxels with {} channels", resolution[0], resolution[1], resolution[2], channels),
+        )),
+    }
+}
+
+/// Reads a dimension from a header, which must not be negative
+fn read_dimension(bytes: &[u8], offset: usize) -> io::Result<usize> {
+    let value = read_i32(bytes, offset)?;
+    return usize::try_from(value).map_err(|_| Error::new(ErrorKind::InvalidData, format!("invalid voxel file dimension {}", value)));
+}
+
+impl VoxelGrid {
+    /// Creates a grid filling the unit cube from density values
+    ///
+    /// ## Arguments
+    /// - `resolution` Number of voxels along X, Y and Z, each at least 1
+    /// - `density` Density of each voxel, X fastest then Y then Z. There must be one for every voxel
+    pub fn from_density(resolution: [usize; 3], density: Vec<f32>) -> io::Result<Self> {
+        let count = value_count(resolution, 1)?;
+        if density.len() != count {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("expected {} voxels but got {}", count, density.len())));
+        }
+
+        return Ok(Self {
+            resolution,
+            channels: 1,
+            data: density,
+            bounds: Aabb::from_points(Vector3::new(), Vector3::from(1.0, 1.0, 1.0)),
+            emission_channel: None,
+            temperature_channel: None,
+        });
+    }
+
+    /// Loads a raw file of little endian f32 densities. The grid fills the unit cube
+    ///
+    /// ## Arguments
+    /// - `path` Path to the file
+    /// - `resolution` Number of voxels along X, Y and Z
+    pub fn load_raw(path: &str, resolution: [usize; 3]) -> io::Result<Self> {
+        let count = value_count(resolution, 1)?;
+        let bytes = fs::read(path)?;
+
+        if bytes.len() < count * 4 {
+            return Err(Error::new(ErrorKind::InvalidData, format!("expected {} voxels in '{}' but found {}", count, path, bytes.len() / 4)));
+        }
+
+        let density = (0..count).map(|i| read_f32(&bytes, i * 4)).collect::<io::Result<Vec<f32>>>()?;
+        return VoxelGrid::from_density(resolution, density);
+    }
+
+    /// Loads a Mitsuba style `.vol` file. Only the float32 encoding is supported
+    ///
+    /// ## Arguments
+    /// - `path` Path to the file
+    pub fn load_vol(path: &str) -> io::Result<Self> {
+        let bytes = fs::read(path)?;
+
+        if bytes.len() < 48 || &bytes[0..3] != b"VOL" || bytes[3] != 3 {
+            return Err(Error::new(ErrorKind::InvalidData, format!("'{}' is not a version 3 .vol file", path)));
+        }
+
+        if read_i32(&bytes, 4)? != 1 {
+            return Err(Error::new(ErrorKind::InvalidData, "only float32 .vol files are supported"));
+        }
+
+        let resolution = [read_dimension(&bytes, 8)?, read_dimension(&bytes, 12)?, read_dimension(&bytes, 16)?];
+        let channels = read_dimension(&bytes, 20)?;
+        let count = value_count(resolution, channels)?;
+
+        let mut bbox = [0.0; 6];
+        for (i, value) in bbox.iter_mut().enumerate() {
+            *value = read_f32(&bytes, 24 + i * 4)? as f64;
+        }
+        // Interpolation divides by the size of the bounds along each axis
+        if !bbox.iter().all(|v| v.is_finite()) || !(0..3).all(|a| bbox[a] < bbox[a + 3]) {
+            return Err(Error::new(ErrorKind::InvalidData, format!("'{}' has an invalid bounding box", path)));
+        }
+
+        if bytes.len() < 48 + count * 4 {
+            return Err(Error::new(ErrorKind::InvalidData, format!("expected {} values in '{}' but found {}", count, path, (bytes.len() - 48) / 4)));
+        }
+        let data = (0..count).map(|i| read_f32(&bytes, 48 + i * 4)).collect::<io::Result<Vec<f32>>>()?;
+
+        let (emission_channel, temperature_channel) = match channels {
+            2 => (None, Some(1)),
+            4 => (Some(1), None),
+            5 => (Some(1), Some(4)),
+            _ => (None, None),
+        };
+
+        return Ok(Self {
+            resolution,
+            channels,
+            data,
+            bounds: Aabb::from_points(Vector3::from(bbox[0], bbox[1], bbox[2]), Vector3::from(bbox[3], bbox[4], bbox[5])),
+            emission_channel,
+            temperature_channel,
+        });
+    }
+
+    /// Returns the value of a channel of a voxel, clamping the coordinates to the edge of the grid
+    pub fn voxel(&self, x: i64, y: i64, z: i64, channel: usize) -> f32 {
+        let clamp = |v: i64, n: usize| v.clamp(0, n as i64 - 1) as usize;
+        let (x, y, z) = (clamp(x, self.resolution[0]), clamp(y, self.resolution[1]), clamp(z, self.resolution[2]));
+
+        return self.data[((z * self.resolution[1] + y) * self.resolution[0] + x) * self.channels + channel];
+    }
+
+    /// Trilinearly interpolates a channel at a point in the space of the grid's bounds
+    pub fn interpolate(&self, p: Vector3, channel: usize) -> f64 {
+        let mut base = [0i64; 3];
+        let mut frac = [0.0; 3];
+
+        for a in 0..3 {
+            let axis = self.bounds.axis(a);
+            // Voxel centers sit half a voxel in from each edge
+            let coord = (p[a] - axis.min) / axis.size() * self.resolution[a] as f64 - 0.5;
+            base[a] = coord.floor() as i64;
+            frac[a] = coord - coord.floor();
+        }
+
+        let mut result = 0.0;
+        for corner in 0..8 {
+            let offset = [corner & 1, (corner >> 1) & 1, (corner >> 2) & 1];
+            let mut weight = 1.0;
+            for a in 0..3 {
+                weight *= if offset[a] == 1 { frac[a] } else { 1.0 - frac[a] };
+            }
+
+            if weight > 0.0 {
+                let value = self.voxel(base[0] + offset[0] as i64, base[1] + offset[1] as i64, base[2] + offset[2] as i64, channel);
+                result += weight * value as f64;
+            }
+        }
+
+        return result;
+    }
+
+    /// Returns the interpolated density at a point
+    pub fn density(&self, p: Vector3) -> f64 {
+        return self.interpolate(p, 0).max(0.0);
+    }
+
+    /// Returns the interpolated RGB emission at a point, or black if the grid has no emission
+    pub fn emission(&self, p: Vector3) -> Vector3 {
+        match self.emission_channel {
+            Some(c) => return Vector3::from(self.interpolate(p, c), self.interpolate(p, c + 1), self.interpolate(p, c + 2)),
+            None => return Vector3::new(),
+        }
+    }
+
+    /// Returns the interpolated temperature at a point in Kelvin, or zero if the grid has no temperature
+    pub fn temperature(&self, p: Vector3) -> f64 {
+        match self.temperature_channel {
+            Some(c) => return self.interpolate(p, c),
+            None => return 0.0,
+        }
+    }
+
+    /// Returns a 64-bit FNV-1a hash of the grid's size, bounds and every value in it.
+    /// It is the same from one build to the next, so films can record which grid they were rendered with
+    pub fn fingerprint(&self) -> u64 {
+        let mut hash: u64 = 0xcbf29ce484222325;
+        let mut add = |bytes: &[u8]| {
+            for &byte in bytes {
+                hash = (hash ^ byte as u64).wrapping_mul(0x100000001b3);
+            }
+        };
+
+        for size in self.resolution.iter().chain([&self.channels]) {
+            add(&(*size as u64).to_le_bytes());
+        }
+        for interval in [self.bounds.x, self.bounds.y, self.bounds.z] {
+            add(&interval.min.to_le_bytes());
+            add(&interval.max.to_le_bytes());
+        }
+        for value in &self.data {
+            add(&value.to_le_bytes());
+        }
+
+        return hash;
+    }
+}
+
+/// A coarse grid storing the maximum density in each block of voxels, which bounds the density used by delta tracking
+#[derive(Clone, Debug)]
+pub struct MajorantGrid {
+    pub resolution: [usize; 3],
+    pub values: Vec<f64>,
+    pub bounds: Aabb,
+}
+
+impl MajorantGrid {
+    /// Builds the majorant grid of a voxel grid
+    pub fn build(grid: &VoxelGrid) -> Self {
+        let resolution = grid.resolution.map(|n| n.div_ceil(MAJORANT_BLOCK_SIZE).max(1));
+        let mut values = vec![0.0; resolution[0] * resolution[1] * resolution[2]];
+
+        for bz in 0..resolution[2] {
+            for by in 0..resolution[1] {
+                for bx in 0..resolution[0] {
+                    // Include one extra voxel on every side since interpolation reaches into neighbouring blocks
+                    let range = |b: usize| (b * MAJORANT_BLOCK_SIZE) as i64 - 1..=((b + 1) * MAJORANT_BLOCK_SIZE) as i64;
+                    let mut max: f32 = 0.0;
+
+                    for z in range(bz) {
+                        for y in range(by) {
+                            for x in range(bx) {
+                                max = max.max(grid.voxel(x, y, z, 0));
+                            }
+                        }
+                    }
+
+                    values[(bz * resolution[1] + by) * resolution[0] + bx] = max as f64;
+                }
+            }
+        }
+
+        Self {
+            resolution,
+            values,
+            bounds: grid.bounds,
+        }
+    }
+
+    /// Returns the majorant of a cell
+    pub fn get(&self, cell: [i64; 3]) -> f64 {
+        return self.values[(cell[2] as usize * self.resolution[1] + cell[1] as usize) * self.resolution[0] + cell[0] as usize];
+    }
+
+    /// Returns the size of a cell along an axis
+    pub fn cell_size(&self, axis: usize) -> f64 {
+        return self.bounds.axis(axis).size() / self.resolution[axis] as f64;
+    }
+
+    /// Returns the range of t values where the ray is inside the grid, clipped to `ray_t`
+    pub fn clip(&self, origin: Vector3, direction: Vector3, ray_t: Interval) -> Option<Interval> {
+        let mut result = ray_t;
+
+        for a in 0..3 {
+            let inv_d = 1.0 / direction[a];
+            let axis = self.bounds.axis(a);
+            let mut t0 = (axis.min - origin[a]) * inv_d;
+            let mut t1 = (axis.max - origin[a]) * inv_d;
+
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+
+            result.min = result.min.max(t0);
+            result.max = result.max.min(t1);
+        }
+
+        if result.max <= result.min {
+            return None;
+        }
+
+        return Some(result);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes a float32 `.vol` file to the temporary directory and returns its path
+    fn write_vol(name: &str, resolution: [i32; 3], channels: i32, bbox: [f32; 6], values: &[f32]) -> String {
+        let mut bytes = b"VOL".to_vec();
+        bytes.push(3);
+        for value in [1, resolution[0], resolution[1], resolution[2], channels] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        for value in bbox.iter().chain(values) {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+
+        let path = std::env::temp_dir().join(format!("raytracer-{}-{}.vol", std::process::id(), name));
+        fs::write(&path, bytes).unwrap();
+        return path.to_string_lossy().into_owned();
+    }
+
+    const UNIT_BOX: [f32; 6] = [0.0, 0.0, 0.0, 1.0, 1.0, 1.0];
+
+    #[test]
+    fn density_must_match_the_resolution() {
+        assert!(VoxelGrid::from_density([2, 2, 2], vec![1.0; 8]).is_ok());
+        assert!(VoxelGrid::from_density([2, 2, 2], vec![1.0; 7]).is_err());
+    }
+
+    #[test]
+    fn zero_resolution_is_rejected() {
+        assert!(VoxelGrid::from_density([0, 2, 2], vec![]).is_err());
+    }
+
+    #[test]
+    fn huge_resolution_is_rejected() {
+        assert!(VoxelGrid::from_density([usize::MAX, 2, 1], vec![]).is_err());
+        assert!(VoxelGrid::from_density([1 << 10, 1 << 10, 1 << 10], vec![]).is_err());
+    }
+
+    #[test]
+    fn vol_file_loads() {
+        let path = write_vol("valid", [2, 1, 1], 1, UNIT_BOX, &[0.25, 0.75]);
+        let grid = VoxelGrid::load_vol(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(grid.resolution, [2, 1, 1]);
+        assert_eq!(grid.voxel(1, 0, 0, 0), 0.75);
+    }
+
+    #[test]
+    fn vol_file_with_negative_dimensions_is_rejected() {
+        let path = write_vol("negative", [-2, 1, 1], 1, UNIT_BOX, &[0.0; 2]);
+        let result = VoxelGrid::load_vol(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn truncated_vol_file_is_rejected() {
+        let path = write_vol("truncated", [4, 4, 4], 1, UNIT_BOX, &[0.0; 10]);
+        let result = VoxelGrid::load_vol(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn vol_file_with_empty_bounds_is_rejected() {
+        let path = write_vol("flat", [1, 1, 1], 1, [0.0, 0.0, 0.0, 1.0, 0.0, 1.0], &[1.0]);
+        let result = VoxelGrid::load_vol(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn fingerprint_changes_with_the_densities_and_the_size() {
+        let grid = VoxelGrid::from_density([2, 1, 1], vec![0.25, 0.75]).unwrap();
+        let same = VoxelGrid::from_density([2, 1, 1], vec![0.25, 0.75]).unwrap();
+        let denser = VoxelGrid::from_density([2, 1, 1], vec![0.25, 0.8]).unwrap();
+        let turned = VoxelGrid::from_density([1, 2, 1], vec![0.25, 0.75]).unwrap();
+
+        assert_eq!(grid.fingerprint(), same.fingerprint());
+        assert_ne!(grid.fingerprint(), denser.fingerprint());
+        assert_ne!(grid.fingerprint(), turned.fingerprint());
+    }
+}